@@ -0,0 +1,54 @@
+// Measures instruction dispatch with and without the decoded instruction cache,
+// and with the JIT, on a loop of arithmetic and branching instructions.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use yarchi::interpreter::chip8::{Chip8, Jit};
+
+const INSTRUCTIONS: u64 = 10_000;
+
+#[rustfmt::skip]
+const PROGRAM: [u8; 22] = [
+    0x60, 0x00, // 200: LD V0, 00
+    0x61, 0x05, // 202: LD V1, 05
+    0x70, 0x01, // 204: ADD V0, 01
+    0x82, 0x04, // 206: ADD V2, V0
+    0x83, 0x26, // 208: SHR V3, V2
+    0x84, 0x15, // 20A: SUB V4, V1
+    0xA3, 0x00, // 20C: LD I, 300
+    0xF2, 0x1E, // 20E: ADD I, V2
+    0x30, 0xFF, // 210: SE V0, FF
+    0x12, 0x04, // 212: JP 204
+    0x12, 0x00, // 214: JP 200
+];
+
+fn dispatch(c: &mut Criterion) {
+    let mut group = c.benchmark_group("dispatch");
+    group.throughput(Throughput::Elements(INSTRUCTIONS));
+
+    for &cached in &[true, false] {
+        let name = if cached { "cached" } else { "uncached" };
+        let mut machine = Chip8::new(false)
+            .load_program(&PROGRAM)
+            .with_decode_cache(cached);
+
+        group.bench_function(BenchmarkId::from_parameter(name), |b| {
+            b.iter(|| {
+                for _ in 0..INSTRUCTIONS {
+                    machine.run_instruction(false);
+                }
+            })
+        });
+    }
+
+    let mut machine = Chip8::new(false).load_program(&PROGRAM);
+    let mut jit = Jit::default();
+    group.bench_function(BenchmarkId::from_parameter("jit"), |b| {
+        b.iter(|| jit.run(&mut machine, INSTRUCTIONS as usize))
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, dispatch);
+criterion_main!(benches);
@@ -0,0 +1,48 @@
+// Measures drawing sprites of various sizes, at positions aligned
+// and not aligned to a byte of the display.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use yarchi::interpreter::chip8::Chip8;
+
+// Draws per iteration; the program draws them and jumps back to the start.
+const DRAWS: usize = 16;
+
+fn draw_program(rows: u8) -> Vec<u8> {
+    let mut program = vec![0xA0, 0x50]; // LD I, 050 (font)
+    for _ in 0..DRAWS {
+        program.extend_from_slice(&[0xD0, 0x10 | rows]); // DRW V0, V1, rows
+    }
+    program.extend_from_slice(&[0x12, 0x00]); // JP 200
+    program
+}
+
+fn draw(c: &mut Criterion) {
+    let mut group = c.benchmark_group("draw");
+    group.throughput(Throughput::Elements(DRAWS as u64));
+
+    for &rows in &[1, 5, 15] {
+        for &x in &[8, 13] {
+            let mut machine = Chip8::new(false).load_program(&draw_program(rows));
+            let mut regs = machine.registers();
+            regs.v[0] = x;
+            regs.v[1] = 4;
+            machine.set_registers(&regs);
+
+            let id = BenchmarkId::new(format!("{} rows", rows), format!("x={}", x));
+            group.bench_function(id, |b| {
+                b.iter(|| {
+                    // LD I, the draws and JP
+                    for _ in 0..DRAWS + 2 {
+                        machine.run_instruction(false);
+                    }
+                })
+            });
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, draw);
+criterion_main!(benches);
@@ -0,0 +1,64 @@
+// Measures running a second (60 frames) of the test ROMs in tests/conformance/roms,
+// including Timendus' test suite if it was fetched, with each timing and with the JIT.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use yarchi::interpreter::chip8::Chip8;
+use yarchi::interpreter::headless::{Headless, Timing};
+
+const FRAMES: u32 = 60;
+const INSTRUCTIONS_PER_FRAME: u32 = 15;
+
+fn roms() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/conformance/roms");
+    let mut roms: Vec<PathBuf> = [dir.clone(), dir.join("timendus")]
+        .iter()
+        .filter_map(|d| fs::read_dir(d).ok())
+        .flatten()
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|e| e == "ch8"))
+        .collect();
+    roms.sort();
+    roms
+}
+
+fn frames(c: &mut Criterion) {
+    let mut group = c.benchmark_group("frames");
+    let variants = [
+        (
+            "chip8",
+            Timing::InstructionsPerFrame(INSTRUCTIONS_PER_FRAME),
+            false,
+        ),
+        (
+            "jit",
+            Timing::InstructionsPerFrame(INSTRUCTIONS_PER_FRAME),
+            true,
+        ),
+        ("vip", Timing::Vip, false),
+    ];
+
+    for path in roms() {
+        let rom = fs::read(&path).unwrap();
+        let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+
+        for &(variant, timing, jit) in &variants {
+            let mut headless = Headless::new(Chip8::new(false).load_program(&rom), timing);
+            if jit {
+                headless = headless.with_jit();
+            }
+
+            group.bench_function(BenchmarkId::new(variant, &name), |b| {
+                b.iter(|| headless.run_frames(FRAMES))
+            });
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, frames);
+criterion_main!(benches);
@@ -0,0 +1,34 @@
+// Measures converting the display to the window rectangles drawn
+// every frame, for an empty, a half lit and a fully lit display.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use yarchi::interpreter::render::display_rects;
+
+const SCALE: u32 = 10;
+
+fn render(c: &mut Criterion) {
+    let mut group = c.benchmark_group("render");
+    let displays = [
+        ("empty", [false; 64 * 32]),
+        ("checkerboard", {
+            let mut d = [false; 64 * 32];
+            for (xy, p) in d.iter_mut().enumerate() {
+                *p = (xy % 64 + xy / 64) % 2 == 0;
+            }
+            d
+        }),
+        ("full", [true; 64 * 32]),
+    ];
+
+    for (name, display) in &displays {
+        group.bench_function(BenchmarkId::from_parameter(name), |b| {
+            b.iter(|| display_rects(display, SCALE))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, render);
+criterion_main!(benches);
@@ -0,0 +1,14 @@
+// Executes arbitrary programs on the interpreter and the reference
+// implementation, failing on the first difference in their states.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use yarchi_fuzz::reference::run_differential;
+use yarchi_fuzz::{Input, STEPS};
+
+fuzz_target!(|input: Input| {
+    let mut machine = input.machine();
+    if let Err(e) = run_differential(&mut machine, input.c48_mode, input.fx1e_overflow, STEPS) {
+        panic!("{}", e);
+    }
+});
@@ -0,0 +1,12 @@
+// Executes arbitrary programs from arbitrary states; any panic is a bug.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use yarchi_fuzz::{Input, STEPS};
+
+fuzz_target!(|input: Input| {
+    let mut machine = input.machine();
+    for _ in 0..STEPS {
+        machine.run_instruction(false);
+    }
+});
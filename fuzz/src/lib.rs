@@ -0,0 +1,43 @@
+// Inputs shared by the fuzz targets: a program with the initial machine state.
+
+use arbitrary::Arbitrary;
+
+use yarchi::interpreter::chip8::Chip8;
+
+#[path = "../../tests/reference/mod.rs"]
+pub mod reference;
+
+// Instructions executed per input; enough to get through loops and subroutines.
+pub const STEPS: usize = 1000;
+
+#[derive(Arbitrary, Debug)]
+pub struct Input {
+    pub c48_mode: bool,
+    pub fx1e_overflow: bool,
+    pub v: [u8; 16],
+    pub i: u16,
+    pub pc: u16,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub keys: Vec<u8>,
+    pub program: Vec<u8>,
+}
+
+impl Input {
+    pub fn machine(&self) -> Chip8 {
+        let mut machine = Chip8::new(self.c48_mode)
+            .with_fx1e_overflow(self.fx1e_overflow)
+            .load_program(&self.program);
+
+        let mut regs = machine.registers();
+        regs.v = self.v;
+        regs.i = self.i;
+        regs.pc = self.pc;
+        regs.delay_timer = self.delay_timer;
+        regs.sound_timer = self.sound_timer;
+        machine.set_registers(&regs);
+
+        machine.input = self.keys.clone();
+        machine
+    }
+}
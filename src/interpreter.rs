@@ -1,27 +1,61 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
 use std::thread::sleep;
 use std::time::{Duration, Instant};
 
-use sdl2::{audio::AudioQueue, audio::AudioSpecDesired, rect::Rect};
-use sdl2::{event::Event, keyboard::Scancode, EventPump};
+use sdl2::audio::AudioDevice;
+use sdl2::event::{Event, WindowEvent};
+use sdl2::{keyboard::Scancode, EventPump};
 use sdl2::{render::Canvas, video::Window};
 
-mod chip8;
+pub mod analysis;
+pub mod audio;
+mod breakpoints;
+mod cheats;
+pub mod chip8;
 pub mod config;
+mod console;
+mod debugger;
+pub mod font;
+mod gdb;
+pub mod headless;
+pub mod loader;
+mod memcheck;
+mod osd;
+mod profiler;
+pub mod render;
+mod scheduler;
+mod search;
+mod symbols;
+pub mod trace;
 
 use crate::InterpErr;
-use chip8::Chip8;
-use config::Config;
-
-// Keeping the main loop at the timer frequency;
-// the instruction execution is then "synced" to
-// this frequency, e.g. for 500 Hz interpreter frequency,
-// this amounts to 500 / 60 ~= 8 cycles, or (assuming
-// each opcode execution takes 2 cycles) 4 instructions.
-const MAIN_LOOP_FREQUENCY: u32 = 60;
-
-// How many microseconds to sleep, in order to sync at 60 Hz;
-// the duration of actual code execution should be subtracted.
-const SLEEP_TIME: u128 = ((100 / MAIN_LOOP_FREQUENCY) * 10000) as u128;
+use audio::ToneGenerator;
+use breakpoints::Breakpoints;
+use cheats::CheatFile;
+use chip8::{Chip8, History, VipFrames};
+use config::{Config, MAX_INSTRUCTIONS_PER_FRAME};
+use console::{parse_command, Command, Console, CONSOLE_HELP};
+use debugger::DebuggerWindow;
+use font::Font;
+use gdb::{GdbEvent, GdbStub};
+use memcheck::MemCheck;
+use osd::Osd;
+use profiler::Profiler;
+use scheduler::{Scheduler, TIMER_FREQUENCY};
+use search::{Location, Pins, RamSearch};
+use symbols::Symbols;
+use trace::Tracer;
+
+// Instructions which can be stepped back through while debugging.
+const HISTORY_CAPACITY: usize = 100_000;
+
+// Search candidates listed at most.
+const MAX_LISTED_CANDIDATES: usize = 32;
+
+// In slow motion, the emulation runs at this fraction of the normal speed.
+const SLOW_MOTION_DIVISOR: u32 = 4;
 
 // Display size, i.e. how many 'points'.
 pub const DISPLAY_WIDTH: usize = 64;
@@ -30,15 +64,38 @@ pub const DISPLAY_HEIGHT: usize = 32;
 pub struct Interpreter {
     machine: Chip8,
     video: Canvas<Window>,
-    audio: AudioQueue<i16>,
+    audio: AudioDevice<ToneGenerator>,
     events: EventPump,
+    scheduler: Scheduler,
+    // machine cycles left over from (or owed to) previous frames in VIP timing mode
+    cycle_credit: i64,
+    osd: Osd,
+    debugger: Option<DebuggerWindow>,
+    gdb: Option<GdbStub>,
+    tracer: Option<Tracer<BufWriter<File>>>,
+    profiler: Option<Profiler>,
+    memcheck: Option<MemCheck>,
+    // executed instructions, for stepping backwards while debugging
+    history: Option<History>,
+    symbols: Symbols,
+    console: Option<Console>,
+    search: RamSearch,
+    pins: Pins,
+    cheats: CheatFile,
     config: Config,
+    speed: Speed,
     debug: Debug,
 }
 
+struct Speed {
+    turbo: bool,
+    slow_motion: bool,
+}
+
 struct Debug {
     running: bool,
     step_exec: bool,
+    breakpoints: Breakpoints,
 }
 
 impl Interpreter {
@@ -47,64 +104,342 @@ impl Interpreter {
         rom: Option<&str>,
         config: Config,
     ) -> Result<Interpreter, InterpErr> {
+        let rom = rom.unwrap();
+        let symbols = match config
+            .symbols
+            .clone()
+            .or_else(|| Symbols::find_for_rom(rom))
+        {
+            Some(path) => Symbols::load(&path)?,
+            None => Symbols::default(),
+        };
+        let mut breakpoints = Breakpoints::default();
+        for location in &config.breakpoints {
+            match symbols.resolve(location) {
+                Some(addr) => breakpoints.insert(addr),
+                None => return Err(format!("unknown breakpoint location '{}'", location).into()),
+            };
+        }
+
+        let cheat_dir = match &config.cheat_dir {
+            Some(dir) => Path::new(dir).to_path_buf(),
+            None => Path::new(rom).with_file_name("cheats"),
+        };
+        let rom_bytes = loader::read_rom(rom, config.zip_entry.as_deref())?;
+        let mut cheats = CheatFile::load(&cheat_dir, &rom_bytes, &symbols)?;
+        let font = match &config.font {
+            Some(font) => Font::load(font)?,
+            None => Font::default(),
+        };
+        let mut blobs = Vec::new();
+        for (addr, path) in &config.blobs {
+            blobs.push((*addr, loader::read_rom(path, None)?));
+        }
+
+        let mut machine = Chip8::new(config.c48_mode)
+            .with_fx1e_overflow(config.fx1e_overflow)
+            .with_font(&font, config.font_addr)
+            .with_load_address(config.load_addr)
+            .load_rom(&rom_bytes)?;
+        for (addr, blob) in &blobs {
+            machine = machine.load_blob(*addr, blob)?;
+        }
+        if let Some(entry) = config.entry {
+            machine = machine.with_entry(entry);
+        }
+        cheats.patch(&mut machine);
+
         let emu = Interpreter {
-            machine: Chip8::new(config.c48_mode).load_program_to_memory(rom.unwrap())?,
+            machine,
             video: Interpreter::initiate_video(sdl_ctx, &config)?,
-            audio: Interpreter::initiate_audio(sdl_ctx)?,
+            audio: audio::initiate_audio(sdl_ctx, &config)?,
             events: sdl_ctx.event_pump()?,
+            scheduler: Scheduler::new(config.instructions_per_second()),
+            cycle_credit: 0,
+            osd: Osd::new(config.show_fps, config.show_registers),
+            debugger: if config.debugger_window {
+                Some(DebuggerWindow::new(&sdl_ctx.video()?)?)
+            } else {
+                None
+            },
+            gdb: match config.gdb_port {
+                Some(port) => Some(GdbStub::listen(port)?),
+                None => None,
+            },
+            tracer: match &config.trace {
+                Some(t) => Some(Tracer::create(t)?),
+                None => None,
+            },
+            profiler: config
+                .profile
+                .as_ref()
+                .map(|_| Profiler::new(&rom_bytes, config.load_addr)),
+            memcheck: if config.check_memory {
+                let memcheck = MemCheck::new(config.font_addr, config.load_addr)
+                    .loaded(config.load_addr, rom_bytes.len());
+                Some(
+                    blobs
+                        .iter()
+                        .fold(memcheck, |m, (addr, blob)| m.loaded(*addr, blob.len())),
+                )
+            } else {
+                None
+            },
+            history: if config.debug_mode || config.gdb_port.is_some() {
+                Some(History::new(HISTORY_CAPACITY))
+            } else {
+                None
+            },
+            symbols,
+            console: if config.debug_mode || config.console || !cheats.cheats().is_empty() {
+                Some(Console::spawn())
+            } else {
+                None
+            },
+            search: RamSearch::default(),
+            pins: Pins::default(),
+            cheats,
             config,
+            speed: Speed {
+                turbo: false,
+                slow_motion: false,
+            },
             debug: Debug {
                 running: true,
                 step_exec: false,
+                breakpoints,
             },
         };
 
-        return Ok(emu);
+        Ok(emu)
     }
 
-
     pub fn run(&mut self) -> Result<(), InterpErr> {
-        let mut previous_time: Instant;
+        let mut previous_time = Instant::now();
 
-        // run the main loop at 60 Hz
         'main_loop: loop {
-            previous_time = Instant::now();
-
             let event_iter: Vec<Event> = self.events.poll_iter().collect();
             for event in event_iter {
                 match event {
                     Event::Quit { .. } => break 'main_loop,
-                    Event::KeyUp { scancode, .. } => {
+                    Event::Window {
+                        window_id,
+                        win_event: WindowEvent::Close,
+                        ..
+                    } => {
+                        // closing the debugger leaves the emulation running
+                        if self.is_debugger_window(window_id) {
+                            self.debugger = None;
+                        } else {
+                            break 'main_loop;
+                        }
+                    }
+                    Event::KeyUp {
+                        scancode: Some(scancode),
+                        ..
+                    } => {
+                        self.handle_hotkey_input(scancode);
                         if self.config.debug_mode {
-                            self.handle_debug_input(scancode.unwrap());
+                            self.handle_debug_input(scancode);
                         }
                     }
                     _ => {}
                 }
             }
 
-            // if in debug mode & paused, skip execution
+            if let Some(gdb) = self.gdb.as_mut() {
+                match gdb.poll(&mut self.machine)? {
+                    GdbEvent::Step => {
+                        self.execute()?;
+                        self.report_gdb_stop(false)?;
+                    }
+                    GdbEvent::ReverseStep => {
+                        self.step_back();
+                        self.report_gdb_stop(false)?;
+                    }
+                    GdbEvent::ReverseContinue => {
+                        let breakpoint = self.reverse_continue();
+                        self.report_gdb_stop(breakpoint)?;
+                    }
+                    GdbEvent::Kill => break 'main_loop,
+                    GdbEvent::Detach => self.gdb = None,
+                    GdbEvent::None => (),
+                }
+            }
+
+            let lines = self
+                .console
+                .as_ref()
+                .map(|c| c.pending())
+                .unwrap_or_default();
+            for line in lines.iter().filter(|l| !l.trim().is_empty()) {
+                match parse_command(line, &self.symbols) {
+                    Ok(cmd) => self.handle_console_command(cmd),
+                    Err(e) => println!("{}", e),
+                }
+            }
+            let now = Instant::now();
+            let elapsed = now.duration_since(previous_time);
+            previous_time = now;
+
+            // if in debug mode & paused, skip execution;
+            // the paused time is not caught up later.
             if self.is_paused() {
+                // timers are frozen, so the tone must not keep playing
+                self.audio.lock().set_playing(false);
+                self.refresh_screen()?;
+                self.refresh_debugger()?;
+                sleep(Duration::from_secs(1) / TIMER_FREQUENCY);
                 continue;
             }
 
-            self.handle_timers();
             self.register_pressed_keys();
+            self.update_turbo();
+
+            if self.speed.turbo && !self.debug.step_exec {
+                self.run_turbo(now)?;
+                self.refresh_screen()?;
+                continue;
+            }
 
-            for _ in 0..self.config.instructions_per_cycle() {
-                self.machine.run_instruction(self.debug.step_exec)
+            let elapsed = if self.speed.slow_motion {
+                elapsed / SLOW_MOTION_DIVISOR
+            } else {
+                elapsed
+            };
+
+            if self.debug.step_exec {
+                self.run_frame(self.config.instructions_per_cycle())?;
+                self.handle_timers();
+            } else if self.config.vip_timing {
+                let tick = self.scheduler.advance(elapsed);
+                for _ in 0..tick.timer_ticks {
+                    self.run_vip_frame()?;
+                    self.handle_timers();
+                }
+            } else {
+                let tick = self.scheduler.advance(elapsed);
+                self.run_interleaved(tick.instructions, tick.timer_ticks)?;
             }
 
             self.refresh_screen()?;
+            self.refresh_debugger()?;
             self.reset_exec_step();
 
-            self.handle_loop_sync(Instant::now().duration_since(previous_time));
+            let frame = Duration::from_secs(1) / TIMER_FREQUENCY;
+            sleep(self.scheduler.until_next_timer_tick().min(frame));
+        }
+
+        if let (Some(profiler), Some(path)) = (&self.profiler, &self.config.profile) {
+            profiler.write_report(path, &self.symbols)?;
+        }
+
+        Ok(())
+    }
+
+    // Runs whole frames back-to-back for the duration of a single
+    // host frame, without syncing to the real time.
+    fn run_turbo(&mut self, frame_start: Instant) -> Result<(), InterpErr> {
+        let frame = Duration::from_secs(1) / TIMER_FREQUENCY;
+
+        while frame_start.elapsed() < frame && !self.is_halted() {
+            self.run_frame(self.config.instructions_per_cycle())?;
+            self.handle_timers();
+        }
+        Ok(())
+    }
+
+    // Spreads the instructions evenly between timer ticks, so that programs
+    // polling the delay timer observe it decrementing at the right pace,
+    // even when several frames are caught up at once.
+    fn run_interleaved(&mut self, instructions: u32, timer_ticks: u32) -> Result<(), InterpErr> {
+        if timer_ticks == 0 {
+            return self.run_instructions(instructions);
+        }
+
+        let mut executed = 0;
+        for t in 1..=timer_ticks {
+            let due = instructions * t / timer_ticks;
+            self.run_instructions(due - executed)?;
+            executed = due;
+            self.handle_timers();
+        }
+        Ok(())
+    }
+
+    fn run_instructions(&mut self, count: u32) -> Result<(), InterpErr> {
+        for _ in 0..count {
+            if !self.execute_next()? {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self) -> Result<(), InterpErr> {
+        let (raw, before) = (self.machine.next_opcode(), self.machine.registers());
+
+        match self.history.as_mut() {
+            Some(history) => self
+                .machine
+                .run_instruction_recorded(self.debug.step_exec, history),
+            None => self.machine.run_instruction(self.debug.step_exec),
+        }
 
+        let (after, writes) = (self.machine.registers(), self.machine.memory_writes());
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer.record(raw, &before, &after, writes, &self.symbols)?;
+        }
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.record(before.pc, raw);
+        }
+        if let Some(memcheck) = self.memcheck.as_mut() {
+            for warning in memcheck.check(raw, &before, &after, writes, &self.symbols) {
+                println!("Warning: {}", warning);
+                self.osd.message(warning);
+            }
         }
 
+        self.osd.record_instructions(1);
         Ok(())
     }
 
+    // Reverts the last executed instruction; returns false if there's no history left.
+    fn step_back(&mut self) -> bool {
+        match self.history.as_mut() {
+            Some(history) => self.machine.step_back(history),
+            None => false,
+        }
+    }
+
+    // Reverts executed instructions until a breakpoint (set either
+    // by the debug keys or GDB) is reached; returns whether it was.
+    fn reverse_continue(&mut self) -> bool {
+        while self.step_back() {
+            let pc = self.machine.registers().pc;
+            if self.debug.breakpoints.contains(pc)
+                || self.gdb.as_ref().is_some_and(|g| g.is_breakpoint(pc))
+            {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn report_gdb_stop(&mut self, breakpoint: bool) -> Result<(), InterpErr> {
+        match self.gdb.as_mut() {
+            Some(gdb) => gdb.report_stop(breakpoint),
+            None => Ok(()),
+        }
+    }
+
+    fn run_frame(&mut self, instructions: u32) -> Result<(), InterpErr> {
+        if self.config.vip_timing {
+            self.run_vip_frame()
+        } else {
+            self.run_instructions(instructions)
+        }
+    }
 
     fn initiate_video(sdl_ctx: &sdl2::Sdl, config: &Config) -> Result<Canvas<Window>, InterpErr> {
         let video_subsys = sdl_ctx.video()?;
@@ -127,69 +462,62 @@ impl Interpreter {
         Ok(canvas)
     }
 
-    fn initiate_audio(sdl_ctx: &sdl2::Sdl) -> Result<AudioQueue<i16>, InterpErr> {
-        let audio_subsys = sdl_ctx.audio()?;
-        let audio_queue = audio_subsys.open_queue::<i16, _>(
-            None,
-            &AudioSpecDesired {
-                freq: Some(44_100),
-                channels: Some(1),
-                samples: Some(4),
-            },
-        )?;
-        audio_queue.queue(&generate_sound());
-        Ok(audio_queue)
-    }
-
     fn refresh_screen(&mut self) -> Result<(), InterpErr> {
-        if !self.machine.screen.should_refresh() {
+        for warning in self.machine.take_warnings() {
+            println!("Warning: {}", warning);
+            self.osd.message(warning);
+        }
+
+        // with OSD content displayed, the screen is redrawn every frame
+        let changed = self.machine.screen.should_refresh();
+        if !changed && !self.osd.is_active() {
             return Ok(());
         }
 
         self.video.set_draw_color(self.config.background_color);
         self.video.clear();
         self.video.set_draw_color(self.config.foreground_color);
+        let rects = render::display_rects(&self.machine.screen.display, self.config.screen_size);
+        self.video.fill_rects(&rects)?;
 
-        for x in 0..64 {
-            for y in 0..32 {
-                let xy = (y * DISPLAY_WIDTH) + x;
-
-                if self.machine.screen.display[xy] {
-                    let r = Rect::new(
-                        (x as u32 * self.config.screen_size) as i32,
-                        (y as u32 * self.config.screen_size) as i32,
-                        self.config.screen_size,
-                        self.config.screen_size,
-                    );
-                    self.video.fill_rect(r)?;
-                    self.video.draw_rect(r)?;
-                }
-            }
-        }
+        let osd_scale = (self.config.screen_size / 5).max(1);
+        self.osd
+            .render(&mut self.video, osd_scale, &self.machine.registers())?;
 
         self.video.present();
         Ok(())
     }
 
+    fn refresh_debugger(&mut self) -> Result<(), InterpErr> {
+        match self.debugger.as_mut() {
+            Some(d) => d.render(&self.machine, &self.symbols),
+            None => Ok(()),
+        }
+    }
+
+    fn is_debugger_window(&self, window_id: u32) -> bool {
+        self.debugger
+            .as_ref()
+            .is_some_and(|d| d.window_id() == window_id)
+    }
+
     fn handle_timers(&mut self) {
-        if self.machine.delay_timer > 0 {
-            self.machine.delay_timer -= 1;
+        self.machine.tick_timers();
+        // pinned values and cheats are forced once per emulated frame
+        self.pins.apply(&mut self.machine);
+        self.cheats.apply(&mut self.machine);
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.end_frame();
         }
 
         // as long as sound timer is > 0, emit beep
-        if self.machine.sound_timer > 0 {
-            self.machine.sound_timer -= 1;
-            self.audio.queue(&generate_sound());
-            self.audio.resume();
-        } else {
-            self.audio.pause();
-        }
+        self.audio.lock().set_playing(self.machine.sound_timer > 0);
     }
 
     fn register_pressed_keys(&mut self) {
         self.machine.input.clear();
         let keyb_state = self.events.keyboard_state();
-        for k in keyb_state.pressed_scancodes().into_iter() {
+        for k in keyb_state.pressed_scancodes() {
             match k {
                 Scancode::Num1 => self.machine.input.push(0x1),
                 Scancode::Num2 => self.machine.input.push(0x2),
@@ -212,16 +540,74 @@ impl Interpreter {
         }
     }
 
+    fn handle_hotkey_input(&mut self, scancode: Scancode) {
+        match scancode {
+            Scancode::M => {
+                let muted = self.audio.lock().toggle_mute();
+                self.osd.message(if muted { "Muted" } else { "Unmuted" });
+            }
+            Scancode::Grave => {
+                self.speed.slow_motion = !self.speed.slow_motion;
+                self.osd.message(if self.speed.slow_motion {
+                    "Slow motion on"
+                } else {
+                    "Slow motion off"
+                });
+            }
+            Scancode::F1 => self.osd.show_fps = !self.osd.show_fps,
+            Scancode::F2 => self.osd.show_registers = !self.osd.show_registers,
+            Scancode::Equals | Scancode::KpPlus => self.change_speed(true),
+            Scancode::Minus | Scancode::KpMinus => self.change_speed(false),
+            _ => (),
+        }
+    }
+
+    // Changes instructions per frame by roughly 10%, at least by one.
+    fn change_speed(&mut self, increase: bool) {
+        // VIP timing runs instructions by their cycle counts, not per frame
+        if self.config.vip_timing {
+            self.osd.message("Speed is fixed with VIP timing");
+            return;
+        }
+
+        let ipf = self.config.instructions_per_cycle();
+        let step = (ipf / 10).max(1);
+        let ipf = if increase {
+            (ipf + step).min(MAX_INSTRUCTIONS_PER_FRAME)
+        } else {
+            ipf.saturating_sub(step).max(1)
+        };
+
+        self.config.set_instructions_per_cycle(ipf);
+        self.scheduler
+            .set_instruction_rate(self.config.instructions_per_second());
+        self.osd.message(format!("Speed: {} instr. per frame", ipf));
+    }
+
+    fn update_turbo(&mut self) {
+        let held = self
+            .events
+            .keyboard_state()
+            .is_scancode_pressed(Scancode::Tab);
+
+        if held != self.speed.turbo {
+            self.speed.turbo = held;
+            self.osd
+                .message(if held { "Turbo on" } else { "Turbo off" });
+        }
+    }
+
     fn handle_debug_input(&mut self, scancode: Scancode) {
         match scancode {
             // Debug
             Scancode::P => println!("{:?}", self.machine),
             Scancode::End => {
-                println!(
-                    "Toggling interpreter state to - running: {}",
-                    !self.debug.running
-                );
-                self.toggle_state()
+                self.toggle_state();
+                self.osd.message(if self.debug.running {
+                    "Running"
+                } else {
+                    "Paused"
+                });
             }
             Scancode::PageDown => {
                 // ignore if interpreter not paused
@@ -229,25 +615,148 @@ impl Interpreter {
                     return;
                 }
 
-                println!("Running next cycle");
+                self.osd.message("Running next cycle");
                 self.debug.step_exec = true;
+                self.debug.breakpoints.resume();
+            }
+            Scancode::PageUp => {
+                if self.debug.running {
+                    return;
+                }
+
+                if self.step_back() {
+                    let pc = self.symbols.describe(self.machine.registers().pc);
+                    self.osd.message(format!("Stepped back to {}", pc));
+                } else {
+                    self.osd.message("No earlier instructions recorded");
+                }
+            }
+            Scancode::Home => {
+                if self.debug.running {
+                    return;
+                }
+
+                if self.reverse_continue() {
+                    let pc = self.machine.registers().pc;
+                    self.osd
+                        .message(format!("Reversed to breakpoint at {:03X}", pc));
+                } else {
+                    self.osd.message("No earlier breakpoint hit recorded");
+                }
+            }
+            Scancode::B => {
+                let pc = self.machine.registers().pc;
+                if self.debug.breakpoints.insert(pc) {
+                    self.osd.message(format!("Breakpoint set at {:03X}", pc));
+                } else {
+                    self.debug.breakpoints.remove(pc);
+                    self.osd
+                        .message(format!("Breakpoint cleared at {:03X}", pc));
+                }
             }
             _ => (),
         }
     }
 
-    fn handle_loop_sync(&mut self, elapsed: Duration) {
-        sleep(Duration::from_micros(
-            SLEEP_TIME.checked_sub(elapsed.as_micros()).unwrap_or(0u128) as u64,
-        ));
+    fn handle_console_command(&mut self, cmd: Command) {
+        match cmd {
+            Command::Search => {
+                self.search.start(&self.machine);
+                println!("{} candidates", self.search.candidates().len());
+            }
+            Command::Filter(filter) => {
+                let left = self.search.filter(&self.machine, filter);
+                println!("{} candidates left", left);
+                if left <= MAX_LISTED_CANDIDATES {
+                    self.list_candidates();
+                }
+            }
+            Command::List => self.list_candidates(),
+            Command::Pin(location, val) => {
+                self.pins.pin(location, val);
+                location.write(&mut self.machine, val);
+                self.osd.message(format!("Pinned {} to {}", location, val));
+            }
+            Command::Unpin(location) => {
+                if self.pins.unpin(location) {
+                    self.osd.message(format!("Unpinned {}", location));
+                } else {
+                    println!("{} is not pinned", location);
+                }
+            }
+            Command::Pins => {
+                for (location, val) in self.pins.iter() {
+                    println!("{} = {}", self.describe_location(location), val);
+                }
+            }
+            Command::Cheats => {
+                if self.cheats.cheats().is_empty() {
+                    println!("No cheats in {}", self.cheats.path().display());
+                }
+                for (n, cheat) in self.cheats.cheats().iter().enumerate() {
+                    let state = if cheat.enabled { "on" } else { "off" };
+                    println!("{}. [{}] {}", n + 1, state, cheat.name);
+                }
+            }
+            Command::ToggleCheat(n) => match self.cheats.toggle(n, &mut self.machine) {
+                Some(enabled) => {
+                    let name = &self.cheats.cheats()[n].name;
+                    let state = if enabled { "on" } else { "off" };
+                    self.osd.message(format!("Cheat {} {}", name, state));
+                    self.save_cheats();
+                }
+                None => println!("No cheat number {}", n + 1),
+            },
+            Command::AddCheat(cheat) => {
+                self.osd.message(format!("Cheat {} added", cheat.name));
+                self.cheats.add(cheat, &mut self.machine);
+                self.save_cheats();
+            }
+            Command::Help => println!("{}", CONSOLE_HELP),
+        }
+    }
+
+    fn save_cheats(&self) {
+        match self.cheats.save() {
+            Ok(_) => println!("Cheats saved to {}", self.cheats.path().display()),
+            Err(e) => println!("Saving cheats failed: {}", e),
+        }
+    }
+
+    fn list_candidates(&self) {
+        let candidates = self.search.candidates();
+        for (location, val) in candidates.iter().take(MAX_LISTED_CANDIDATES) {
+            println!("{} = {}", self.describe_location(location), val);
+        }
+        if candidates.len() > MAX_LISTED_CANDIDATES {
+            println!("...");
+        }
+    }
+
+    fn describe_location(&self, location: &Location) -> String {
+        match location {
+            Location::Memory(addr) => match self.symbols.nearest_label(*addr) {
+                Some(label) => format!("{} ({})", location, label),
+                None => location.to_string(),
+            },
+            Location::Register(_) => location.to_string(),
+        }
     }
 
     fn is_paused(&self) -> bool {
-        self.config.debug_mode && !self.debug.running && !self.debug.step_exec
+        self.is_halted() || self.config.debug_mode && !self.debug.running && !self.debug.step_exec
+    }
+
+    // Halted by the attached GDB.
+    fn is_halted(&self) -> bool {
+        self.gdb.as_ref().is_some_and(|g| g.is_halted())
     }
 
     fn toggle_state(&mut self) {
-        self.debug.running = !self.debug.running
+        self.debug.running = !self.debug.running;
+        if self.debug.running {
+            self.debug.breakpoints.resume();
+        }
     }
 
     fn reset_exec_step(&mut self) {
@@ -257,18 +766,36 @@ impl Interpreter {
     }
 }
 
-// generate a square wave
-fn generate_sound() -> Vec<i16> {
-    let tone_volume = 1_000i16;
-    let period = 48_000 / 256;
-    let mut result = Vec::new();
+impl VipFrames for Interpreter {
+    type Error = InterpErr;
 
-    for x in 0..8_000 {
-        result.push(if (x / period) % 2 == 0 {
-            tone_volume
-        } else {
-            -tone_volume
-        });
+    fn machine(&self) -> &Chip8 {
+        &self.machine
+    }
+
+    fn cycle_credit(&mut self) -> &mut i64 {
+        &mut self.cycle_credit
+    }
+
+    // Executes a single instruction, unless the execution
+    // is halted (by a breakpoint); returns whether it was executed.
+    fn execute_next(&mut self) -> Result<bool, InterpErr> {
+        let pc = self.machine.registers().pc;
+        if let Some(gdb) = self.gdb.as_mut() {
+            if gdb.is_halted() || gdb.check_breakpoint(pc)? {
+                return Ok(false);
+            }
+        }
+
+        if self.debug.breakpoints.hit(pc) {
+            self.debug.running = false;
+            self.debug.step_exec = false;
+            let location = self.symbols.describe(pc);
+            self.osd.message(format!("Breakpoint at {}", location));
+            return Ok(false);
+        }
+
+        self.execute()?;
+        Ok(true)
     }
-    result
 }
@@ -0,0 +1,160 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+mod cfg;
+mod lint;
+
+pub use cfg::{BasicBlock, Cfg, Edge, EdgeKind};
+pub use lint::{lint, Finding, Report, Severity};
+
+// How an instruction passes control on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Flow {
+    // continues with the next instruction
+    Next,
+    // continues with the next instruction or the one after it
+    Skip,
+    Jump(u16),
+    // jump to the address plus a register, e.g. into a jump table
+    IndirectJump(u16),
+    // continues at the subroutine, and with the next instruction once it returns
+    Call(u16),
+    Return,
+}
+
+pub fn flow(opcode: u16) -> Flow {
+    let nnn = opcode & 0xFFF;
+    match opcode >> 12 {
+        0x0 if opcode == 0x00EE => Flow::Return,
+        0x1 => Flow::Jump(nnn),
+        0x2 => Flow::Call(nnn),
+        0x3 | 0x4 | 0x5 | 0x9 => Flow::Skip,
+        0xB => Flow::IndirectJump(nnn),
+        0xE if matches!(opcode & 0xFF, 0x9E | 0xA1) => Flow::Skip,
+        _ => Flow::Next,
+    }
+}
+
+// ROM as loaded to memory.
+pub struct Rom<'a> {
+    bytes: &'a [u8],
+    start: u16,
+}
+
+impl<'a> Rom<'a> {
+    // ROM loaded at, and executed from, given address;
+    // bytes which don't fit in memory are ignored.
+    pub fn at(bytes: &'a [u8], start: u16) -> Rom<'a> {
+        let max_len = 4096usize.saturating_sub(start as usize);
+        Rom {
+            bytes: &bytes[..bytes.len().min(max_len)],
+            start,
+        }
+    }
+
+    pub fn start(&self) -> u16 {
+        self.start
+    }
+
+    // Address just past the last byte of the ROM.
+    pub fn end(&self) -> u32 {
+        self.start as u32 + self.bytes.len() as u32
+    }
+
+    // Whether an instruction at the address lies within the ROM.
+    pub fn contains(&self, addr: u16) -> bool {
+        addr >= self.start && addr as u32 + 2 <= self.end()
+    }
+
+    pub fn opcode_at(&self, addr: u16) -> Option<u16> {
+        if !self.contains(addr) {
+            return None;
+        }
+        let offset = (addr - self.start) as usize;
+        Some(u16::from_be_bytes([
+            self.bytes[offset],
+            self.bytes[offset + 1],
+        ]))
+    }
+
+    // Addresses control may pass to after the instruction at `addr`, within
+    // the same routine (calls return to the next instruction). Of an indirect
+    // jump, only its base address is known.
+    pub fn successors(&self, addr: u16, opcode: u16) -> Vec<u16> {
+        match flow(opcode) {
+            Flow::Next | Flow::Call(_) => vec![addr + 2],
+            Flow::Skip => vec![addr + 2, addr + 4],
+            Flow::Jump(target) | Flow::IndirectJump(target) => vec![target],
+            Flow::Return => Vec::new(),
+        }
+    }
+
+    // Instructions (address and opcode) of the routine starting at `entry`,
+    // i.e. reachable from it without following calls.
+    pub fn routine(&self, entry: u16) -> BTreeMap<u16, u16> {
+        self.walk(&[entry], false)
+    }
+
+    // Instructions reachable from the start, following calls.
+    pub fn reachable(&self) -> BTreeMap<u16, u16> {
+        self.walk(&[self.start], true)
+    }
+
+    fn walk(&self, entries: &[u16], into_calls: bool) -> BTreeMap<u16, u16> {
+        let mut found = BTreeMap::new();
+        let mut pending: Vec<u16> = entries.to_vec();
+
+        while let Some(addr) = pending.pop() {
+            if found.contains_key(&addr) {
+                continue;
+            }
+            let opcode = match self.opcode_at(addr) {
+                Some(op) => op,
+                None => continue,
+            };
+            found.insert(addr, opcode);
+
+            pending.extend(self.successors(addr, opcode));
+            if let (true, Flow::Call(target)) = (into_calls, flow(opcode)) {
+                pending.push(target);
+            }
+        }
+
+        found
+    }
+
+    // Entry addresses of the subroutines called from reachable code.
+    pub fn subroutines(&self) -> BTreeSet<u16> {
+        self.reachable()
+            .values()
+            .filter_map(|&op| match flow(op) {
+                Flow::Call(target) => Some(target),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Flow, Rom};
+
+    #[test]
+    fn reachable_code_is_found() {
+        let rom = [
+            0x22, 0x08, // 200: CALL 208
+            0x30, 0x01, // 202: SE V0, 01
+            0x12, 0x00, // 204: JP 200
+            0x12, 0x0C, // 206: JP 20C
+            0x00, 0xEE, // 208: RET
+            0xFF, 0xFF, // 20A: data
+        ];
+        let rom = Rom::at(&rom, 0x200);
+
+        let reachable: Vec<u16> = rom.reachable().keys().copied().collect();
+        assert_eq!(reachable, [0x200, 0x202, 0x204, 0x206, 0x208]);
+        assert_eq!(rom.routine(0x208).len(), 1);
+        assert_eq!(rom.subroutines().into_iter().collect::<Vec<_>>(), [0x208]);
+        assert_eq!(super::flow(0xB300), Flow::IndirectJump(0x300));
+        assert!(!rom.contains(0x20C));
+    }
+}
@@ -0,0 +1,263 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use super::{flow, Flow, Rom};
+use crate::interpreter::chip8::disassemble;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EdgeKind {
+    // to the block following in memory
+    Next,
+    Jump,
+    // a register is added to the target, of which only the base is known
+    IndirectJump,
+    // skip instruction's condition holds
+    Skip,
+    // subroutine entry; the caller continues with the next block once it returns
+    Call,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Edge {
+    pub to: u16,
+    pub kind: EdgeKind,
+}
+
+// Straight-line instructions, entered only at the first and left only after the last.
+#[derive(Debug)]
+pub struct BasicBlock {
+    pub start: u16,
+    // address and opcode
+    pub instructions: Vec<(u16, u16)>,
+    pub edges: Vec<Edge>,
+}
+
+impl BasicBlock {
+    fn last(&self) -> (u16, u16) {
+        *self.instructions.last().unwrap()
+    }
+
+    pub fn returns(&self) -> bool {
+        flow(self.last().1) == Flow::Return
+    }
+}
+
+// Control-flow graph of the code reachable from the program start.
+pub struct Cfg {
+    pub start: u16,
+    pub blocks: BTreeMap<u16, BasicBlock>,
+    pub subroutines: BTreeSet<u16>,
+}
+
+impl Cfg {
+    // Graph of the ROM loaded at, and executed from, given address.
+    pub fn new(bytes: &[u8], load_addr: u16) -> Cfg {
+        let rom = Rom::at(bytes, load_addr);
+        let reachable = rom.reachable();
+        let leaders = leaders(&rom, &reachable);
+
+        let mut blocks: BTreeMap<u16, BasicBlock> = BTreeMap::new();
+        let mut current: Option<BasicBlock> = None;
+        for (&addr, &op) in &reachable {
+            let continues = matches!(&current, Some(b) if b.last().0 + 2 == addr);
+            if !continues || leaders.contains(&addr) {
+                if let Some(block) = current.take() {
+                    blocks.insert(block.start, block);
+                }
+            }
+
+            let block = current.get_or_insert_with(|| BasicBlock {
+                start: addr,
+                instructions: Vec::new(),
+                edges: Vec::new(),
+            });
+            block.instructions.push((addr, op));
+        }
+        if let Some(block) = current {
+            blocks.insert(block.start, block);
+        }
+
+        for block in blocks.values_mut() {
+            block.edges = edges(&rom, block.last());
+        }
+
+        Cfg {
+            start: rom.start(),
+            blocks,
+            subroutines: rom.subroutines(),
+        }
+    }
+
+    // Graph in the Graphviz DOT language; blocks are labelled with their
+    // disassembly, subroutine entries are drawn bold and the program start double.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph cfg {\n    node [shape=box, fontname=monospace];\n");
+
+        for block in self.blocks.values() {
+            let mut label = String::new();
+            for &(addr, op) in &block.instructions {
+                let line = format!("{:03X}  {:04X}  {}", addr, op, disassemble(op, |_| None));
+                label.push_str(&escape(&line));
+                label.push_str("\\l");
+            }
+
+            let style = if block.start == self.start {
+                ", peripheries=2"
+            } else if self.subroutines.contains(&block.start) {
+                ", style=bold"
+            } else {
+                ""
+            };
+            let _ = writeln!(
+                dot,
+                "    {} [label=\"{}\"{}];",
+                node(block.start),
+                label,
+                style
+            );
+        }
+
+        for block in self.blocks.values() {
+            for edge in &block.edges {
+                let attrs = match edge.kind {
+                    EdgeKind::Next => "",
+                    EdgeKind::Jump => " [label=\"jump\"]",
+                    EdgeKind::IndirectJump => " [label=\"jump + V\", style=dashed]",
+                    EdgeKind::Skip => " [label=\"skip\"]",
+                    EdgeKind::Call => " [label=\"call\", style=dotted]",
+                };
+                // targets out of the ROM have no block; draw them anyway
+                let _ = writeln!(
+                    dot,
+                    "    {} -> {}{};",
+                    node(block.start),
+                    node(edge.to),
+                    attrs
+                );
+            }
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+fn node(addr: u16) -> String {
+    format!("b{:03X}", addr)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// Addresses starting basic blocks: the program start, targets of jumps
+// and calls, and the instructions after any which passes control elsewhere.
+fn leaders(rom: &Rom<'_>, reachable: &BTreeMap<u16, u16>) -> BTreeSet<u16> {
+    let mut leaders = BTreeSet::new();
+    leaders.insert(rom.start());
+
+    for (&addr, &op) in reachable {
+        let f = flow(op);
+        if let Flow::Jump(t) | Flow::IndirectJump(t) | Flow::Call(t) = f {
+            leaders.insert(t);
+        }
+        if f != Flow::Next {
+            leaders.extend(rom.successors(addr, op));
+            leaders.insert(addr + 2);
+        }
+    }
+
+    leaders
+}
+
+fn edges(rom: &Rom<'_>, (addr, op): (u16, u16)) -> Vec<Edge> {
+    let next = |kind| Edge { to: addr + 2, kind };
+    match flow(op) {
+        Flow::Next => vec![next(EdgeKind::Next)],
+        Flow::Skip => vec![
+            next(EdgeKind::Next),
+            Edge {
+                to: addr + 4,
+                kind: EdgeKind::Skip,
+            },
+        ],
+        Flow::Jump(to) => vec![Edge {
+            to,
+            kind: EdgeKind::Jump,
+        }],
+        Flow::IndirectJump(to) => vec![Edge {
+            to,
+            kind: EdgeKind::IndirectJump,
+        }],
+        Flow::Call(to) => vec![
+            Edge {
+                to,
+                kind: EdgeKind::Call,
+            },
+            next(EdgeKind::Next),
+        ],
+        Flow::Return => Vec::new(),
+    }
+    .into_iter()
+    // jumps out of the ROM are kept, falling off its end isn't
+    .filter(|e| e.kind != EdgeKind::Next || rom.contains(e.to))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cfg, Edge, EdgeKind};
+
+    #[test]
+    fn blocks_are_split_at_control_flow() {
+        let rom = [
+            0x60, 0x01, // 200: LD V0, 01
+            0x22, 0x0A, // 202: CALL 20A
+            0x30, 0x01, // 204: SE V0, 01
+            0x12, 0x00, // 206: JP 200
+            0x12, 0x04, // 208: JP 204
+            0x70, 0x01, // 20A: ADD V0, 01
+            0x00, 0xEE, // 20C: RET
+        ];
+        let cfg = Cfg::new(&rom, 0x200);
+
+        let starts: Vec<u16> = cfg.blocks.keys().copied().collect();
+        assert_eq!(starts, [0x200, 0x204, 0x206, 0x208, 0x20A]);
+        assert_eq!(cfg.blocks[&0x200].instructions.len(), 2);
+        assert_eq!(
+            cfg.blocks[&0x204].edges,
+            [
+                Edge {
+                    to: 0x206,
+                    kind: EdgeKind::Next
+                },
+                Edge {
+                    to: 0x208,
+                    kind: EdgeKind::Skip
+                }
+            ]
+        );
+        assert!(cfg.blocks[&0x20A].returns());
+
+        let dot = cfg.to_dot();
+        assert!(dot.contains("b200 -> b20A [label=\"call\", style=dotted];"));
+        assert!(
+            dot.contains("b20A [label=\"20A  7001  ADD V0, 01\\l20C  00EE  RET\\l\", style=bold];")
+        );
+    }
+
+    #[test]
+    fn graph_starts_at_the_load_address() {
+        let rom = [
+            0x60, 0x01, // 600: LD V0, 01
+            0x16, 0x00, // 602: JP 600
+        ];
+        let cfg = Cfg::new(&rom, 0x600);
+
+        assert_eq!(cfg.blocks.keys().copied().collect::<Vec<u16>>(), [0x600]);
+        assert!(cfg
+            .to_dot()
+            .contains("b600 [label=\"600  6001  LD V0, 01\\l"));
+        assert!(cfg.to_dot().contains("\\l\", peripheries=2];"));
+    }
+}
@@ -0,0 +1,546 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use super::{flow, Flow, Rom};
+use crate::interpreter::chip8::{disassemble, is_known_opcode};
+
+// How far back (in instructions) registers set for BNNN are looked for,
+// and how far after FX55 / FX65 uses of I are.
+const LOOK_BEHIND: u16 = 8;
+const LOOK_AHEAD: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Finding {
+    // None for findings about the whole ROM
+    pub addr: Option<u16>,
+    pub severity: Severity,
+    pub message: String,
+}
+
+// Result of checking a ROM without running it.
+pub struct Report {
+    pub findings: Vec<Finding>,
+    // quirk name and what the ROM probably expects of it
+    pub quirks: Vec<(&'static str, String)>,
+    reachable: BTreeMap<u16, u16>,
+}
+
+impl Report {
+    pub fn has_errors(&self) -> bool {
+        self.findings.iter().any(|f| f.severity == Severity::Error)
+    }
+
+    fn count(&self, severity: Severity) -> usize {
+        self.findings
+            .iter()
+            .filter(|f| f.severity == severity)
+            .count()
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Info => write!(f, "info"),
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for finding in &self.findings {
+            match finding
+                .addr
+                .and_then(|a| Some((a, *self.reachable.get(&a)?)))
+            {
+                Some((addr, op)) => write!(
+                    f,
+                    "{:03X}  {:04X}  {:<16}  ",
+                    addr,
+                    op,
+                    disassemble(op, |_| None)
+                )?,
+                None => {
+                    if let Some(addr) = finding.addr {
+                        write!(f, "{:03X}  ", addr)?;
+                    }
+                }
+            }
+            writeln!(f, "{}: {}", finding.severity, finding.message)?;
+        }
+
+        writeln!(f, "\nQuirks:")?;
+        for (name, verdict) in &self.quirks {
+            writeln!(f, "- {}: {}", name, verdict)?;
+        }
+
+        writeln!(
+            f,
+            "\n{} reachable instructions; {} errors, {} warnings",
+            self.reachable.len(),
+            self.count(Severity::Error),
+            self.count(Severity::Warning)
+        )
+    }
+}
+
+// Checks the code reachable from the program start for unknown instructions,
+// jumps out of the ROM, unbounded recursion and the quirks it relies on;
+// the ROM is loaded at `load_addr`, and meant to be run in `c48_mode`.
+pub fn lint(bytes: &[u8], load_addr: u16, c48_mode: bool) -> Report {
+    let rom = Rom::at(bytes, load_addr);
+    let reachable = rom.reachable();
+    let mut findings = Vec::new();
+
+    for (&addr, &op) in &reachable {
+        if !is_known_opcode(op) {
+            findings.push(error(
+                addr,
+                "unknown instruction, skipped by the interpreter",
+            ));
+        }
+        check_targets(&rom, addr, op, &mut findings);
+    }
+    check_subroutines(&rom, &mut findings);
+
+    let quirks = vec![
+        (
+            "shift (8XY6 / 8XYE)",
+            shift_quirk(&reachable, c48_mode, &mut findings),
+        ),
+        (
+            "jump (BNNN)",
+            jump_quirk(&rom, &reachable, c48_mode, &mut findings),
+        ),
+        (
+            "memory (FX55 / FX65)",
+            memory_quirk(&rom, &reachable, &mut findings),
+        ),
+    ];
+
+    findings.sort_by_key(|f| (f.addr, f.severity));
+    Report {
+        findings,
+        quirks,
+        reachable,
+    }
+}
+
+fn finding(addr: u16, severity: Severity, message: impl Into<String>) -> Finding {
+    Finding {
+        addr: Some(addr),
+        severity,
+        message: message.into(),
+    }
+}
+
+fn error(addr: u16, message: impl Into<String>) -> Finding {
+    finding(addr, Severity::Error, message)
+}
+
+fn check_targets(rom: &Rom<'_>, addr: u16, op: u16, findings: &mut Vec<Finding>) {
+    let target = match flow(op) {
+        Flow::Jump(t) | Flow::Call(t) | Flow::IndirectJump(t) => Some(t),
+        _ => None,
+    };
+    match target {
+        Some(t) if t < rom.start() => findings.push(error(
+            addr,
+            format!(
+                "target {:03X} is below the program start, in the interpreter's memory",
+                t
+            ),
+        )),
+        Some(t) if !rom.contains(t) => findings.push(error(
+            addr,
+            format!("target {:03X} is past the end of the ROM", t),
+        )),
+        _ => {}
+    }
+
+    let falls_through = matches!(flow(op), Flow::Next | Flow::Skip | Flow::Call(_));
+    if falls_through && !rom.contains(addr + 2) {
+        findings.push(finding(
+            addr,
+            Severity::Warning,
+            "execution continues past the end of the ROM",
+        ));
+    }
+}
+
+// Looks for subroutines which never return and cycles of calls.
+fn check_subroutines(rom: &Rom<'_>, findings: &mut Vec<Finding>) {
+    let mut calls = BTreeMap::new();
+    for entry in rom.subroutines() {
+        let routine = rom.routine(entry);
+        if !routine.is_empty() && !routine.values().any(|&op| flow(op) == Flow::Return) {
+            findings.push(finding(
+                entry,
+                Severity::Warning,
+                "subroutine never returns; each call leaves its return address on the stack",
+            ));
+        }
+
+        let callees: BTreeSet<u16> = routine
+            .values()
+            .filter_map(|&op| match flow(op) {
+                Flow::Call(t) => Some(t),
+                _ => None,
+            })
+            .collect();
+        calls.insert(entry, callees);
+    }
+
+    let mut reported = BTreeSet::new();
+    for &entry in calls.keys() {
+        if let Some(cycle) = find_cycle(&calls, entry) {
+            // a cycle is found from each of its subroutines; report it once
+            let members: BTreeSet<u16> = cycle.iter().copied().collect();
+            if reported.insert(members) {
+                let path: Vec<String> = cycle.iter().map(|a| format!("{:03X}", a)).collect();
+                findings.push(finding(
+                    entry,
+                    Severity::Warning,
+                    format!(
+                        "possibly unbounded recursion: {} -> {:03X}",
+                        path.join(" -> "),
+                        entry
+                    ),
+                ));
+            }
+        }
+    }
+}
+
+// Path of calls leading from the subroutine back to itself, if any.
+fn find_cycle(calls: &BTreeMap<u16, BTreeSet<u16>>, entry: u16) -> Option<Vec<u16>> {
+    let mut visited = BTreeSet::new();
+    let mut path = vec![entry];
+    cycle_from(calls, entry, entry, &mut visited, &mut path).then_some(path)
+}
+
+fn cycle_from(
+    calls: &BTreeMap<u16, BTreeSet<u16>>,
+    entry: u16,
+    current: u16,
+    visited: &mut BTreeSet<u16>,
+    path: &mut Vec<u16>,
+) -> bool {
+    for &callee in calls.get(&current).into_iter().flatten() {
+        if callee == entry {
+            return true;
+        }
+        if visited.insert(callee) {
+            path.push(callee);
+            if cycle_from(calls, entry, callee, visited, path) {
+                return true;
+            }
+            path.pop();
+        }
+    }
+    false
+}
+
+fn is_shift(op: u16) -> bool {
+    op >> 12 == 0x8 && matches!(op & 0xF, 0x6 | 0xE)
+}
+
+// 8XY6 / 8XYE shift VY into VX on CHIP-8, VX itself on CHIP-48;
+// ROMs for the latter often pass 0 as Y.
+fn shift_quirk(
+    reachable: &BTreeMap<u16, u16>,
+    c48_mode: bool,
+    findings: &mut Vec<Finding>,
+) -> String {
+    let shifts: Vec<(u16, u16)> = reachable
+        .iter()
+        .map(|(&a, &op)| (a, op))
+        .filter(|&(_, op)| is_shift(op))
+        .collect();
+    let sensitive: Vec<(u16, u16)> = shifts
+        .iter()
+        .copied()
+        .filter(|&(_, op)| op >> 8 & 0xF != op >> 4 & 0xF)
+        .collect();
+
+    for &(addr, _) in &sensitive {
+        findings.push(finding(
+            addr,
+            Severity::Info,
+            "shifts VY in CHIP-8 mode, VX in CHIP-48 mode",
+        ));
+    }
+
+    if shifts.is_empty() {
+        return "not used".to_string();
+    }
+    if sensitive.is_empty() {
+        return "not relied on, all shifts have VX = VY".to_string();
+    }
+
+    let expects_c48 = sensitive.iter().all(|&(_, op)| op >> 4 & 0xF == 0);
+    expectation(
+        expects_c48,
+        c48_mode,
+        "shifting VX",
+        "shifting VY",
+        findings,
+    )
+}
+
+// BNNN jumps to NNN + V0 on CHIP-8, BXNN to XNN + VX on CHIP-48;
+// guessed by which of the registers is set shortly before the jump.
+fn jump_quirk(
+    rom: &Rom<'_>,
+    reachable: &BTreeMap<u16, u16>,
+    c48_mode: bool,
+    findings: &mut Vec<Finding>,
+) -> String {
+    let jumps: Vec<(u16, u16)> = reachable
+        .iter()
+        .map(|(&a, &op)| (a, op))
+        .filter(|&(_, op)| op >> 12 == 0xB)
+        .collect();
+    if jumps.is_empty() {
+        return "not used".to_string();
+    }
+
+    let (mut vx_set, mut v0_set, mut sensitive) = (0, 0, 0);
+    for &(addr, op) in &jumps {
+        let x = (op >> 8 & 0xF) as u8;
+        if x == 0 {
+            continue;
+        }
+        sensitive += 1;
+        findings.push(finding(
+            addr,
+            Severity::Info,
+            format!("adds V0 in CHIP-8 mode, V{:X} in CHIP-48 mode", x),
+        ));
+
+        let preceding: Vec<u16> = (1..=LOOK_BEHIND)
+            .filter_map(|n| rom.opcode_at(addr.checked_sub(2 * n)?))
+            .collect();
+        match (
+            preceding.iter().any(|&p| writes_register(p, x)),
+            preceding.iter().any(|&p| writes_register(p, 0)),
+        ) {
+            (true, false) => vx_set += 1,
+            (false, true) => v0_set += 1,
+            _ => {}
+        }
+    }
+
+    if sensitive == 0 {
+        return "not relied on, all jumps have NNN below 100".to_string();
+    }
+    if vx_set == v0_set {
+        return "relied on, but can't tell which way".to_string();
+    }
+    expectation(
+        vx_set > v0_set,
+        c48_mode,
+        "adding VX",
+        "adding V0",
+        findings,
+    )
+}
+
+fn writes_register(op: u16, r: u8) -> bool {
+    let x = (op >> 8 & 0xF) as u8;
+    match op >> 12 {
+        0x6 | 0x7 | 0x8 | 0xC => x == r,
+        0xF => match op & 0xFF {
+            0x07 | 0x0A => x == r,
+            0x65 => r <= x,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+// FX55 / FX65 increment I on the COSMAC VIP (and in CHIP-8 mode), but not on CHIP-48;
+// ROMs using I afterwards without setting it again rely on one or the other.
+fn memory_quirk(
+    rom: &Rom<'_>,
+    reachable: &BTreeMap<u16, u16>,
+    findings: &mut Vec<Finding>,
+) -> String {
+    let stores: Vec<u16> = reachable
+        .iter()
+        .filter(|&(_, &op)| op >> 12 == 0xF && matches!(op & 0xFF, 0x55 | 0x65))
+        .map(|(&a, _)| a)
+        .collect();
+    if stores.is_empty() {
+        return "not used".to_string();
+    }
+
+    let mut sensitive = 0;
+    for &addr in &stores {
+        if let Some(user) = next_use_of_i(rom, addr) {
+            sensitive += 1;
+            findings.push(finding(
+                addr,
+                Severity::Info,
+                format!(
+                    "I is used at {:03X} without being set again; it's incremented on the COSMAC VIP only",
+                    user
+                ),
+            ));
+        }
+    }
+
+    if sensitive == 0 {
+        "not relied on, I is set again after each use".to_string()
+    } else {
+        format!(
+            "relied on after {} of {} uses; CHIP-8 mode increments I like the COSMAC VIP, CHIP-48 mode leaves it unchanged",
+            sensitive,
+            stores.len()
+        )
+    }
+}
+
+// Address of an instruction using I following the one at `addr`, before I is set again.
+fn next_use_of_i(rom: &Rom<'_>, addr: u16) -> Option<u16> {
+    let mut pending: Vec<u16> = rom.successors(addr, rom.opcode_at(addr)?);
+    let mut seen = BTreeSet::new();
+
+    while let Some(a) = pending.pop() {
+        if seen.len() >= LOOK_AHEAD || !seen.insert(a) {
+            continue;
+        }
+        let op = match rom.opcode_at(a) {
+            Some(op) => op,
+            None => continue,
+        };
+
+        match (op >> 12, op & 0xFF) {
+            (0xD, _) | (0xF, 0x1E) | (0xF, 0x33) | (0xF, 0x55) | (0xF, 0x65) => return Some(a),
+            // I is set again
+            (0xA, _) | (0xF, 0x29) => continue,
+            _ => pending.extend(rom.successors(a, op)),
+        }
+    }
+
+    None
+}
+
+// Verdict on a quirk; warns if the chosen mode doesn't match what the ROM probably expects.
+fn expectation(
+    expects_c48: bool,
+    c48_mode: bool,
+    c48_behaviour: &str,
+    chip8_behaviour: &str,
+    findings: &mut Vec<Finding>,
+) -> String {
+    let (behaviour, mode) = if expects_c48 {
+        (c48_behaviour, "CHIP-48 mode (--chip-48-mode)")
+    } else {
+        (chip8_behaviour, "CHIP-8 mode")
+    };
+
+    if expects_c48 != c48_mode {
+        findings.push(Finding {
+            addr: None,
+            severity: Severity::Warning,
+            message: format!("the ROM probably expects {}, as in {}", behaviour, mode),
+        });
+    }
+    format!("probably relies on {}, as in {}", behaviour, mode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lint, Severity};
+
+    fn messages(rom: &[u8], c48_mode: bool) -> Vec<(Option<u16>, Severity)> {
+        lint(rom, 0x200, c48_mode)
+            .findings
+            .iter()
+            .map(|f| (f.addr, f.severity))
+            .collect()
+    }
+
+    #[test]
+    fn invalid_code_is_reported() {
+        let rom = [
+            0x22, 0x08, // 200: CALL 208
+            0x01, 0x23, // 202: SYS 123 (unknown)
+            0x11, 0x00, // 204: JP 100
+            0x12, 0x00, // 206: JP 200
+            0x22, 0x0C, // 208: CALL 20C
+            0x00, 0xEE, // 20A: RET
+            0x22, 0x08, // 20C: CALL 208
+            0x13, 0x00, // 20E: JP 300
+        ];
+        let report = lint(&rom, 0x200, false);
+
+        assert!(report.has_errors());
+        assert_eq!(
+            messages(&rom, false),
+            [
+                (Some(0x202), Severity::Error),
+                (Some(0x204), Severity::Error),
+                (Some(0x208), Severity::Warning),
+                (Some(0x20C), Severity::Warning),
+                (Some(0x20E), Severity::Error),
+            ]
+        );
+        assert!(report.findings[2].message.contains("208 -> 20C -> 208"));
+        assert!(report.findings[3].message.contains("never returns"));
+    }
+
+    #[test]
+    fn relied_on_quirks_are_reported() {
+        let rom = [
+            0xA3, 0x00, // 200: LD I, 300
+            0xF1, 0x55, // 202: LD [I], V1
+            0xF1, 0x55, // 204: LD [I], V1
+            0x81, 0x06, // 206: SHR V1, V0
+            0x62, 0x04, // 208: LD V2, 04
+            0xB2, 0x0C, // 20A: JP V0, 20C (BXNN: 20C + V2)
+            0x12, 0x00, // 20C: JP 200
+            0x12, 0x00, // 20E: JP 200
+            0x12, 0x00, // 210: JP 200
+        ];
+
+        let report = lint(&rom, 0x200, true);
+
+        assert!(!report.has_errors());
+        assert!(report.quirks[0].1.contains("shifting VX"));
+        assert!(report.quirks[1].1.contains("adding VX"));
+        assert!(report.quirks[2].1.contains("relied on after 1 of 2 uses"));
+        // no warning, as the ROM is checked for CHIP-48 mode
+        assert!(report.findings.iter().all(|f| f.severity == Severity::Info));
+
+        assert!(lint(&rom, 0x200, false)
+            .findings
+            .iter()
+            .any(|f| f.addr.is_none() && f.severity == Severity::Warning));
+    }
+
+    #[test]
+    fn targets_are_checked_against_the_load_address() {
+        let rom = [
+            0x12, 0x00, // 600: JP 200
+            0x16, 0x00, // 602: JP 600
+        ];
+        let report = lint(&rom, 0x600, false);
+
+        assert_eq!(report.findings.len(), 1);
+        assert_eq!(report.findings[0].addr, Some(0x600));
+        assert!(report.findings[0]
+            .message
+            .contains("below the program start"));
+    }
+}
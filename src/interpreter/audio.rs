@@ -0,0 +1,306 @@
+use std::f32::consts::PI;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::str::FromStr;
+
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+
+use crate::interpreter::config::Config;
+use crate::InterpErr;
+
+pub const SAMPLE_RATE: i32 = 44_100;
+
+// Amplitude at 100% volume; kept well below i16::MAX,
+// since a full-scale square wave is unpleasantly loud.
+const MAX_AMPLITUDE: f32 = 10_000.0;
+
+// Size of the RIFF / WAVE header for 16-bit PCM.
+const WAV_HEADER_LEN: u32 = 44;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Waveform {
+    Square,
+    Sine,
+    Triangle,
+}
+
+impl FromStr for Waveform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Waveform, String> {
+        match s {
+            "square" => Ok(Waveform::Square),
+            "sine" => Ok(Waveform::Sine),
+            "triangle" => Ok(Waveform::Triangle),
+            _ => Err(format!("unknown waveform '{}'", s)),
+        }
+    }
+}
+
+// Generates the beep tone inside the SDL audio callback. The phase
+// is carried over between callbacks (and while the tone is silent),
+// so the wave never jumps, which is what caused clicks previously.
+pub struct ToneGenerator {
+    waveform: Waveform,
+    phase: f32,
+    phase_inc: f32,
+    amplitude: f32,
+    playing: bool,
+    muted: bool,
+    recorder: Option<WavWriter<BufWriter<File>>>,
+}
+
+impl ToneGenerator {
+    pub fn new(waveform: Waveform, frequency: f32, volume: u8, sample_rate: i32) -> ToneGenerator {
+        ToneGenerator {
+            waveform,
+            phase: 0.0,
+            phase_inc: frequency / sample_rate as f32,
+            amplitude: MAX_AMPLITUDE * (volume.min(100) as f32 / 100.0),
+            playing: false,
+            muted: false,
+            recorder: None,
+        }
+    }
+
+    pub fn set_playing(&mut self, playing: bool) {
+        self.playing = playing;
+    }
+
+    pub fn toggle_mute(&mut self) -> bool {
+        self.muted = !self.muted;
+        self.muted
+    }
+
+    fn record_to(&mut self, writer: WavWriter<BufWriter<File>>) {
+        self.recorder = Some(writer);
+    }
+
+    fn fill(&mut self, out: &mut [i16]) {
+        for s in out.iter_mut() {
+            *s = if self.playing && !self.muted {
+                (self.sample_at(self.phase) * self.amplitude) as i16
+            } else {
+                0
+            };
+
+            self.phase = (self.phase + self.phase_inc) % 1.0;
+        }
+
+        if let Some(rec) = self.recorder.as_mut() {
+            if let Err(e) = rec.write_samples(out) {
+                eprintln!(
+                    "Warning: writing WAV output failed: {}; recording stopped",
+                    e
+                );
+                self.recorder = None;
+            }
+        }
+    }
+
+    // value of the waveform at the given phase, in range -1.0 - 1.0
+    fn sample_at(&self, phase: f32) -> f32 {
+        match self.waveform {
+            Waveform::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Sine => (2.0 * PI * phase).sin(),
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+        }
+    }
+}
+
+impl AudioCallback for ToneGenerator {
+    type Channel = i16;
+
+    fn callback(&mut self, out: &mut [i16]) {
+        self.fill(out);
+    }
+}
+
+pub fn initiate_audio(
+    sdl_ctx: &sdl2::Sdl,
+    config: &Config,
+) -> Result<AudioDevice<ToneGenerator>, InterpErr> {
+    let recorder = match &config.wav_output {
+        Some(path) => Some(WavWriter::new(
+            BufWriter::new(File::create(path)?),
+            SAMPLE_RATE as u32,
+        )?),
+        None => None,
+    };
+
+    let audio_subsys = sdl_ctx.audio()?;
+    let device = audio_subsys.open_playback(
+        None,
+        &AudioSpecDesired {
+            freq: Some(SAMPLE_RATE),
+            channels: Some(1),
+            samples: Some(512),
+        },
+        |spec| {
+            let mut gen = ToneGenerator::new(
+                config.waveform,
+                config.tone_frequency,
+                config.volume,
+                spec.freq,
+            );
+            if let Some(rec) = recorder {
+                gen.record_to(rec);
+            }
+            gen
+        },
+    )?;
+
+    // the device keeps running, the generator outputs
+    // silence while the sound timer is not active.
+    device.resume();
+    Ok(device)
+}
+
+// Minimal writer for mono 16-bit PCM WAV files; the sizes in the
+// header are patched once the writer is finished or dropped.
+pub struct WavWriter<W: Write + Seek> {
+    out: W,
+    data_len: u32,
+    finished: bool,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut out: W, sample_rate: u32) -> io::Result<WavWriter<W>> {
+        let byte_rate = sample_rate * 2;
+
+        out.write_all(b"RIFF")?;
+        out.write_all(&0u32.to_le_bytes())?;
+        out.write_all(b"WAVE")?;
+        out.write_all(b"fmt ")?;
+        out.write_all(&16u32.to_le_bytes())?; // fmt chunk size
+        out.write_all(&1u16.to_le_bytes())?; // PCM
+        out.write_all(&1u16.to_le_bytes())?; // mono
+        out.write_all(&sample_rate.to_le_bytes())?;
+        out.write_all(&byte_rate.to_le_bytes())?;
+        out.write_all(&2u16.to_le_bytes())?; // block align
+        out.write_all(&16u16.to_le_bytes())?; // bits per sample
+        out.write_all(b"data")?;
+        out.write_all(&0u32.to_le_bytes())?;
+
+        Ok(WavWriter {
+            out,
+            data_len: 0,
+            finished: false,
+        })
+    }
+
+    pub fn write_samples(&mut self, samples: &[i16]) -> io::Result<()> {
+        for s in samples {
+            self.out.write_all(&s.to_le_bytes())?;
+        }
+        self.data_len += (samples.len() * 2) as u32;
+        Ok(())
+    }
+
+    pub fn finish(&mut self) -> io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = true;
+
+        self.out.seek(SeekFrom::Start(4))?;
+        self.out
+            .write_all(&(WAV_HEADER_LEN - 8 + self.data_len).to_le_bytes())?;
+        self.out.seek(SeekFrom::Start(40))?;
+        self.out.write_all(&self.data_len.to_le_bytes())?;
+        self.out.seek(SeekFrom::End(0))?;
+        self.out.flush()
+    }
+}
+
+impl<W: Write + Seek> Drop for WavWriter<W> {
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            eprintln!("Warning: finalizing WAV output failed: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ToneGenerator, WavWriter, Waveform};
+    use std::io::Cursor;
+
+    #[test]
+    fn silence_is_generated_when_not_playing_or_muted() {
+        let mut gen = ToneGenerator::new(Waveform::Square, 441.0, 100, 44_100);
+        let mut buf = [1i16; 64];
+
+        gen.fill(&mut buf);
+        assert!(buf.iter().all(|&s| s == 0));
+
+        gen.set_playing(true);
+        assert!(gen.toggle_mute());
+        gen.fill(&mut buf);
+        assert!(buf.iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn phase_is_continuous_across_callbacks() {
+        let mut whole = ToneGenerator::new(Waveform::Sine, 300.0, 50, 44_100);
+        let mut split = ToneGenerator::new(Waveform::Sine, 300.0, 50, 44_100);
+        whole.set_playing(true);
+        split.set_playing(true);
+
+        let mut expected = [0i16; 200];
+        whole.fill(&mut expected);
+
+        let mut first = [0i16; 73];
+        let mut second = [0i16; 127];
+        split.fill(&mut first);
+        split.fill(&mut second);
+
+        assert_eq!(&expected[..73], &first[..]);
+        assert_eq!(&expected[73..], &second[..]);
+    }
+
+    #[test]
+    fn waveforms_are_generated_correctly() {
+        // 100 samples per period
+        let mut square = ToneGenerator::new(Waveform::Square, 441.0, 100, 44_100);
+        let mut triangle = ToneGenerator::new(Waveform::Triangle, 441.0, 100, 44_100);
+        square.set_playing(true);
+        triangle.set_playing(true);
+
+        let mut buf = [0i16; 100];
+        square.fill(&mut buf);
+        assert_eq!(buf[10], 10_000);
+        assert_eq!(buf[60], -10_000);
+
+        triangle.fill(&mut buf);
+        assert_eq!(buf[0], -10_000);
+        assert!(buf[25].abs() <= 1);
+        assert!((buf[50] - 10_000).abs() <= 1);
+    }
+
+    #[test]
+    fn wav_header_is_patched_on_finish() {
+        let mut wav = WavWriter::new(Cursor::new(Vec::new()), 44_100).unwrap();
+        wav.write_samples(&[1, -1, 2]).unwrap();
+        wav.finish().unwrap();
+
+        let bytes = wav.out.get_ref().clone();
+        assert_eq!(bytes.len(), 44 + 6);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(
+            u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+            42
+        );
+        assert_eq!(
+            u32::from_le_bytes([bytes[40], bytes[41], bytes[42], bytes[43]]),
+            6
+        );
+        assert_eq!(&bytes[44..46], &1i16.to_le_bytes());
+    }
+}
@@ -0,0 +1,56 @@
+use std::collections::BTreeSet;
+
+// Breakpoint addresses, set by the debug keys or by GDB. Execution resumed
+// at a breakpoint runs its instruction rather than stopping there again.
+#[derive(Default)]
+pub struct Breakpoints {
+    addrs: BTreeSet<u16>,
+    resumed: bool,
+}
+
+impl Breakpoints {
+    // Returns whether the breakpoint wasn't set already.
+    pub fn insert(&mut self, addr: u16) -> bool {
+        self.addrs.insert(addr)
+    }
+
+    pub fn remove(&mut self, addr: u16) -> bool {
+        self.addrs.remove(&addr)
+    }
+
+    pub fn contains(&self, addr: u16) -> bool {
+        self.addrs.contains(&addr)
+    }
+
+    // Lets the next instruction run even if it has a breakpoint.
+    pub fn resume(&mut self) {
+        self.resumed = true;
+    }
+
+    // Called before each instruction; whether execution stops at it.
+    pub fn hit(&mut self, pc: u16) -> bool {
+        let resumed = std::mem::replace(&mut self.resumed, false);
+        !resumed && self.contains(pc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Breakpoints;
+
+    #[test]
+    fn resumed_execution_passes_the_breakpoint_once() {
+        let mut breakpoints = Breakpoints::default();
+        assert!(breakpoints.insert(0x202));
+        assert!(!breakpoints.insert(0x202));
+
+        assert!(!breakpoints.hit(0x200));
+        assert!(breakpoints.hit(0x202));
+        breakpoints.resume();
+        assert!(!breakpoints.hit(0x202));
+        assert!(breakpoints.hit(0x202));
+
+        assert!(breakpoints.remove(0x202));
+        assert!(!breakpoints.hit(0x202));
+    }
+}
@@ -0,0 +1,295 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::interpreter::chip8::Chip8;
+use crate::interpreter::console::parse_value;
+use crate::interpreter::loader::decode_hex;
+use crate::interpreter::search::Location;
+use crate::interpreter::symbols::Symbols;
+use crate::InterpErr;
+
+const CHEAT_FILE_EXTENSION: &str = "cht";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Effect {
+    // forces a register or memory byte to the value every frame
+    Force(Location, u8),
+    // replaces ROM bytes at the address once, when enabled
+    Patch(u16, Vec<u8>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cheat {
+    pub name: String,
+    pub enabled: bool,
+    pub effects: Vec<Effect>,
+    // bytes replaced by enabled patches, restored when disabled
+    original: Vec<(u16, u8)>,
+}
+
+impl fmt::Display for Effect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Effect::Force(location, val) => write!(f, "{}={}", location, val),
+            Effect::Patch(addr, bytes) => {
+                write!(f, "patch {:03X}=", addr)?;
+                bytes.iter().try_for_each(|b| write!(f, "{:02X}", b))
+            }
+        }
+    }
+}
+
+// Cheats of a single ROM, stored in a file named by the hash of the ROM,
+// so they are found again whatever the ROM file is called. One cheat per line,
+// '+' or '-' marking whether it's enabled, its name and its effects:
+//
+//   + Infinite lives: 3F0=9
+//   - Start at level 5: V5=5, lives=3
+//   + Skip intro: patch 200=1240
+//
+// Lines starting with '#' are ignored.
+pub struct CheatFile {
+    path: PathBuf,
+    cheats: Vec<Cheat>,
+}
+
+impl CheatFile {
+    // Loads the cheats of the ROM from given directory; there are none if there's no file yet.
+    pub fn load(dir: &Path, rom: &[u8], symbols: &Symbols) -> Result<CheatFile, InterpErr> {
+        let path = dir.join(format!("{:08X}.{}", crc32(rom), CHEAT_FILE_EXTENSION));
+        let cheats = match std::fs::read_to_string(&path) {
+            Ok(content) => {
+                parse_cheats(&content, symbols).map_err(|e| format!("{}: {}", path.display(), e))?
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(CheatFile { path, cheats })
+    }
+
+    pub fn save(&self) -> Result<(), InterpErr> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&self.path, format_cheats(&self.cheats))?;
+        Ok(())
+    }
+
+    pub fn cheats(&self) -> &[Cheat] {
+        &self.cheats
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn add(&mut self, cheat: Cheat, machine: &mut Chip8) {
+        self.cheats.push(cheat);
+        let last = self.cheats.len() - 1;
+        if self.cheats[last].enabled {
+            self.cheats[last].patch(machine);
+        }
+    }
+
+    // Toggles the n-th cheat; returns its state, or None if there's no such cheat.
+    pub fn toggle(&mut self, n: usize, machine: &mut Chip8) -> Option<bool> {
+        let cheat = self.cheats.get_mut(n)?;
+        cheat.enabled = !cheat.enabled;
+        if cheat.enabled {
+            cheat.patch(machine);
+        } else {
+            cheat.unpatch(machine);
+        }
+        Some(cheat.enabled)
+    }
+
+    // Applies the patches of the enabled cheats; called once the ROM is loaded.
+    pub fn patch(&mut self, machine: &mut Chip8) {
+        for cheat in self.cheats.iter_mut().filter(|c| c.enabled) {
+            cheat.patch(machine);
+        }
+    }
+
+    // Forces the values of the enabled cheats; called every frame.
+    pub fn apply(&self, machine: &mut Chip8) {
+        for cheat in self.cheats.iter().filter(|c| c.enabled) {
+            for effect in &cheat.effects {
+                if let Effect::Force(location, val) = effect {
+                    location.write(machine, *val);
+                }
+            }
+        }
+    }
+}
+
+impl Cheat {
+    fn patch(&mut self, machine: &mut Chip8) {
+        for effect in &self.effects {
+            if let Effect::Patch(addr, bytes) = effect {
+                for (offset, &b) in bytes.iter().enumerate() {
+                    let a = addr.wrapping_add(offset as u16);
+                    let memory = machine.memory();
+                    self.original.push((a, memory[a as usize % memory.len()]));
+                    machine.poke(a, b);
+                }
+            }
+        }
+    }
+
+    fn unpatch(&mut self, machine: &mut Chip8) {
+        for (addr, b) in self.original.drain(..).rev() {
+            machine.poke(addr, b);
+        }
+    }
+}
+
+// Parses a cheat definition, as found in the cheat file without the leading '+' / '-'.
+pub fn parse_cheat(s: &str, enabled: bool, symbols: &Symbols) -> Result<Cheat, String> {
+    let (name, effects) = s
+        .split_once(':')
+        .ok_or_else(|| format!("expected 'NAME: EFFECTS', got '{}'", s))?;
+
+    let effects = effects
+        .split(',')
+        .map(|e| parse_effect(e.trim(), symbols))
+        .collect::<Result<Vec<Effect>, String>>()?;
+
+    Ok(Cheat {
+        name: name.trim().to_string(),
+        enabled,
+        effects,
+        original: Vec::new(),
+    })
+}
+
+fn parse_effect(s: &str, symbols: &Symbols) -> Result<Effect, String> {
+    let (target, val) = s.split_once('=').ok_or_else(|| {
+        format!(
+            "expected 'LOCATION=VALUE' or 'patch ADDRESS=BYTES', got '{}'",
+            s
+        )
+    })?;
+    let (target, val) = (target.trim(), val.trim());
+
+    if let Some(addr) = target.strip_prefix("patch ") {
+        let addr = symbols
+            .resolve(addr.trim())
+            .ok_or_else(|| format!("unknown address '{}'", addr.trim()))?;
+        return match decode_hex(val) {
+            Some(bytes) if !bytes.is_empty() => Ok(Effect::Patch(addr, bytes)),
+            _ => Err(format!("invalid patch bytes '{}'", val)),
+        };
+    }
+
+    let location =
+        Location::parse(target, symbols).ok_or_else(|| format!("unknown location '{}'", target))?;
+    Ok(Effect::Force(location, parse_value(val)?))
+}
+
+fn parse_cheats(content: &str, symbols: &Symbols) -> Result<Vec<Cheat>, String> {
+    content
+        .lines()
+        .enumerate()
+        .map(|(n, l)| (n, l.trim()))
+        .filter(|(_, l)| !l.is_empty() && !l.starts_with('#'))
+        .map(|(n, line)| {
+            let enabled = match line.chars().next() {
+                Some('+') => true,
+                Some('-') => false,
+                _ => return Err(format!("line {}: expected '+' or '-' at the start", n + 1)),
+            };
+            parse_cheat(&line[1..], enabled, symbols).map_err(|e| format!("line {}: {}", n + 1, e))
+        })
+        .collect()
+}
+
+fn format_cheats(cheats: &[Cheat]) -> String {
+    cheats
+        .iter()
+        .map(|c| {
+            let effects: Vec<String> = c.effects.iter().map(|e| e.to_string()).collect();
+            let state = if c.enabled { '+' } else { '-' };
+            format!("{} {}: {}\n", state, c.name, effects.join(", "))
+        })
+        .collect()
+}
+
+// CRC-32 (as used by zip and PNG) of the ROM, identifying it in the cheat file name.
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &b| {
+        (0..8).fold(crc ^ b as u32, |c, _| {
+            if c & 1 == 1 {
+                (c >> 1) ^ 0xEDB8_8320
+            } else {
+                c >> 1
+            }
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_matches_reference_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn cheat_files_are_parsed_and_formatted() {
+        let symbols = Symbols::parse("lives 3F0").unwrap();
+        let content = "# cheats\n\
+                       + Infinite lives: lives=9\n\
+                       - Level 5: V5=5, 2A0=0x10\n\
+                       + Skip intro: patch 200=1240\n";
+
+        let cheats = parse_cheats(content, &symbols).unwrap();
+        assert_eq!(cheats.len(), 3);
+        assert_eq!(
+            cheats[0].effects,
+            [Effect::Force(Location::Memory(0x3F0), 9)]
+        );
+        assert!(!cheats[1].enabled);
+        assert_eq!(
+            cheats[1].effects[0],
+            Effect::Force(Location::Register(5), 5)
+        );
+        assert_eq!(cheats[2].effects, [Effect::Patch(0x200, vec![0x12, 0x40])]);
+
+        assert_eq!(
+            format_cheats(&cheats),
+            "+ Infinite lives: 3F0=9\n\
+             - Level 5: V5=5, 2A0=16\n\
+             + Skip intro: patch 200=1240\n"
+        );
+
+        assert!(parse_cheats("Infinite lives: 3F0=9", &symbols).is_err());
+        assert!(parse_cheats("+ Infinite lives", &symbols).is_err());
+        assert!(parse_cheats("+ Broken patch: patch 200=124", &symbols).is_err());
+    }
+
+    #[test]
+    fn cheats_are_applied_and_toggled() {
+        let symbols = Symbols::default();
+        let mut machine = Chip8::new(false);
+        machine.poke(0x200, 0xAA);
+
+        let mut file = CheatFile {
+            path: PathBuf::new(),
+            cheats: parse_cheats("+ Lives: 3F0=9\n+ Patch: patch 200=12\n", &symbols).unwrap(),
+        };
+        file.patch(&mut machine);
+        file.apply(&mut machine);
+        assert_eq!(machine.memory()[0x3F0], 9);
+        assert_eq!(machine.memory()[0x200], 0x12);
+
+        assert_eq!(file.toggle(1, &mut machine), Some(false));
+        assert_eq!(machine.memory()[0x200], 0xAA);
+        assert_eq!(file.toggle(1, &mut machine), Some(true));
+        assert_eq!(machine.memory()[0x200], 0x12);
+        assert_eq!(file.toggle(2, &mut machine), None);
+    }
+}
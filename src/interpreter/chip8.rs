@@ -1,17 +1,45 @@
 use core::fmt;
+use std::collections::BTreeSet;
 
 use num_traits::int::PrimInt;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 
+mod disassembler;
+mod history;
 mod instruction;
+mod jit;
 mod operations;
 mod program_counter;
+mod timing;
 
-use crate::interpreter::config::FONT;
+use crate::interpreter::font::{Font, FONT_SIZE, SMALL_FONT_SIZE};
+use crate::interpreter::loader;
 use crate::interpreter::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use crate::InterpErr;
 use instruction::Instruction;
-use operations::MAIN_TABLE as OP_TABLE;
+use operations::{decode, Op};
 use program_counter::ProgramCounter;
 
+pub use disassembler::disassemble;
+pub use history::History;
+pub use jit::Jit;
+pub use timing::VipFrames;
+
+// Whether the interpreter implements the opcode; unknown ones are skipped with a warning.
+pub fn is_known_opcode(opcode: u16) -> bool {
+    operations::is_known(Instruction::new_from_bytes(
+        (opcode >> 8) as u8,
+        opcode as u8,
+    ))
+}
+
+// Programs are loaded at and start executing from this address by default.
+pub const PROGRAM_START: u16 = 0x200;
+
+// The font is loaded to the interpreter area, by default at this address.
+pub const FONT_ADDR: u16 = 0x050;
+
 pub struct Chip8 {
     memory: [u8; 4096],
     pc: ProgramCounter,
@@ -23,6 +51,42 @@ pub struct Chip8 {
     pub sound_timer: u8,
     pub input: Vec<u8>,
     c48_mode: bool,
+    // FX1E sets VF when I leaves the addressable memory (Amiga interpreter)
+    fx1e_overflow: bool,
+    warnings: Vec<String>,
+    // addresses of the unknown instructions warned about, each only once
+    warned: BTreeSet<u16>,
+    memory_writes: Vec<MemoryWrite>,
+    // addresses and previous values of memory written from outside of the
+    // program since the last instruction, to be reverted with it
+    pokes: Vec<(u16, u8)>,
+    // instructions decoded at each address, so each is decoded only once
+    // unless the memory holding it is written to (self-modifying code)
+    decoded: Box<[Option<(Op, Instruction)>]>,
+    decode_cache: bool,
+    // code pages written to since the JIT last checked, as a bitmask
+    written_pages: u64,
+    rng: StdRng,
+    font_addr: u16,
+    load_addr: u16,
+}
+
+// A memory write done by an instruction.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MemoryWrite {
+    pub addr: u16,
+    pub value: u8,
+    pub previous: u8,
+}
+
+// Snapshot of the register state, used for inspecting the machine.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Registers {
+    pub pc: u16,
+    pub v: [u8; 16],
+    pub i: u16,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
 }
 
 pub struct Screen {
@@ -37,7 +101,7 @@ enum Reg {
 
 impl Screen {
     fn new() -> Screen {
-        return Screen{
+        Screen {
             display: [false; DISPLAY_WIDTH * DISPLAY_HEIGHT],
             refresh: false,
         }
@@ -59,9 +123,9 @@ impl Screen {
 
 impl Chip8 {
     pub fn new(c48_mode: bool) -> Chip8 {
-        return Chip8 {
+        Chip8 {
             memory: [0; 4096],
-            pc: ProgramCounter::new_with_value(0x200), // program starts at 0x200
+            pc: ProgramCounter::new_with_value(PROGRAM_START),
             v: [0; 16],
             i: 0,
             stack: Vec::<u16>::new(),
@@ -70,54 +134,260 @@ impl Chip8 {
             sound_timer: 0,
             input: Vec::<u8>::new(),
             c48_mode,
+            fx1e_overflow: false,
+            warnings: Vec::new(),
+            warned: BTreeSet::new(),
+            memory_writes: Vec::new(),
+            pokes: Vec::new(),
+            decoded: vec![None; 4096].into_boxed_slice(),
+            decode_cache: true,
+            written_pages: 0,
+            rng: StdRng::from_entropy(),
+            font_addr: FONT_ADDR,
+            load_addr: PROGRAM_START,
         }
-        .load_font();
+        .with_font(&Font::default(), FONT_ADDR)
     }
 
-    pub fn load_program_to_memory(mut self, path: &str) -> Result<Chip8, std::io::Error> {
-        let f = std::fs::read(path)?;
-        let mut addr = 0x200; // program starts at 0x200
+    // Reads the ROM (see `loader::read_rom`) and loads it.
+    pub fn load_program_to_memory(self, path: &str) -> Result<Chip8, InterpErr> {
+        let rom = loader::read_rom(path, None)?;
+        Ok(self.load_rom(&rom)?)
+    }
 
-        for b in f {
-            self.memory[addr] = b;
-            addr += 1;
+    // Loads the program, unless it's too large to fit in memory.
+    pub fn load_rom(self, rom: &[u8]) -> Result<Chip8, String> {
+        let max_len = self.memory.len() - self.load_addr as usize;
+        if rom.len() > max_len {
+            return Err(format!(
+                "program is {} bytes, at most {} fit in memory",
+                rom.len(),
+                max_len
+            ));
         }
 
-        Ok(self)
+        Ok(self.load_program(rom))
     }
 
-    fn load_font(mut self) -> Chip8 {
-        let mut addr = 0x050;
+    // Loads the program at the load address; bytes which don't fit in memory are dropped.
+    pub fn load_program(mut self, program: &[u8]) -> Chip8 {
+        let memory = &mut self.memory[self.load_addr as usize..];
+        let len = program.len().min(memory.len());
+        memory[..len].copy_from_slice(&program[..len]);
+        self.decoded.fill(None);
+        self.written_pages = u64::MAX;
+
+        self
+    }
 
-        for &f in FONT.iter() {
-            self.memory[addr] = f;
-            addr += 1;
+    // Loads additional data (e.g. a test fixture) at given address,
+    // unless it's too large to fit in memory.
+    pub fn load_blob(mut self, addr: u16, blob: &[u8]) -> Result<Chip8, String> {
+        let addr = addr as usize;
+        if addr + blob.len() > self.memory.len() {
+            return Err(format!(
+                "{} bytes at {:03X} don't fit in memory",
+                blob.len(),
+                addr
+            ));
         }
 
+        self.memory[addr..addr + blob.len()].copy_from_slice(blob);
+        self.decoded.fill(None);
+        self.written_pages = u64::MAX;
+        Ok(self)
+    }
+
+    // Sets the address the program is loaded at and starts executing from
+    // (e.g. 0x600 for ETI-660 programs); to be done before loading it.
+    pub fn with_load_address(mut self, addr: u16) -> Chip8 {
+        self.load_addr = addr;
+        self.pc.set_to(addr);
+        self
+    }
+
+    // Starts executing from given address instead of the load address.
+    pub fn with_entry(mut self, addr: u16) -> Chip8 {
+        self.pc.set_to(addr);
+        self
+    }
+
+    pub fn load_addr(&self) -> u16 {
+        self.load_addr
+    }
+
+    // Enables / disables caching of decoded instructions; enabled by default.
+    pub fn with_decode_cache(mut self, enabled: bool) -> Chip8 {
+        self.decode_cache = enabled;
+        self.decoded.fill(None);
         self
     }
 
+    // Enables / disables setting VF on FX1E overflowing I; disabled by default,
+    // as on the COSMAC VIP.
+    pub fn with_fx1e_overflow(mut self, enabled: bool) -> Chip8 {
+        self.fx1e_overflow = enabled;
+        self
+    }
+
+    // Seeds the random number generator of CXKK, making runs reproducible.
+    pub fn with_rng_seed(mut self, seed: u64) -> Chip8 {
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    // Loads the font (replacing the current one) at given address in the
+    // interpreter area; to be done before loading the program.
+    pub fn with_font(mut self, font: &Font, addr: u16) -> Chip8 {
+        let old = self.font_addr as usize;
+        self.memory[old..old + FONT_SIZE].fill(0);
+
+        let addr = addr as usize;
+        self.memory[addr..addr + SMALL_FONT_SIZE].copy_from_slice(&font.small);
+        self.memory[addr + SMALL_FONT_SIZE..addr + FONT_SIZE].copy_from_slice(&font.big);
+        self.font_addr = addr as u16;
+        self.decoded.fill(None);
+        self.written_pages = u64::MAX;
+
+        self
+    }
+
+    // Address of the small characters, followed by the large digits.
+    pub fn font_addr(&self) -> u16 {
+        self.font_addr
+    }
+
     pub fn run_instruction(&mut self, is_debug: bool) {
-        let instr = self.fetch();
+        self.memory_writes.clear();
+        self.pokes.clear();
+        let (op, instr) = self.fetch();
         if is_debug {
             println!("Executed instr: Ox{:X}", instr.to_raw_instr())
         }
 
-        self.decode_and_execute(instr)
+        op(self, instr)
     }
 
+    // Counts down the delay and sound timers; called at 60 Hz.
+    pub fn tick_timers(&mut self) {
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+    }
+
+    pub fn registers(&self) -> Registers {
+        Registers {
+            pc: self.pc.value(),
+            v: self.v,
+            i: self.i,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+        }
+    }
+
+    pub fn set_registers(&mut self, regs: &Registers) {
+        self.pc.set_to(regs.pc);
+        self.v = regs.v;
+        self.i = regs.i;
+        self.delay_timer = regs.delay_timer;
+        self.sound_timer = regs.sound_timer;
+    }
+
+    // Raw opcode of the instruction to be executed next.
+    pub fn next_opcode(&self) -> u16 {
+        self.peek().to_raw_instr()
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    // Writes to memory from outside of the program (e.g. by debugger);
+    // addresses beyond the memory size wrap around.
+    pub fn poke(&mut self, addr: u16, val: u8) {
+        let previous = self.memory[addr as usize % self.memory.len()];
+        self.pokes.push((addr, previous));
+        self.store(addr as usize, val);
+    }
+
+    // Memory writes done by the last executed instruction.
+    pub fn memory_writes(&self) -> &[MemoryWrite] {
+        &self.memory_writes
+    }
+
+    // Return addresses of the subroutines called, the innermost last.
+    pub fn stack(&self) -> &[u16] {
+        &self.stack
+    }
+
+    // Warnings raised during execution since the last call.
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    #[cfg(test)]
     fn decode_and_execute(&mut self, instr: Instruction) {
-        OP_TABLE[instr.first_nibble() as usize](self, instr);
+        decode(instr)(self, instr);
     }
 
-    fn fetch(&mut self) -> Instruction {
+    fn fetch(&mut self) -> (Op, Instruction) {
+        let decoded = self.decode_next();
+        self.pc.increment();
+
+        decoded
+    }
+
+    fn decode_next(&mut self) -> (Op, Instruction) {
+        if !self.decode_cache {
+            let instr = self.peek();
+            return (decode(instr), instr);
+        }
+
+        let addr = self.pc.value() as usize % self.memory.len();
+        match self.decoded[addr] {
+            Some(decoded) => decoded,
+            None => {
+                let instr = self.peek();
+                let decoded = (decode(instr), instr);
+                self.decoded[addr] = Some(decoded);
+                decoded
+            }
+        }
+    }
+
+    // read 2 successive bytes from memory, without moving the program counter.
+    fn peek(&self) -> Instruction {
         let addr = self.pc.value() as usize;
+        Instruction::new_from_bytes(self.read_memory(addr), self.read_memory(addr + 1))
+    }
 
-        // read 2 successive bytes from memory.
-        let instr = Instruction::new_from_bytes(self.memory[addr], self.memory[addr + 1]);
-        self.pc.increment();
+    // Memory accesses done by instructions wrap around at the memory size,
+    // as addresses computed from I or the program counter may point beyond it.
+    fn read_memory(&self, addr: usize) -> u8 {
+        self.memory[addr % self.memory.len()]
+    }
+
+    // All memory writes done by instructions go through here, so they can be tracked.
+    fn write_memory(&mut self, addr: usize, val: u8) {
+        let addr = addr % self.memory.len();
+        self.memory_writes.push(MemoryWrite {
+            addr: addr as u16,
+            value: val,
+            previous: self.memory[addr],
+        });
+        self.store(addr, val);
+    }
 
-        return instr;
+    // Writes to memory without tracking; every write to memory
+    // after loading the program must go through here, so that
+    // the cached instructions and compiled blocks containing the byte
+    // are decoded again.
+    fn store(&mut self, addr: usize, val: u8) {
+        let len = self.memory.len();
+        let addr = addr % len;
+        self.memory[addr] = val;
+        self.decoded[addr] = None;
+        self.decoded[(addr + len - 1) % len] = None;
+        self.written_pages |= jit::page_mask(addr as u16);
     }
 
     fn set_reg_to<T: PrimInt>(&mut self, r: Reg, val: T) {
@@ -152,28 +422,34 @@ impl Chip8 {
 
     fn draw(&mut self, instr: Instruction) {
         let (x, y) = instr.x_y();
-        let mut display_x = (self.v[x] % 64) as usize;
-        let mut display_y = (self.v[y] % 32) as usize;
+        let start_x = (self.v[x] % 64) as usize;
+        let start_y = (self.v[y] % 32) as usize;
 
         let bytes_to_read = instr.last_nibble();
         let addr = self.i as usize;
         self.set_reg_to(Reg::V(15), 0u8);
 
         for i in 0..bytes_to_read as usize {
-            let mut sprite_byte = self.memory[addr + i];
+            // sprites are clipped at the bottom edge.
+            let display_y = start_y + i;
+            if display_y >= DISPLAY_HEIGHT {
+                break;
+            }
 
-            // iterate over all bits of current sprite byte.
-            for _ in 0..8 {
-                let current_pos = (display_y * DISPLAY_WIDTH) + display_x;
+            let sprite_byte = self.read_memory(addr + i);
 
-                // break if out of bounds.
-                if current_pos >= 2048 {
+            // iterate over all bits of current sprite byte.
+            for bit in 0..8 {
+                // ... and at the right edge.
+                let display_x = start_x + bit;
+                if display_x >= DISPLAY_WIDTH {
                     break;
                 }
 
                 // if sprite bit is set, flip the display point;
                 // if both are on, set flag register.
-                if sprite_byte & 0x80 == 0x80 {
+                if sprite_byte & (0x80 >> bit) != 0 {
+                    let current_pos = display_y * DISPLAY_WIDTH + display_x;
                     if self.screen.display[current_pos] {
                         self.screen.display[current_pos] = false;
                         self.set_reg_to(Reg::V(15), 1u8);
@@ -181,22 +457,20 @@ impl Chip8 {
                         self.screen.display[current_pos] = true;
                     }
                 }
-                sprite_byte <<= 1;
-                display_x += 1;
             }
-
-            // reset the X position.
-            display_x = display_x.checked_sub(8).unwrap_or(0);
-            display_y += 1;
         }
     }
 
     fn handle_unknown_instr(&mut self, instr: Instruction) {
-        println!(
-            "Warning: Unknown instruction 0x{:X} at program counter {}; skipping",
+        // a program looping over it would otherwise pile up warnings
+        if !self.warned.insert(self.pc.value()) {
+            return;
+        }
+        self.warnings.push(format!(
+            "Unknown instruction 0x{:X} at program counter {}; skipping",
             instr.to_raw_instr(),
             self.pc.value()
-        )
+        ))
     }
 }
 
@@ -273,13 +547,97 @@ mod tests {
         machine.memory[0x666] = 0b01010101;
 
         machine.decode_and_execute(Instruction::new_from_bytes(0xD0, 0x11));
-        assert_eq!(machine.screen.display[65], false);
-        assert_eq!(machine.screen.display[66], true);
-        assert_eq!(machine.screen.display[67], false);
-        assert_eq!(machine.screen.display[68], true);
-        assert_eq!(machine.screen.display[69], false);
-        assert_eq!(machine.screen.display[70], true);
-        assert_eq!(machine.screen.display[71], false);
-        assert_eq!(machine.screen.display[72], true);
+        assert!(!machine.screen.display[65]);
+        assert!(machine.screen.display[66]);
+        assert!(!machine.screen.display[67]);
+        assert!(machine.screen.display[68]);
+        assert!(!machine.screen.display[69]);
+        assert!(machine.screen.display[70]);
+        assert!(!machine.screen.display[71]);
+        assert!(machine.screen.display[72]);
+    }
+
+    #[test]
+    fn programs_and_blobs_are_loaded_at_given_addresses() {
+        let machine = Chip8::new(false)
+            .with_load_address(0x600)
+            .load_rom(&[0x12, 0x34])
+            .unwrap()
+            .load_blob(0x300, &[0xAB])
+            .unwrap();
+        assert_eq!(machine.pc.value(), 0x600);
+        assert_eq!(machine.memory[0x600..0x602], [0x12, 0x34]);
+        assert_eq!(machine.memory[0x300], 0xAB);
+
+        let machine = machine.with_entry(0x602);
+        assert_eq!(machine.pc.value(), 0x602);
+        assert!(Chip8::new(false).load_blob(0xFFF, &[0, 0]).is_err());
+    }
+
+    #[test]
+    fn memory_accesses_beyond_memory_size_wrap_around() {
+        let mut machine = Chip8::new(false);
+        machine.memory[0x000] = 0xFF;
+
+        // FX1E moves I past the end of memory; drawing reads the sprite from its start
+        machine.decode_and_execute(Instruction::new_from_bytes(0xAF, 0xFF));
+        machine.decode_and_execute(Instruction::new_from_bytes(0x60, 0x01));
+        machine.decode_and_execute(Instruction::new_from_bytes(0xF0, 0x1E));
+        machine.decode_and_execute(Instruction::new_from_bytes(0xD1, 0x1F));
+        assert!(machine.screen.display[..8].iter().all(|&p| p));
+
+        machine.decode_and_execute(Instruction::new_from_bytes(0xFF, 0x55));
+        assert_eq!(machine.memory[0x00F], machine.v[15]);
+
+        machine.pc.set_to(0x10FE);
+        machine.run_instruction(false);
+    }
+
+    #[test]
+    fn sprites_are_clipped_at_the_edges() {
+        let mut machine = Chip8::new(false);
+        machine.memory[0x300..0x302].copy_from_slice(&[0xFF, 0xFF]);
+        machine.i = 0x300;
+        machine.v[0] = 60;
+        machine.v[1] = 31;
+
+        machine.decode_and_execute(Instruction::new_from_bytes(0xD0, 0x12));
+        let lit = machine.screen.display.iter().filter(|&&p| p).count();
+        assert_eq!(lit, 4);
+        assert!(machine.screen.display[31 * 64 + 63]);
+    }
+
+    #[test]
+    fn overwritten_instructions_are_decoded_again() {
+        // LD V0, 01; LD I, 201; LD [I], V0; JP 200
+        let program = [0x60, 0x01, 0xA2, 0x01, 0xF0, 0x55, 0x12, 0x00];
+        let mut machine = Chip8::new(false).load_program(&program);
+        for _ in 0..4 {
+            machine.run_instruction(false);
+        }
+
+        // written from outside: LD V0, 07
+        machine.poke(0x201, 0x07);
+        machine.run_instruction(false);
+        assert_eq!(machine.v[0], 0x07);
+
+        // written by the program itself: LD V0, 09
+        machine.run_instruction(false);
+        machine.v[0] = 0x09;
+        machine.run_instruction(false);
+        machine.run_instruction(false);
+        machine.v[0] = 0;
+        machine.run_instruction(false);
+        assert_eq!(machine.v[0], 0x09);
+    }
+
+    #[test]
+    fn unknown_instructions_are_warned_about_once() {
+        // an unknown instruction, then JP 200
+        let mut machine = Chip8::new(false).load_program(&[0xF0, 0xFF, 0x12, 0x00]);
+        for _ in 0..1000 {
+            machine.run_instruction(false);
+        }
+        assert_eq!(machine.take_warnings().len(), 1);
     }
 }
@@ -0,0 +1,114 @@
+use super::instruction::Instruction;
+
+// Translates a raw instruction to a mnemonic, using the widespread
+// syntax from Cowgod's CHIP-8 technical reference. Address operands
+// having a label are shown by its name.
+pub fn disassemble<'a>(raw: u16, label: impl Fn(u16) -> Option<&'a str>) -> String {
+    let instr = Instruction::new_from_bytes((raw >> 8) as u8, raw as u8);
+    let (x, y) = instr.x_y();
+    let (nnn, kk, n) = (instr.nnn(), instr.kk(), instr.last_nibble());
+    let nnn = match label(nnn) {
+        Some(name) => name.to_string(),
+        None => format!("{:03X}", nnn),
+    };
+
+    match instr.first_nibble() {
+        0x0 => match raw {
+            0x00E0 => "CLS".to_string(),
+            0x00EE => "RET".to_string(),
+            _ => format!("SYS {}", nnn),
+        },
+        0x1 => format!("JP {}", nnn),
+        0x2 => format!("CALL {}", nnn),
+        0x3 => format!("SE V{:X}, {:02X}", x, kk),
+        0x4 => format!("SNE V{:X}, {:02X}", x, kk),
+        0x5 if n == 0 => format!("SE V{:X}, V{:X}", x, y),
+        0x6 => format!("LD V{:X}, {:02X}", x, kk),
+        0x7 => format!("ADD V{:X}, {:02X}", x, kk),
+        0x8 => {
+            let op = match n {
+                0x0 => "LD",
+                0x1 => "OR",
+                0x2 => "AND",
+                0x3 => "XOR",
+                0x4 => "ADD",
+                0x5 => "SUB",
+                0x6 => "SHR",
+                0x7 => "SUBN",
+                0xE => "SHL",
+                _ => return data(raw),
+            };
+            format!("{} V{:X}, V{:X}", op, x, y)
+        }
+        0x9 if n == 0 => format!("SNE V{:X}, V{:X}", x, y),
+        0xA => format!("LD I, {}", nnn),
+        0xB => format!("JP V0, {}", nnn),
+        0xC => format!("RND V{:X}, {:02X}", x, kk),
+        0xD => format!("DRW V{:X}, V{:X}, {:X}", x, y, n),
+        0xE => match kk {
+            0x9E => format!("SKP V{:X}", x),
+            0xA1 => format!("SKNP V{:X}", x),
+            _ => data(raw),
+        },
+        0xF => match kk {
+            0x07 => format!("LD V{:X}, DT", x),
+            0x0A => format!("LD V{:X}, K", x),
+            0x15 => format!("LD DT, V{:X}", x),
+            0x18 => format!("LD ST, V{:X}", x),
+            0x1E => format!("ADD I, V{:X}", x),
+            0x29 => format!("LD F, V{:X}", x),
+            0x30 => format!("LD HF, V{:X}", x),
+            0x33 => format!("LD B, V{:X}", x),
+            0x55 => format!("LD [I], V{:X}", x),
+            0x65 => format!("LD V{:X}, [I]", x),
+            _ => data(raw),
+        },
+        _ => data(raw),
+    }
+}
+
+// anything not decodable is shown as data
+fn data(raw: u16) -> String {
+    format!("DW {:04X}", raw)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::disassemble;
+
+    fn no_labels(_: u16) -> Option<&'static str> {
+        None
+    }
+
+    #[test]
+    fn instructions_are_disassembled() {
+        assert_eq!(disassemble(0x00E0, no_labels), "CLS");
+        assert_eq!(disassemble(0x1234, no_labels), "JP 234");
+        assert_eq!(disassemble(0x3A0F, no_labels), "SE VA, 0F");
+        assert_eq!(disassemble(0x8AB4, no_labels), "ADD VA, VB");
+        assert_eq!(disassemble(0xD125, no_labels), "DRW V1, V2, 5");
+        assert_eq!(disassemble(0xF355, no_labels), "LD [I], V3");
+    }
+
+    #[test]
+    fn address_operands_are_shown_by_label() {
+        let label = |addr| {
+            if addr == 0x2A0 {
+                Some("draw_player")
+            } else {
+                None
+            }
+        };
+
+        assert_eq!(disassemble(0x22A0, label), "CALL draw_player");
+        assert_eq!(disassemble(0xA2A0, label), "LD I, draw_player");
+        assert_eq!(disassemble(0x12A2, label), "JP 2A2");
+    }
+
+    #[test]
+    fn invalid_instructions_are_disassembled_as_data() {
+        assert_eq!(disassemble(0x5121, no_labels), "DW 5121");
+        assert_eq!(disassemble(0x8AB9, no_labels), "DW 8AB9");
+        assert_eq!(disassemble(0xE1FF, no_labels), "DW E1FF");
+    }
+}
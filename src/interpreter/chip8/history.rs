@@ -0,0 +1,185 @@
+use std::collections::VecDeque;
+
+use super::{Chip8, Registers};
+use crate::interpreter::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+// Everything needed to revert a single executed instruction.
+// The stack is only saved by instructions which change it, and
+// of the display only the pixels flipped are kept.
+struct Delta {
+    registers: Registers,
+    memory: Vec<(u16, u8)>,
+    stack: Option<Vec<u16>>,
+    flipped_pixels: Vec<u16>,
+}
+
+// Executed instructions which can be stepped back through,
+// up to given capacity; the oldest ones are forgotten first.
+pub struct History {
+    deltas: VecDeque<Delta>,
+    capacity: usize,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> History {
+        History {
+            deltas: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    fn push(&mut self, delta: Delta) {
+        if self.deltas.len() == self.capacity {
+            self.deltas.pop_front();
+        }
+        self.deltas.push_back(delta);
+    }
+}
+
+impl Chip8 {
+    // Executes the next instruction, recording how to revert it.
+    pub fn run_instruction_recorded(&mut self, is_debug: bool, history: &mut History) {
+        self.record_pokes(history);
+        let raw = self.next_opcode();
+        let registers = self.registers();
+        let stack = match raw {
+            0x00EE | 0x2000..=0x2FFF => Some(self.stack.clone()),
+            _ => None,
+        };
+        let display = match raw {
+            0x00E0 | 0xD000..=0xDFFF => Some(self.screen.display),
+            _ => None,
+        };
+
+        self.run_instruction(is_debug);
+
+        let flipped_pixels = match display {
+            Some(before) => (0..DISPLAY_WIDTH * DISPLAY_HEIGHT)
+                .filter(|&p| before[p] != self.screen.display[p])
+                .map(|p| p as u16)
+                .collect(),
+            None => Vec::new(),
+        };
+
+        history.push(Delta {
+            registers,
+            memory: self
+                .memory_writes
+                .iter()
+                .map(|w| (w.addr, w.previous))
+                .collect(),
+            stack,
+            flipped_pixels,
+        });
+    }
+
+    // Memory written from outside of the program (by the debugger, cheats
+    // or pinned values) is reverted along with the last instruction.
+    fn record_pokes(&mut self, history: &mut History) {
+        let pokes = std::mem::take(&mut self.pokes);
+        if let Some(delta) = history.deltas.back_mut() {
+            delta.memory.extend(pokes);
+        }
+    }
+
+    // Reverts the last recorded instruction; returns false if there is none.
+    pub fn step_back(&mut self, history: &mut History) -> bool {
+        self.record_pokes(history);
+        let delta = match history.deltas.pop_back() {
+            Some(d) => d,
+            None => return false,
+        };
+
+        self.set_registers(&delta.registers);
+        // reverted in reverse order, in case an address was written more than once
+        for &(addr, val) in delta.memory.iter().rev() {
+            self.store(addr as usize, val);
+        }
+        if let Some(stack) = delta.stack {
+            self.stack = stack;
+        }
+        for &p in &delta.flipped_pixels {
+            self.screen.display[p as usize] ^= true;
+            self.screen.refresh = true;
+        }
+
+        self.memory_writes.clear();
+        self.pokes.clear();
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::History;
+    use crate::interpreter::chip8::Chip8;
+
+    fn machine_with_program(program: &[u8]) -> Chip8 {
+        let mut machine = Chip8::new(false);
+        for (offset, &b) in program.iter().enumerate() {
+            machine.poke(0x200 + offset as u16, b);
+        }
+        machine
+    }
+
+    #[test]
+    fn stepping_back_reverts_registers_memory_stack_and_screen() {
+        let mut machine = machine_with_program(&[
+            0x60, 0x7B, // LD V0, 7B
+            0xA3, 0x00, // LD I, 300
+            0xF0, 0x33, // LD B, V0
+            0xD1, 0x15, // DRW V1, V1, 5
+            0x22, 0x00, // CALL 200
+        ]);
+        let initial = machine.registers();
+        let mut history = History::new(16);
+
+        for _ in 0..5 {
+            machine.run_instruction_recorded(false, &mut history);
+        }
+        assert_eq!(machine.stack(), &[0x20A]);
+        assert_eq!(&machine.memory()[0x300..0x303], &[1, 2, 3]);
+        assert!(machine.screen.display.iter().any(|&p| p));
+
+        for _ in 0..5 {
+            assert!(machine.step_back(&mut history));
+        }
+        assert!(!machine.step_back(&mut history));
+
+        assert_eq!(machine.registers(), initial);
+        assert!(machine.stack().is_empty());
+        assert_eq!(&machine.memory()[0x300..0x303], &[0, 0, 0]);
+        assert!(machine.screen.display.iter().all(|&p| !p));
+    }
+
+    #[test]
+    fn pokes_are_reverted_with_the_last_instruction() {
+        let mut machine = machine_with_program(&[0x60, 0x01, 0x12, 0x00]); // LD V0, 01; JP 200
+        let mut history = History::new(16);
+
+        machine.run_instruction_recorded(false, &mut history);
+        machine.poke(0x300, 0xAA);
+        machine.poke(0x300, 0xBB);
+        machine.run_instruction_recorded(false, &mut history);
+        machine.poke(0x301, 0xCC);
+
+        assert!(machine.step_back(&mut history));
+        assert_eq!(&machine.memory()[0x300..0x302], &[0xBB, 0x00]);
+        assert!(machine.step_back(&mut history));
+        assert_eq!(&machine.memory()[0x300..0x302], &[0x00, 0x00]);
+    }
+
+    #[test]
+    fn oldest_instructions_are_forgotten() {
+        let mut machine = machine_with_program(&[0x70, 0x01, 0x12, 0x00]); // ADD V0, 01; JP 200
+        let mut history = History::new(2);
+
+        for _ in 0..4 {
+            machine.run_instruction_recorded(false, &mut history);
+        }
+        assert!(machine.step_back(&mut history));
+        assert!(machine.step_back(&mut history));
+        assert!(!machine.step_back(&mut history));
+        assert_eq!(machine.registers().v[0], 1);
+    }
+}
@@ -3,38 +3,38 @@ pub struct Instruction(u16);
 
 impl Instruction {
     pub fn new_from_bytes(b1: u8, b2: u8) -> Instruction {
-        return Instruction(u16::from_be_bytes([b1, b2]));
+        Instruction(u16::from_be_bytes([b1, b2]))
     }
 
     pub fn to_raw_instr(self) -> u16 {
-        return self.0;
+        self.0
     }
 
     pub fn first_nibble(self) -> u8 {
-        return ((self.0 & 0xF000) >> 12) as u8;
+        ((self.0 & 0xF000) >> 12) as u8
     }
 
     pub fn last_nibble(self) -> u8 {
-        return (self.0 & 0xF) as u8;
+        (self.0 & 0xF) as u8
     }
 
     pub fn x(self) -> usize {
-        return ((self.0 & 0x0F00) >> 8) as usize;
+        ((self.0 & 0x0F00) >> 8) as usize
     }
 
     pub fn x_y(self) -> (usize, usize) {
-        return (
+        (
             ((self.0 & 0x0F00) >> 8) as usize,
             ((self.0 & 0x0F0) >> 4) as usize,
-        );
+        )
     }
 
     pub fn nnn(self) -> u16 {
-        return self.0 & 0x0FFF;
+        self.0 & 0x0FFF
     }
 
     pub fn kk(self) -> u8 {
-        return (self.0 & 0x00FF) as u8;
+        (self.0 & 0x00FF) as u8
     }
 }
 
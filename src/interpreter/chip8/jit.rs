@@ -0,0 +1,235 @@
+use super::instruction::Instruction;
+use super::operations::{decode, reset_flag_after_logic, Op};
+use super::Chip8;
+
+// Longest block compiled, in instructions.
+const MAX_BLOCK_LEN: usize = 64;
+
+// Compiled blocks are invalidated by the code pages they're on,
+// when any byte of the page is written to.
+pub(super) const PAGE_SIZE: usize = 64;
+
+type Compiled = Box<dyn Fn(&mut Chip8)>;
+
+// A basic block: straight-line instructions, which don't touch the program
+// counter, compiled to closures with their operands bound, followed by
+// the instruction ending the block by changing the program counter.
+struct Block {
+    start: u16,
+    body: Vec<Compiled>,
+    // None if the block ends without a jump, e.g. after a memory write
+    end: Option<(Op, Instruction)>,
+    // code pages of the block's instructions, as a bitmask
+    pages: u64,
+}
+
+// Dynamic recompiler: runs the machine a basic block at a time, instead of
+// fetching and decoding every instruction. Results are the same as with
+// `Chip8::run_instruction`, but there's no per-instruction hook (debugging,
+// tracing, history, VIP timing), so it suits batch runs of ROMs.
+pub struct Jit {
+    // blocks by their start address
+    blocks: Vec<Option<Block>>,
+    // pages holding compiled blocks
+    code_pages: u64,
+}
+
+impl Default for Jit {
+    fn default() -> Jit {
+        Jit {
+            blocks: (0..4096).map(|_| None).collect(),
+            code_pages: 0,
+        }
+    }
+}
+
+impl Jit {
+    // Executes given number of instructions.
+    pub fn run(&mut self, machine: &mut Chip8, instructions: usize) {
+        machine.memory_writes.clear();
+        machine.pokes.clear();
+
+        let mut left = instructions;
+        while left > 0 {
+            left -= self.run_block(machine, left);
+        }
+    }
+
+    // Executes up to `max` instructions, at most up to the end of the block
+    // at program counter; returns the number of instructions executed.
+    fn run_block(&mut self, machine: &mut Chip8, max: usize) -> usize {
+        if machine.written_pages != 0 {
+            self.invalidate(std::mem::take(&mut machine.written_pages));
+        }
+
+        let start = machine.pc.value();
+        let index = start as usize % self.blocks.len();
+        if !matches!(&self.blocks[index], Some(b) if b.start == start) {
+            let block = compile(machine, start);
+            self.code_pages |= block.pages;
+            self.blocks[index] = Some(block);
+        }
+        let block = self.blocks[index].as_ref().unwrap();
+
+        let n = block.body.len().min(max);
+        for op in &block.body[..n] {
+            op(machine);
+        }
+
+        let next = start.wrapping_add(2 * n as u16);
+        match block.end {
+            Some((op, instr)) if n == block.body.len() && n < max => {
+                machine.pc.set_to(next.wrapping_add(2));
+                op(machine, instr);
+                n + 1
+            }
+            _ => {
+                machine.pc.set_to(next);
+                n
+            }
+        }
+    }
+
+    // Drops the blocks on the written pages.
+    fn invalidate(&mut self, written_pages: u64) {
+        if written_pages & self.code_pages == 0 {
+            return;
+        }
+
+        self.code_pages = 0;
+        for slot in self.blocks.iter_mut() {
+            match slot {
+                Some(b) if b.pages & written_pages != 0 => *slot = None,
+                Some(b) => self.code_pages |= b.pages,
+                None => {}
+            }
+        }
+    }
+}
+
+fn compile(machine: &Chip8, start: u16) -> Block {
+    let mut block = Block {
+        start,
+        body: Vec::new(),
+        end: None,
+        pages: 0,
+    };
+
+    let mut addr = start;
+    while block.body.len() < MAX_BLOCK_LEN {
+        let instr = Instruction::new_from_bytes(
+            machine.read_memory(addr as usize),
+            machine.read_memory(addr as usize + 1),
+        );
+        block.pages |= page_mask(addr) | page_mask(addr.wrapping_add(1));
+
+        if ends_block(instr) {
+            block.end = Some((decode(instr), instr));
+            break;
+        }
+        block.body.push(compile_instruction(instr));
+        // the write may change the instructions that follow
+        if writes_memory(instr) {
+            break;
+        }
+        addr = addr.wrapping_add(2);
+    }
+
+    block
+}
+
+pub(super) fn page_mask(addr: u16) -> u64 {
+    1 << (addr as usize % 4096 / PAGE_SIZE)
+}
+
+// Instructions reading or changing the program counter, including
+// the unknown ones (their warning shows the program counter).
+fn ends_block(instr: Instruction) -> bool {
+    match instr.first_nibble() {
+        0x1..=0x5 | 0x9 | 0xB | 0xE => true,
+        0x0 => instr.kk() != 0xE0,
+        0x8 => !matches!(instr.last_nibble(), 0x0..=0x7 | 0xE),
+        0xF => !matches!(
+            instr.kk(),
+            0x07 | 0x15 | 0x18 | 0x1E | 0x29 | 0x30 | 0x33 | 0x55 | 0x65
+        ),
+        _ => false,
+    }
+}
+
+fn writes_memory(instr: Instruction) -> bool {
+    instr.first_nibble() == 0xF && matches!(instr.kk(), 0x33 | 0x55)
+}
+
+// The most common instructions are compiled to closures doing just their
+// work; the rest call their operation with the instruction bound.
+fn compile_instruction(instr: Instruction) -> Compiled {
+    let (x, y) = instr.x_y();
+    let (kk, nnn) = (instr.kk(), instr.nnn());
+
+    match (instr.first_nibble(), instr.last_nibble()) {
+        (0x6, _) => Box::new(move |c| c.v[x] = kk),
+        (0x7, _) => Box::new(move |c| c.v[x] = c.v[x].wrapping_add(kk)),
+        (0x8, 0x0) => Box::new(move |c| c.v[x] = c.v[y]),
+        (0x8, 0x1) => Box::new(move |c| {
+            c.v[x] |= c.v[y];
+            reset_flag_after_logic(c);
+        }),
+        (0x8, 0x2) => Box::new(move |c| {
+            c.v[x] &= c.v[y];
+            reset_flag_after_logic(c);
+        }),
+        (0x8, 0x3) => Box::new(move |c| {
+            c.v[x] ^= c.v[y];
+            reset_flag_after_logic(c);
+        }),
+        (0xA, _) => Box::new(move |c| c.i = nnn),
+        _ => {
+            let op = decode(instr);
+            Box::new(move |c| op(c, instr))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Jit;
+    use crate::interpreter::chip8::Chip8;
+
+    #[test]
+    fn blocks_are_cut_short_by_the_instruction_count() {
+        // LD V0, 01; ADD V0, 02; LD I, 300; JP 200
+        let program = [0x60, 0x01, 0x70, 0x02, 0xA3, 0x00, 0x12, 0x00];
+        let mut machine = Chip8::new(false).load_program(&program);
+        let mut jit = Jit::default();
+
+        jit.run(&mut machine, 2);
+        assert_eq!((machine.v[0], machine.pc.value()), (3, 0x204));
+
+        jit.run(&mut machine, 2);
+        assert_eq!((machine.i, machine.pc.value()), (0x300, 0x200));
+
+        jit.run(&mut machine, 5);
+        assert_eq!((machine.v[0], machine.pc.value()), (1, 0x202));
+    }
+
+    #[test]
+    fn blocks_are_compiled_again_after_code_is_written() {
+        // LD V0, 05; LD I, 201; LD [I], V0; JP 200
+        let program = [0x60, 0x05, 0xA2, 0x01, 0xF0, 0x55, 0x12, 0x00];
+        let mut machine = Chip8::new(false).load_program(&program);
+        let mut jit = Jit::default();
+        jit.run(&mut machine, 4);
+
+        machine.poke(0x201, 0x09);
+        jit.run(&mut machine, 1);
+        assert_eq!(machine.v[0], 0x09);
+
+        // written by the program itself
+        machine.v[0] = 0x07;
+        jit.run(&mut machine, 3);
+        machine.v[0] = 0;
+        jit.run(&mut machine, 1);
+        assert_eq!(machine.v[0], 0x07);
+    }
+}
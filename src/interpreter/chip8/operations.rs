@@ -1,115 +1,88 @@
 use super::instruction::Instruction;
 use super::{Chip8, Reg};
+use crate::interpreter::font::SMALL_FONT_SIZE;
 
-use rand::prelude::*;
+use rand::Rng;
 
-pub const MAIN_TABLE: [fn(&mut Chip8, Instruction); 16] = [
-    op_table_0, op_1nnn, op_2nnn, op_3xkk, op_4xkk, op_5xy0, op_6xkk, op_7xkk, op_table_8, op_9xy0,
-    op_annn, op_bnnn, op_cxkk, op_dxyn, op_table_e, op_table_f,
-];
+// Executes a decoded instruction.
+pub type Op = fn(&mut Chip8, Instruction);
 
-fn op_table_0(c: &mut Chip8, instr: Instruction) {
-    match instr.kk() {
-        0xE0 => {
-            c.screen.clear();
-            c.screen.refresh = true;
-        }
-        0xEE => c.pc.set_to(c.stack.pop().unwrap_or_default()),
-        _ => c.handle_unknown_instr(instr),
-    }
+// Finds the operation executing given instruction; the result
+// depends only on the opcode, so it can be cached.
+pub fn decode(instr: Instruction) -> Op {
+    try_decode(instr).unwrap_or(op_unknown)
 }
 
-fn op_table_8(c: &mut Chip8, instr: Instruction) {
-    let (x, y) = instr.x_y();
-    match instr.last_nibble() {
-        0x0 => c.set_reg_to(Reg::V(x), c.v[y]),
-        0x1 => c.set_reg_to(Reg::V(x), c.v[x] | c.v[y]),
-        0x2 => c.set_reg_to(Reg::V(x), c.v[x] & c.v[y]),
-        0x3 => c.set_reg_to(Reg::V(x), c.v[x] ^ c.v[y]),
-        0x4 => {
-            let (val, overflow) = c.v[x].overflowing_add(c.v[y]);
-            c.set_register_flag_if_else_0(overflow);
-            c.set_reg_to(Reg::V(x), val);
-        }
-        0x5 => {
-            let (val, overflow) = c.v[x].overflowing_sub(c.v[y]);
-            c.set_register_flag_if_else_0(!overflow);
-            c.set_reg_to(Reg::V(x), val);
-        }
-        0x6 => {
-            if !c.c48_mode {
-                c.set_reg_to(Reg::V(x), c.v[y])
-            };
-            let (val, overflow) = c.v[x].overflowing_shr(1);
-            c.set_register_flag_if_else_0(overflow);
-            c.set_reg_to(Reg::V(x), val);
-        }
-        0x7 => {
-            let (val, overflow) = c.v[y].overflowing_sub(c.v[x]);
-            c.set_register_flag_if_else_0(!overflow);
-            c.set_reg_to(Reg::V(x), val);
-        }
-        0xE => {
-            if !c.c48_mode {
-                c.set_reg_to(Reg::V(x), c.v[y])
-            };
-            let (val, overflow) = c.v[x].overflowing_shl(1);
-            c.set_register_flag_if_else_0(!overflow);
-            c.set_reg_to(Reg::V(x), val);
-        }
-        _ => c.handle_unknown_instr(instr),
-    }
+// Whether the instruction is implemented, i.e. isn't skipped with a warning.
+pub fn is_known(instr: Instruction) -> bool {
+    try_decode(instr).is_some()
 }
 
-fn op_table_e(c: &mut Chip8, instr: Instruction) {
-    match instr.kk() {
-        0x9E => c.pc.increment_if(c.input.contains(&c.v[instr.x()])),
-        0xA1 => c.pc.increment_if(!c.input.contains(&c.v[instr.x()])),
-        _ => c.handle_unknown_instr(instr),
-    }
+fn try_decode(instr: Instruction) -> Option<Op> {
+    let op: Op = match instr.first_nibble() {
+        0x0 => match instr.kk() {
+            0xE0 => op_00e0,
+            0xEE => op_00ee,
+            _ => return None,
+        },
+        0x1 => op_1nnn,
+        0x2 => op_2nnn,
+        0x3 => op_3xkk,
+        0x4 => op_4xkk,
+        0x5 => op_5xy0,
+        0x6 => op_6xkk,
+        0x7 => op_7xkk,
+        0x8 => match instr.last_nibble() {
+            0x0 => op_8xy0,
+            0x1 => op_8xy1,
+            0x2 => op_8xy2,
+            0x3 => op_8xy3,
+            0x4 => op_8xy4,
+            0x5 => op_8xy5,
+            0x6 => op_8xy6,
+            0x7 => op_8xy7,
+            0xE => op_8xye,
+            _ => return None,
+        },
+        0x9 => op_9xy0,
+        0xA => op_annn,
+        0xB => op_bnnn,
+        0xC => op_cxkk,
+        0xD => op_dxyn,
+        0xE => match instr.kk() {
+            0x9E => op_ex9e,
+            0xA1 => op_exa1,
+            _ => return None,
+        },
+        _ => match instr.kk() {
+            0x07 => op_fx07,
+            0x0A => op_fx0a,
+            0x15 => op_fx15,
+            0x18 => op_fx18,
+            0x1E => op_fx1e,
+            0x29 => op_fx29,
+            0x30 => op_fx30,
+            0x33 => op_fx33,
+            0x55 => op_fx55,
+            0x65 => op_fx65,
+            _ => return None,
+        },
+    };
+
+    Some(op)
 }
-fn op_table_f(c: &mut Chip8, instr: Instruction) {
-    let x = instr.x();
-    match instr.kk() {
-        0x07 => c.set_reg_to(Reg::V(x), c.delay_timer),
-        0x0A => {
-            c.pc.decrement_if((&c.input).is_empty());
-            let key = c.input.first().unwrap_or(&0u8).to_owned();
-            c.set_reg_to(Reg::V(x), key)
-        }
-        0x15 => c.delay_timer = c.v[x],
-        0x18 => c.sound_timer = c.v[x],
-        0x1E => {
-            c.add_to_reg(Reg::I, c.v[x]);
-
-            if c.i > 0x1000 {
-                c.set_reg_to(Reg::V(15), 1u8);
-            }
-        }
-        0x29 => {
-            // get last nibble only for char.
-            let ch = (c.v[x] & 0xF) as u16;
-            // start address + offset to given character
-            c.set_reg_to(Reg::I, 0x050 + (5 * ch));
-        }
-        0x33 => {
-            let val = c.v[x];
-            c.memory[c.i as usize] = val / 100;
-            c.memory[(c.i + 1) as usize] = (val % 100) / 10;
-            c.memory[(c.i + 2) as usize] = (val % 100) % 10;
-        }
-        0x55 => {
-            for n in 0..x + 1 {
-                c.memory[(c.i as usize + n) as usize] = c.v[n];
-            }
-        }
-        0x65 => {
-            for n in 0..x + 1 {
-                c.set_reg_to(Reg::V(n), c.memory[(c.i as usize + n) as usize]);
-            }
-        }
-        _ => c.handle_unknown_instr(instr),
-    }
+
+fn op_unknown(c: &mut Chip8, instr: Instruction) {
+    c.handle_unknown_instr(instr)
+}
+
+fn op_00e0(c: &mut Chip8, _: Instruction) {
+    c.screen.clear();
+    c.screen.refresh = true;
+}
+
+fn op_00ee(c: &mut Chip8, _: Instruction) {
+    c.pc.set_to(c.stack.pop().unwrap_or_default())
 }
 
 fn op_1nnn(c: &mut Chip8, instr: Instruction) {
@@ -140,6 +113,82 @@ fn op_6xkk(c: &mut Chip8, instr: Instruction) {
 fn op_7xkk(c: &mut Chip8, instr: Instruction) {
     c.add_to_reg(Reg::V(instr.x()), instr.kk());
 }
+
+fn op_8xy0(c: &mut Chip8, instr: Instruction) {
+    let (x, y) = instr.x_y();
+    c.set_reg_to(Reg::V(x), c.v[y])
+}
+
+fn op_8xy1(c: &mut Chip8, instr: Instruction) {
+    let (x, y) = instr.x_y();
+    c.set_reg_to(Reg::V(x), c.v[x] | c.v[y]);
+    reset_flag_after_logic(c);
+}
+
+fn op_8xy2(c: &mut Chip8, instr: Instruction) {
+    let (x, y) = instr.x_y();
+    c.set_reg_to(Reg::V(x), c.v[x] & c.v[y]);
+    reset_flag_after_logic(c);
+}
+
+fn op_8xy3(c: &mut Chip8, instr: Instruction) {
+    let (x, y) = instr.x_y();
+    c.set_reg_to(Reg::V(x), c.v[x] ^ c.v[y]);
+    reset_flag_after_logic(c);
+}
+
+// ambiguous OP: the COSMAC VIP interpreter leaves VF cleared after
+// 8XY1 / 8XY2 / 8XY3, CHIP-48 leaves it unchanged
+pub(super) fn reset_flag_after_logic(c: &mut Chip8) {
+    if !c.c48_mode {
+        c.set_reg_to(Reg::V(15), 0u8)
+    }
+}
+
+// VF is set after the result, so that it holds the flag even if X is F
+fn op_8xy4(c: &mut Chip8, instr: Instruction) {
+    let (x, y) = instr.x_y();
+    let (val, overflow) = c.v[x].overflowing_add(c.v[y]);
+    c.set_reg_to(Reg::V(x), val);
+    c.set_register_flag_if_else_0(overflow);
+}
+
+fn op_8xy5(c: &mut Chip8, instr: Instruction) {
+    let (x, y) = instr.x_y();
+    let (val, overflow) = c.v[x].overflowing_sub(c.v[y]);
+    c.set_reg_to(Reg::V(x), val);
+    c.set_register_flag_if_else_0(!overflow);
+}
+
+// ambiguous OP: shifts VY in CHIP-8, VX in CHIP-48 mode
+fn op_8xy6(c: &mut Chip8, instr: Instruction) {
+    let (x, y) = instr.x_y();
+    if !c.c48_mode {
+        c.set_reg_to(Reg::V(x), c.v[y])
+    };
+    let shifted_out = c.v[x] & 0x01;
+    c.set_reg_to(Reg::V(x), c.v[x] >> 1);
+    c.set_reg_to(Reg::V(15), shifted_out);
+}
+
+fn op_8xy7(c: &mut Chip8, instr: Instruction) {
+    let (x, y) = instr.x_y();
+    let (val, overflow) = c.v[y].overflowing_sub(c.v[x]);
+    c.set_reg_to(Reg::V(x), val);
+    c.set_register_flag_if_else_0(!overflow);
+}
+
+// ambiguous OP: shifts VY in CHIP-8, VX in CHIP-48 mode
+fn op_8xye(c: &mut Chip8, instr: Instruction) {
+    let (x, y) = instr.x_y();
+    if !c.c48_mode {
+        c.set_reg_to(Reg::V(x), c.v[y])
+    };
+    let shifted_out = c.v[x] >> 7;
+    c.set_reg_to(Reg::V(x), c.v[x] << 1);
+    c.set_reg_to(Reg::V(15), shifted_out);
+}
+
 fn op_9xy0(c: &mut Chip8, instr: Instruction) {
     let (x, y) = instr.x_y();
     c.pc.increment_if(c.v[x] != c.v[y]);
@@ -148,17 +197,17 @@ fn op_annn(c: &mut Chip8, instr: Instruction) {
     c.set_reg_to(Reg::I, instr.nnn());
 }
 
-// ambiguous OP: either BNNN or BXKK
+// ambiguous OP: either BNNN or BXNN
 fn op_bnnn(c: &mut Chip8, instr: Instruction) {
     if c.c48_mode {
-        c.pc.set_to((instr.kk() + c.v[instr.x()]) as u16)
+        c.pc.set_to(instr.nnn() + c.v[instr.x()] as u16)
     } else {
         c.pc.set_to(instr.nnn() + c.v[0] as u16)
     }
 }
 
 fn op_cxkk(c: &mut Chip8, instr: Instruction) {
-    let r: u8 = thread_rng().gen();
+    let r: u8 = c.rng.gen();
     c.set_reg_to(Reg::V(instr.x()), r & instr.kk());
 }
 
@@ -166,3 +215,539 @@ fn op_dxyn(c: &mut Chip8, instr: Instruction) {
     c.draw(instr);
     c.screen.refresh = true;
 }
+
+fn op_ex9e(c: &mut Chip8, instr: Instruction) {
+    c.pc.increment_if(c.input.contains(&c.v[instr.x()]))
+}
+
+fn op_exa1(c: &mut Chip8, instr: Instruction) {
+    c.pc.increment_if(!c.input.contains(&c.v[instr.x()]))
+}
+
+fn op_fx07(c: &mut Chip8, instr: Instruction) {
+    c.set_reg_to(Reg::V(instr.x()), c.delay_timer)
+}
+
+fn op_fx0a(c: &mut Chip8, instr: Instruction) {
+    // wait for a key by executing this instruction again
+    c.pc.decrement_if(c.input.is_empty());
+    if let Some(&key) = c.input.first() {
+        c.set_reg_to(Reg::V(instr.x()), key)
+    }
+}
+
+fn op_fx15(c: &mut Chip8, instr: Instruction) {
+    c.delay_timer = c.v[instr.x()]
+}
+
+fn op_fx18(c: &mut Chip8, instr: Instruction) {
+    c.sound_timer = c.v[instr.x()]
+}
+
+// ambiguous OP: the COSMAC VIP leaves VF unchanged, the Amiga interpreter
+// sets it to whether I is beyond the addressable memory
+fn op_fx1e(c: &mut Chip8, instr: Instruction) {
+    c.add_to_reg(Reg::I, c.v[instr.x()]);
+
+    if c.fx1e_overflow {
+        c.set_register_flag_if_else_0(c.i > 0xFFF);
+    }
+}
+
+fn op_fx29(c: &mut Chip8, instr: Instruction) {
+    // get last nibble only for char.
+    let ch = (c.v[instr.x()] & 0xF) as u16;
+    // start address + offset to given character
+    c.set_reg_to(Reg::I, c.font_addr + (5 * ch));
+}
+
+// SCHIP: points I to a large digit, which follow the small characters.
+fn op_fx30(c: &mut Chip8, instr: Instruction) {
+    let digit = (c.v[instr.x()] & 0xF) as u16;
+    c.set_reg_to(Reg::I, c.font_addr + SMALL_FONT_SIZE as u16 + 10 * digit);
+}
+
+fn op_fx33(c: &mut Chip8, instr: Instruction) {
+    let val = c.v[instr.x()];
+    c.write_memory(c.i as usize, val / 100);
+    c.write_memory(c.i as usize + 1, (val % 100) / 10);
+    c.write_memory(c.i as usize + 2, (val % 100) % 10);
+}
+
+// ambiguous OP: increments I past the stored registers in CHIP-8, leaves it in CHIP-48 mode
+fn op_fx55(c: &mut Chip8, instr: Instruction) {
+    for n in 0..instr.x() + 1 {
+        c.write_memory(c.i as usize + n, c.v[n]);
+    }
+    increment_i_after_memory(c, instr);
+}
+
+// ambiguous OP: increments I past the loaded registers in CHIP-8, leaves it in CHIP-48 mode
+fn op_fx65(c: &mut Chip8, instr: Instruction) {
+    for n in 0..instr.x() + 1 {
+        c.set_reg_to(Reg::V(n), c.read_memory(c.i as usize + n));
+    }
+    increment_i_after_memory(c, instr);
+}
+
+fn increment_i_after_memory(c: &mut Chip8, instr: Instruction) {
+    if !c.c48_mode {
+        c.add_to_reg(Reg::I, instr.x() as u16 + 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_known, Chip8, Instruction};
+    use std::collections::BTreeSet;
+
+    // Program start; the opcode under test is placed here.
+    const START: u16 = 0x200;
+    const NEXT: u16 = START + 2;
+    const SKIPPED: u16 = START + 4;
+
+    struct Case {
+        name: &'static str,
+        opcode: u16,
+        c48_mode: bool,
+        setup: fn(&mut Chip8),
+        check: fn(&Chip8),
+    }
+
+    fn none(_: &mut Chip8) {}
+
+    fn case(name: &'static str, opcode: u16, setup: fn(&mut Chip8), check: fn(&Chip8)) -> Case {
+        Case {
+            name,
+            opcode,
+            c48_mode: false,
+            setup,
+            check,
+        }
+    }
+
+    fn c48_case(name: &'static str, opcode: u16, setup: fn(&mut Chip8), check: fn(&Chip8)) -> Case {
+        Case {
+            c48_mode: true,
+            ..case(name, opcode, setup, check)
+        }
+    }
+
+    fn cases() -> Vec<Case> {
+        vec![
+            // 0NNN
+            case(
+                "00E0 clears the screen",
+                0x00E0,
+                |c| c.screen.display[100] = true,
+                |c| assert!(c.screen.display.iter().all(|&p| !p) && c.screen.refresh),
+            ),
+            case(
+                "00EE returns from a subroutine",
+                0x00EE,
+                |c| c.stack.push(0x300),
+                |c| assert!(c.pc.value() == 0x300 && c.stack.is_empty()),
+            ),
+            case("0NNN is reported as unknown", 0x0123, none, |c| {
+                assert!(c.warnings.len() == 1 && c.pc.value() == NEXT)
+            }),
+            // 1NNN - 2NNN
+            case("1NNN jumps", 0x1ABC, none, |c| {
+                assert_eq!(c.pc.value(), 0xABC)
+            }),
+            case("2NNN calls a subroutine", 0x2ABC, none, |c| {
+                assert!(c.pc.value() == 0xABC && c.stack == [NEXT])
+            }),
+            // 3XKK - 5XY0, 9XY0
+            case(
+                "3XKK skips if equal",
+                0x3A42,
+                |c| c.v[0xA] = 0x42,
+                |c| assert_eq!(c.pc.value(), SKIPPED),
+            ),
+            case("3XKK doesn't skip if not equal", 0x3A42, none, |c| {
+                assert_eq!(c.pc.value(), NEXT)
+            }),
+            case("4XKK skips if not equal", 0x4A42, none, |c| {
+                assert_eq!(c.pc.value(), SKIPPED)
+            }),
+            case(
+                "4XKK doesn't skip if equal",
+                0x4A42,
+                |c| c.v[0xA] = 0x42,
+                |c| assert_eq!(c.pc.value(), NEXT),
+            ),
+            case("5XY0 skips if equal", 0x5AB0, none, |c| {
+                assert_eq!(c.pc.value(), SKIPPED)
+            }),
+            case(
+                "5XY0 doesn't skip if not equal",
+                0x5AB0,
+                |c| c.v[0xA] = 1,
+                |c| assert_eq!(c.pc.value(), NEXT),
+            ),
+            case(
+                "9XY0 skips if not equal",
+                0x9AB0,
+                |c| c.v[0xA] = 1,
+                |c| assert_eq!(c.pc.value(), SKIPPED),
+            ),
+            case("9XY0 doesn't skip if equal", 0x9AB0, none, |c| {
+                assert_eq!(c.pc.value(), NEXT)
+            }),
+            // 6XKK - 7XKK
+            case("6XKK loads a value", 0x6A42, none, |c| {
+                assert_eq!(c.v[0xA], 0x42)
+            }),
+            case(
+                "7XKK adds a value, wrapping without carry",
+                0x7A02,
+                |c| c.v[0xA] = 0xFF,
+                |c| assert!(c.v[0xA] == 0x01 && c.v[0xF] == 0),
+            ),
+            // 8XYN
+            case(
+                "8XY0 copies",
+                0x8AB0,
+                |c| c.v[0xB] = 7,
+                |c| assert_eq!(c.v[0xA], 7),
+            ),
+            case(
+                "8XY1 ORs",
+                0x8AB1,
+                |c| (c.v[0xA], c.v[0xB]) = (0b1100, 0b1010),
+                |c| assert_eq!(c.v[0xA], 0b1110),
+            ),
+            case(
+                "8XY2 ANDs",
+                0x8AB2,
+                |c| (c.v[0xA], c.v[0xB]) = (0b1100, 0b1010),
+                |c| assert_eq!(c.v[0xA], 0b1000),
+            ),
+            case(
+                "8XY3 XORs",
+                0x8AB3,
+                |c| (c.v[0xA], c.v[0xB]) = (0b1100, 0b1010),
+                |c| assert_eq!(c.v[0xA], 0b0110),
+            ),
+            case(
+                "8XY1 resets VF",
+                0x8AB1,
+                |c| c.v[0xF] = 1,
+                |c| assert_eq!(c.v[0xF], 0),
+            ),
+            c48_case(
+                "8XY1 leaves VF in CHIP-48 mode",
+                0x8AB1,
+                |c| c.v[0xF] = 1,
+                |c| assert_eq!(c.v[0xF], 1),
+            ),
+            case(
+                "8XY2 resets VF",
+                0x8AB2,
+                |c| c.v[0xF] = 1,
+                |c| assert_eq!(c.v[0xF], 0),
+            ),
+            c48_case(
+                "8XY2 leaves VF in CHIP-48 mode",
+                0x8AB2,
+                |c| c.v[0xF] = 1,
+                |c| assert_eq!(c.v[0xF], 1),
+            ),
+            case(
+                "8XY3 resets VF",
+                0x8AB3,
+                |c| c.v[0xF] = 1,
+                |c| assert_eq!(c.v[0xF], 0),
+            ),
+            c48_case(
+                "8XY3 leaves VF in CHIP-48 mode",
+                0x8AB3,
+                |c| c.v[0xF] = 1,
+                |c| assert_eq!(c.v[0xF], 1),
+            ),
+            case(
+                "8XY4 adds with carry",
+                0x8AB4,
+                |c| (c.v[0xA], c.v[0xB]) = (0xFF, 0x02),
+                |c| assert!(c.v[0xA] == 0x01 && c.v[0xF] == 1),
+            ),
+            case(
+                "8XY4 adds without carry",
+                0x8AB4,
+                |c| (c.v[0xA], c.v[0xB], c.v[0xF]) = (0x01, 0x02, 1),
+                |c| assert!(c.v[0xA] == 0x03 && c.v[0xF] == 0),
+            ),
+            case(
+                "8XY4 sets the carry even if X is F",
+                0x8FB4,
+                |c| (c.v[0xF], c.v[0xB]) = (0xFF, 0x02),
+                |c| assert_eq!(c.v[0xF], 1),
+            ),
+            case(
+                "8XY5 subtracts without borrow",
+                0x8AB5,
+                |c| (c.v[0xA], c.v[0xB]) = (0x05, 0x02),
+                |c| assert!(c.v[0xA] == 0x03 && c.v[0xF] == 1),
+            ),
+            case(
+                "8XY5 subtracts with borrow",
+                0x8AB5,
+                |c| (c.v[0xA], c.v[0xB]) = (0x02, 0x05),
+                |c| assert!(c.v[0xA] == 0xFD && c.v[0xF] == 0),
+            ),
+            case(
+                "8XY5 sets the flag even if X is F",
+                0x8FB5,
+                |c| (c.v[0xF], c.v[0xB]) = (0x05, 0x02),
+                |c| assert_eq!(c.v[0xF], 1),
+            ),
+            case(
+                "8XY6 shifts VY right, VF is the shifted out bit",
+                0x8AB6,
+                |c| (c.v[0xA], c.v[0xB]) = (0x00, 0x05),
+                |c| assert!(c.v[0xA] == 0x02 && c.v[0xF] == 1),
+            ),
+            case(
+                "8XY6 clears VF if no bit is shifted out",
+                0x8AB6,
+                |c| (c.v[0xB], c.v[0xF]) = (0x04, 1),
+                |c| assert!(c.v[0xA] == 0x02 && c.v[0xF] == 0),
+            ),
+            c48_case(
+                "8XY6 shifts VX right in CHIP-48 mode",
+                0x8AB6,
+                |c| (c.v[0xA], c.v[0xB]) = (0x81, 0x00),
+                |c| assert!(c.v[0xA] == 0x40 && c.v[0xF] == 1),
+            ),
+            case(
+                "8XY6 sets the flag even if X is F",
+                0x8FB6,
+                |c| c.v[0xB] = 0x02,
+                |c| assert_eq!(c.v[0xF], 0),
+            ),
+            case(
+                "8XY7 subtracts reversed without borrow",
+                0x8AB7,
+                |c| (c.v[0xA], c.v[0xB]) = (0x02, 0x05),
+                |c| assert!(c.v[0xA] == 0x03 && c.v[0xF] == 1),
+            ),
+            case(
+                "8XY7 subtracts reversed with borrow",
+                0x8AB7,
+                |c| (c.v[0xA], c.v[0xB]) = (0x05, 0x02),
+                |c| assert!(c.v[0xA] == 0xFD && c.v[0xF] == 0),
+            ),
+            case(
+                "8XYE shifts VY left, VF is the shifted out bit",
+                0x8ABE,
+                |c| (c.v[0xA], c.v[0xB]) = (0x00, 0x81),
+                |c| assert!(c.v[0xA] == 0x02 && c.v[0xF] == 1),
+            ),
+            case(
+                "8XYE clears VF if no bit is shifted out",
+                0x8ABE,
+                |c| (c.v[0xB], c.v[0xF]) = (0x41, 1),
+                |c| assert!(c.v[0xA] == 0x82 && c.v[0xF] == 0),
+            ),
+            c48_case(
+                "8XYE shifts VX left in CHIP-48 mode",
+                0x8ABE,
+                |c| (c.v[0xA], c.v[0xB]) = (0x81, 0x00),
+                |c| assert!(c.v[0xA] == 0x02 && c.v[0xF] == 1),
+            ),
+            case("8XYN with unknown N is reported", 0x8AB9, none, |c| {
+                assert_eq!(c.warnings.len(), 1)
+            }),
+            // ANNN - DXYN
+            case("ANNN loads I", 0xAABC, none, |c| assert_eq!(c.i, 0xABC)),
+            case(
+                "BNNN jumps with V0 offset",
+                0xB300,
+                |c| c.v[0] = 0x10,
+                |c| assert_eq!(c.pc.value(), 0x310),
+            ),
+            c48_case(
+                "BXNN jumps with VX offset in CHIP-48 mode",
+                0xB3F0,
+                |c| (c.v[0], c.v[3]) = (0x01, 0x20),
+                |c| assert_eq!(c.pc.value(), 0x410),
+            ),
+            case(
+                "CXKK masks the random value",
+                0xCA00,
+                |c| c.v[0xA] = 0xFF,
+                |c| assert_eq!(c.v[0xA], 0),
+            ),
+            case(
+                "DXYN draws a sprite",
+                0xDAB1,
+                |c| (c.memory[0x300], c.i, c.v[0xA], c.v[0xB]) = (0x80, 0x300, 2, 1),
+                |c| assert!(c.screen.display[64 + 2] && c.v[0xF] == 0 && c.screen.refresh),
+            ),
+            case(
+                "DXYN reports collision",
+                0xDAB1,
+                |c| {
+                    (c.memory[0x300], c.i) = (0x80, 0x300);
+                    c.screen.display[0] = true;
+                },
+                |c| assert!(!c.screen.display[0] && c.v[0xF] == 1),
+            ),
+            // EXKK
+            case(
+                "EX9E skips if key pressed",
+                0xEA9E,
+                |c| (c.v[0xA], c.input) = (5, vec![5]),
+                |c| assert_eq!(c.pc.value(), SKIPPED),
+            ),
+            case("EX9E doesn't skip if key not pressed", 0xEA9E, none, |c| {
+                assert_eq!(c.pc.value(), NEXT)
+            }),
+            case("EXA1 skips if key not pressed", 0xEAA1, none, |c| {
+                assert_eq!(c.pc.value(), SKIPPED)
+            }),
+            case(
+                "EXA1 doesn't skip if key pressed",
+                0xEAA1,
+                |c| (c.v[0xA], c.input) = (5, vec![5]),
+                |c| assert_eq!(c.pc.value(), NEXT),
+            ),
+            // FXKK
+            case(
+                "FX07 reads the delay timer",
+                0xFA07,
+                |c| c.delay_timer = 9,
+                |c| assert_eq!(c.v[0xA], 9),
+            ),
+            case(
+                "FX0A waits for a key",
+                0xFA0A,
+                |c| c.v[0xA] = 9,
+                |c| assert!(c.pc.value() == START && c.v[0xA] == 9),
+            ),
+            case(
+                "FX0A reads a pressed key",
+                0xFA0A,
+                |c| c.input = vec![7],
+                |c| assert!(c.v[0xA] == 7 && c.pc.value() == NEXT),
+            ),
+            case(
+                "FX15 sets the delay timer",
+                0xFA15,
+                |c| c.v[0xA] = 9,
+                |c| assert_eq!(c.delay_timer, 9),
+            ),
+            case(
+                "FX18 sets the sound timer",
+                0xFA18,
+                |c| c.v[0xA] = 9,
+                |c| assert_eq!(c.sound_timer, 9),
+            ),
+            case(
+                "FX1E adds to I, leaving VF",
+                0xFA1E,
+                |c| (c.i, c.v[0xA], c.v[0xF]) = (0xFF0, 0x0F, 1),
+                |c| assert!(c.i == 0xFFF && c.v[0xF] == 1),
+            ),
+            case(
+                "FX1E clears VF with the overflow quirk when I stays in memory",
+                0xFA1E,
+                |c| (c.fx1e_overflow, c.i, c.v[0xA], c.v[0xF]) = (true, 0xFF0, 0x0F, 1),
+                |c| assert!(c.i == 0xFFF && c.v[0xF] == 0),
+            ),
+            case(
+                "FX1E sets VF with the overflow quirk when I leaves the memory",
+                0xFA1E,
+                |c| (c.fx1e_overflow, c.i, c.v[0xA]) = (true, 0xFF0, 0x10),
+                |c| assert!(c.i == 0x1000 && c.v[0xF] == 1),
+            ),
+            case(
+                "FX29 points I to a character",
+                0xFA29,
+                |c| c.v[0xA] = 0x1B,
+                |c| assert_eq!(c.i, 0x050 + 5 * 0xB),
+            ),
+            case(
+                "FX30 points I to a large digit",
+                0xFA30,
+                |c| c.v[0xA] = 0x17,
+                |c| assert_eq!(c.i, 0x050 + 80 + 10 * 0x7),
+            ),
+            case(
+                "FX33 stores BCD",
+                0xFA33,
+                |c| (c.i, c.v[0xA]) = (0x300, 254),
+                |c| assert_eq!(c.memory[0x300..0x303], [2, 5, 4]),
+            ),
+            case(
+                "FX55 stores V0 - VX, incrementing I",
+                0xF255,
+                |c| (c.i, c.v[0], c.v[1], c.v[2], c.v[3]) = (0x300, 1, 2, 3, 4),
+                |c| assert!(c.memory[0x300..0x304] == [1, 2, 3, 0] && c.i == 0x303),
+            ),
+            c48_case(
+                "FX55 leaves I in CHIP-48 mode",
+                0xF255,
+                |c| c.i = 0x300,
+                |c| assert_eq!(c.i, 0x300),
+            ),
+            case(
+                "FX65 loads V0 - VX, incrementing I",
+                0xF265,
+                |c| {
+                    c.i = 0x300;
+                    c.memory[0x300..0x304].copy_from_slice(&[1, 2, 3, 4]);
+                },
+                |c| assert!(c.v[..4] == [1, 2, 3, 0] && c.i == 0x303),
+            ),
+            c48_case(
+                "FX65 leaves I in CHIP-48 mode",
+                0xF265,
+                |c| c.i = 0x300,
+                |c| assert_eq!(c.i, 0x300),
+            ),
+            case("FXKK with unknown KK is reported", 0xFAFF, none, |c| {
+                assert_eq!(c.warnings.len(), 1)
+            }),
+        ]
+    }
+
+    #[test]
+    fn all_opcodes_behave_as_specified() {
+        for case in cases() {
+            let mut machine = Chip8::new(case.c48_mode);
+            machine.poke(START, (case.opcode >> 8) as u8);
+            machine.poke(START + 1, case.opcode as u8);
+            (case.setup)(&mut machine);
+
+            machine.run_instruction(false);
+
+            let result =
+                std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| (case.check)(&machine)));
+            assert!(result.is_ok(), "case failed: {}", case.name);
+        }
+    }
+
+    // The bits telling the opcode apart from the others, e.g. 0xF01E for FX1E.
+    fn opcode_class(opcode: u16) -> u16 {
+        match opcode >> 12 {
+            0x0 | 0xE | 0xF => opcode & 0xF0FF,
+            0x8 => opcode & 0xF00F,
+            _ => opcode & 0xF000,
+        }
+    }
+
+    #[test]
+    fn every_known_opcode_is_covered() {
+        let covered: BTreeSet<u16> = cases().iter().map(|c| opcode_class(c.opcode)).collect();
+        let known: BTreeSet<u16> = (0..=0xFFFF)
+            .filter(|&op: &u16| is_known(Instruction::new_from_bytes((op >> 8) as u8, op as u8)))
+            .map(opcode_class)
+            .collect();
+
+        for class in known {
+            assert!(covered.contains(&class), "no case for {:04X}", class);
+        }
+    }
+}
@@ -6,7 +6,7 @@ impl ProgramCounter {
         ProgramCounter(val)
     }
 
-    pub fn set_to(&mut self, val: u16) -> () {
+    pub fn set_to(&mut self, val: u16) {
         self.0 = val
     }
 
@@ -14,19 +14,19 @@ impl ProgramCounter {
         self.0
     }
 
-    pub fn increment(&mut self) -> () {
-        self.0 += 2
+    pub fn increment(&mut self) {
+        self.0 = self.0.wrapping_add(2)
     }
 
-    pub fn increment_if(&mut self, condition: bool) -> () {
+    pub fn increment_if(&mut self, condition: bool) {
         if condition {
             self.increment()
         }
     }
 
-    pub fn decrement_if(&mut self, condition: bool) -> () {
+    pub fn decrement_if(&mut self, condition: bool) {
         if condition {
-            self.0 -= 2
+            self.0 = self.0.wrapping_sub(2)
         }
     }
 }
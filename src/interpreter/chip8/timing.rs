@@ -0,0 +1,187 @@
+use super::instruction::Instruction;
+use super::Chip8;
+
+// The COSMAC VIP runs its CDP1802 at 1.7609 MHz, with 8 clock
+// periods per machine cycle, i.e. ~3668 machine cycles per 60 Hz frame.
+const CYCLES_PER_FRAME: u32 = 3668;
+
+// Part of each frame is taken away from the interpreter: the CDP1861
+// display fetches 128 scanlines of 8 bytes via DMA (one machine cycle
+// per byte) and the interrupt routine itself updates the timers.
+const DISPLAY_DMA_CYCLES: u32 = 128 * 8;
+const INTERRUPT_CYCLES: u32 = 46;
+
+// Machine cycles available to the interpreter in a single frame.
+pub const VIP_CYCLES_PER_FRAME: u32 = CYCLES_PER_FRAME - DISPLAY_DMA_CYCLES - INTERRUPT_CYCLES;
+
+// Fetching and decoding any instruction takes the same time.
+const FETCH_CYCLES: u32 = 40;
+
+// Extra cycles spent by conditional skips when the skip is taken.
+const SKIP_TAKEN_CYCLES: u32 = 4;
+
+// DXYN is charged per sprite row; rows not aligned to a byte in
+// the display buffer need to be shifted and written to two bytes.
+const DRAW_BASE_CYCLES: u32 = 26;
+const DRAW_ALIGNED_ROW_CYCLES: u32 = 34;
+const DRAW_UNALIGNED_ROW_CYCLES: u32 = 46;
+
+// Runs a machine frame by frame with the timing of the COSMAC VIP; implemented
+// by the interpreter and the headless runner, which execute instructions
+// differently, so both run exactly the same frames.
+pub trait VipFrames {
+    type Error;
+
+    fn machine(&self) -> &Chip8;
+
+    // Cycles left over from (or overspent by) the previous frames.
+    fn cycle_credit(&mut self) -> &mut i64;
+
+    // Executes the next instruction; returns false if it wasn't executed
+    // (e.g. halted by a breakpoint), which ends the frame early.
+    fn execute_next(&mut self) -> Result<bool, Self::Error>;
+
+    // Runs the instructions fitting into a single frame of the COSMAC VIP.
+    // As on the VIP, a draw waits for the next display interrupt, i.e.
+    // unless it is the first instruction, it ends the current frame.
+    fn run_vip_frame(&mut self) -> Result<(), Self::Error> {
+        *self.cycle_credit() += VIP_CYCLES_PER_FRAME as i64;
+        let mut frame_start = true;
+
+        while *self.cycle_credit() > 0 {
+            if !frame_start && self.machine().next_instruction_waits_for_vsync() {
+                *self.cycle_credit() = 0;
+                break;
+            }
+
+            let cost = self.machine().next_instruction_vip_cycles() as i64;
+            if !self.execute_next()? {
+                break;
+            }
+            *self.cycle_credit() -= cost;
+            frame_start = false;
+        }
+        Ok(())
+    }
+}
+
+// Execution costs follow Laurence Scotford's analysis of the original
+// VIP interpreter; costs which depend on data are approximated
+// from the same analysis.
+impl Chip8 {
+    // Cost of the instruction at program counter, in machine cycles.
+    pub fn next_instruction_vip_cycles(&self) -> u32 {
+        FETCH_CYCLES + self.vip_execution_cycles(self.peek())
+    }
+
+    // DXYN waits for the display interrupt before drawing on the VIP.
+    pub fn next_instruction_waits_for_vsync(&self) -> bool {
+        self.peek().first_nibble() == 0xD
+    }
+
+    fn vip_execution_cycles(&self, instr: Instruction) -> u32 {
+        let (x, y) = instr.x_y();
+        let skip = |taken: bool| if taken { SKIP_TAKEN_CYCLES } else { 0 };
+
+        match instr.first_nibble() {
+            0x0 => match instr.kk() {
+                0xE0 => 24 + 3078,
+                0xEE => 10,
+                _ => 0,
+            },
+            0x1 => 12,
+            0x2 => 26,
+            0x3 => 10 + skip(self.v[x] == instr.kk()),
+            0x4 => 10 + skip(self.v[x] != instr.kk()),
+            0x5 => 14 + skip(self.v[x] == self.v[y]),
+            0x6 => 6,
+            0x7 => 10,
+            0x8 => match instr.last_nibble() {
+                0x0 => 12,
+                _ => 44,
+            },
+            0x9 => 14 + skip(self.v[x] != self.v[y]),
+            0xA => 12,
+            0xB => {
+                // crossing a page boundary costs an extra carry
+                let page_cross = (instr.nnn() & 0xFF) + self.v[0] as u16 > 0xFF;
+                22 + if page_cross { 2 } else { 0 }
+            }
+            0xC => 36,
+            0xD => {
+                let rows = instr.last_nibble() as u32;
+                let row_cycles = if self.v[x].is_multiple_of(8) {
+                    DRAW_ALIGNED_ROW_CYCLES
+                } else {
+                    DRAW_UNALIGNED_ROW_CYCLES
+                };
+                DRAW_BASE_CYCLES + rows * row_cycles
+            }
+            0xE => {
+                let pressed = self.input.contains(&self.v[x]);
+                match instr.kk() {
+                    0x9E => 14 + skip(pressed),
+                    _ => 14 + skip(!pressed),
+                }
+            }
+            _ => match instr.kk() {
+                0x0A => 19,
+                0x1E => 16,
+                0x29 => 16,
+                // BCD is computed by repeated subtraction
+                0x33 => {
+                    let val = self.v[x] as u32;
+                    84 + 16 * (val / 100 + (val % 100) / 10 + val % 10)
+                }
+                0x55 | 0x65 => 14 + 14 * (x as u32 + 1),
+                _ => 10,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Chip8;
+    use super::{
+        DRAW_ALIGNED_ROW_CYCLES, DRAW_BASE_CYCLES, DRAW_UNALIGNED_ROW_CYCLES, FETCH_CYCLES,
+    };
+
+    fn machine_with(b1: u8, b2: u8) -> Chip8 {
+        let mut machine = Chip8::new(false);
+        machine.memory[0x200] = b1;
+        machine.memory[0x201] = b2;
+        machine
+    }
+
+    #[test]
+    fn fixed_costs_include_fetch() {
+        let machine = machine_with(0x61, 0x05);
+        assert_eq!(machine.next_instruction_vip_cycles(), FETCH_CYCLES + 6);
+        assert!(!machine.next_instruction_waits_for_vsync());
+    }
+
+    #[test]
+    fn taken_skips_cost_more() {
+        let mut machine = machine_with(0x31, 0x05);
+        let not_taken = machine.next_instruction_vip_cycles();
+        machine.v[1] = 0x05;
+        assert_eq!(machine.next_instruction_vip_cycles(), not_taken + 4);
+    }
+
+    #[test]
+    fn draw_cost_depends_on_rows_and_alignment() {
+        let mut machine = machine_with(0xD0, 0x13);
+        assert!(machine.next_instruction_waits_for_vsync());
+        assert_eq!(
+            machine.next_instruction_vip_cycles(),
+            FETCH_CYCLES + DRAW_BASE_CYCLES + 3 * DRAW_ALIGNED_ROW_CYCLES
+        );
+
+        machine.v[0] = 3;
+        assert_eq!(
+            machine.next_instruction_vip_cycles(),
+            FETCH_CYCLES + DRAW_BASE_CYCLES + 3 * DRAW_UNALIGNED_ROW_CYCLES
+        );
+    }
+}
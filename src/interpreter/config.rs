@@ -1,49 +1,73 @@
 use sdl2::pixels::Color;
 
-use crate::interpreter::MAIN_LOOP_FREQUENCY;
+use crate::interpreter::audio::Waveform;
+use crate::interpreter::chip8::{FONT_ADDR, PROGRAM_START};
+use crate::interpreter::font::FONT_SIZE;
+use crate::interpreter::scheduler::TIMER_FREQUENCY;
+use crate::interpreter::trace::{
+    parse_address_range, parse_opcode_classes, TraceConfig, TraceFilter, TraceFormat,
+};
 
 // Used to calculate the actual screen size from configuration.
 const DEFAULT_SCREEN_SIZE_COEFF: u32 = 10;
 
+// ETI-660 programs are loaded at and start from this address.
+const ETI_660_PROGRAM_START: u16 = 0x600;
+
 // Default frequency to use.
 const DEFAULT_EMU_FREQUENCY: u32 = 500;
 
+// Upper bound for instructions per frame; XO-CHIP programs may need
+// tens of thousands, this leaves plenty of headroom.
+pub const MAX_INSTRUCTIONS_PER_FRAME: u32 = 1_000_000;
+
+// Default beep tone settings.
+const DEFAULT_TONE_FREQUENCY: f32 = 256.0;
+const DEFAULT_VOLUME: u8 = 10;
+
 const DEFAULT_BACKGROUND_COLOR: Color = Color::RGB(0, 0, 0);
 const DEFAULT_FOREGROUND_COLOR: Color = Color::RGB(0, 255, 102);
 
-// 16 characters, each represented by 5 bytes
-pub const FONT: [u8; 0x10 * 5] = [
-    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-    0x20, 0x60, 0x20, 0x20, 0x70, // 1
-    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
-];
-
 pub struct Config {
     pub screen_size: u32,
     emu_speed: u32,
     pub background_color: Color,
     pub foreground_color: Color,
     pub debug_mode: bool,
+    pub debugger_window: bool,
+    pub gdb_port: Option<u16>,
+    pub symbols: Option<String>,
+    pub breakpoints: Vec<String>,
+    pub console: bool,
+    pub cheat_dir: Option<String>,
     pub c48_mode: bool,
+    pub fx1e_overflow: bool,
+    pub vip_timing: bool,
+    pub show_fps: bool,
+    pub show_registers: bool,
+    pub tone_frequency: f32,
+    pub waveform: Waveform,
+    pub volume: u8,
+    pub wav_output: Option<String>,
+    pub trace: Option<TraceConfig>,
+    pub profile: Option<String>,
+    pub check_memory: bool,
+    pub font: Option<String>,
+    pub font_addr: u16,
+    pub zip_entry: Option<String>,
+    pub load_addr: u16,
+    pub entry: Option<u16>,
+    // address and path of each additional blob loaded
+    pub blobs: Vec<(u16, String)>,
 }
 
 impl Config {
     pub fn from_args(matches: &clap::ArgMatches<'_>) -> Config {
-        if matches.is_present("debug") {
-            println!("{}\n{}", "Entering debug mode...", crate::DEBUG_MSG)
+        let debug_mode = matches.is_present("debug")
+            || matches.is_present("debugger-window")
+            || matches.is_present("break");
+        if debug_mode {
+            println!("Entering debug mode...\n{}", crate::DEBUG_MSG)
         }
 
         Config {
@@ -53,20 +77,61 @@ impl Config {
                 .unwrap_or(DEFAULT_BACKGROUND_COLOR),
             foreground_color: Config::set_color(matches, "fg-color")
                 .unwrap_or(DEFAULT_FOREGROUND_COLOR),
-            debug_mode: matches.is_present("debug"),
+            debug_mode,
+            debugger_window: matches.is_present("debugger-window"),
+            gdb_port: matches.value_of("gdb").map(|p| p.parse::<u16>().unwrap()),
+            symbols: matches.value_of("symbols").map(String::from),
+            breakpoints: matches
+                .values_of("break")
+                .map(|b| b.map(String::from).collect())
+                .unwrap_or_default(),
+            console: matches.is_present("console"),
+            cheat_dir: matches.value_of("cheat-dir").map(String::from),
             c48_mode: matches.is_present("c48"),
+            fx1e_overflow: matches.is_present("fx1e-overflow"),
+            vip_timing: matches.is_present("vip-timing"),
+            show_fps: matches.is_present("show-fps"),
+            show_registers: matches.is_present("show-registers"),
+            tone_frequency: Config::set_tone_frequency(matches),
+            waveform: Config::set_waveform(matches),
+            volume: Config::set_volume(matches),
+            wav_output: matches.value_of("wav-output").map(String::from),
+            trace: Config::set_trace(matches),
+            profile: matches.value_of("profile").map(String::from),
+            check_memory: matches.is_present("check-memory"),
+            font: matches.value_of("font").map(String::from),
+            font_addr: matches
+                .value_of("font-address")
+                .map(|a| parse_font_address(a).unwrap())
+                .unwrap_or(FONT_ADDR),
+            zip_entry: matches.value_of("zip-entry").map(String::from),
+            load_addr: load_address(matches),
+            entry: matches.value_of("entry").map(|a| parse_address(a).unwrap()),
+            blobs: matches
+                .values_of("blob")
+                .map(|b| b.map(|b| parse_blob(b).unwrap()).collect())
+                .unwrap_or_default(),
         }
     }
 
     // Assuming each instruction takes 2 cycles.
+    pub fn instructions_per_second(&self) -> u32 {
+        self.emu_speed / 2
+    }
+
+    // Instructions executed during a single timer period (1/60 s).
     pub fn instructions_per_cycle(&self) -> u32 {
-        return (self.emu_speed / MAIN_LOOP_FREQUENCY) / 2;
+        self.instructions_per_second() / TIMER_FREQUENCY
+    }
+
+    pub fn set_instructions_per_cycle(&mut self, ipf: u32) {
+        self.emu_speed = ipf * TIMER_FREQUENCY * 2;
     }
 
     fn set_color(m: &clap::ArgMatches<'_>, arg: &str) -> Option<Color> {
         match m.value_of(arg) {
             Some(v) => {
-                let rgb: Vec<&str> = v.split(",").collect();
+                let rgb: Vec<&str> = v.split(',').collect();
                 Some(Color::RGB(
                     rgb[0].parse::<u8>().unwrap(),
                     rgb[1].parse::<u8>().unwrap(),
@@ -89,9 +154,95 @@ impl Config {
     }
 
     fn set_emu_frequency(m: &clap::ArgMatches<'_>) -> u32 {
+        if let Some(ipf) = m.value_of("ipf") {
+            return ipf.parse::<u32>().unwrap() * TIMER_FREQUENCY * 2;
+        }
+
         match m.value_of("freq") {
             Some(v) => v.parse::<u32>().unwrap(),
             _ => DEFAULT_EMU_FREQUENCY,
         }
     }
+
+    fn set_tone_frequency(m: &clap::ArgMatches<'_>) -> f32 {
+        match m.value_of("tone-freq") {
+            Some(v) => v.parse::<f32>().unwrap(),
+            _ => DEFAULT_TONE_FREQUENCY,
+        }
+    }
+
+    fn set_waveform(m: &clap::ArgMatches<'_>) -> Waveform {
+        match m.value_of("waveform") {
+            Some(v) => v.parse::<Waveform>().unwrap(),
+            _ => Waveform::Square,
+        }
+    }
+
+    fn set_volume(m: &clap::ArgMatches<'_>) -> u8 {
+        match m.value_of("volume") {
+            Some(v) => v.parse::<u8>().unwrap(),
+            _ => DEFAULT_VOLUME,
+        }
+    }
+
+    fn set_trace(m: &clap::ArgMatches<'_>) -> Option<TraceConfig> {
+        let path = m.value_of("trace")?;
+
+        Some(TraceConfig {
+            path: path.to_string(),
+            format: match m.value_of("trace-format") {
+                Some(v) => v.parse::<TraceFormat>().unwrap(),
+                _ => TraceFormat::Text,
+            },
+            filter: TraceFilter {
+                range: m
+                    .value_of("trace-range")
+                    .map(|r| parse_address_range(r).unwrap()),
+                opcode_classes: m
+                    .value_of("trace-opcodes")
+                    .map(|c| parse_opcode_classes(c).unwrap()),
+            },
+        })
+    }
+}
+
+// Parses a memory address in hex, such as "200" or "0x200".
+// Address the program is loaded at, given by `--load-addr` or `--eti-660`;
+// the lint and cfg subcommands take these as well.
+pub fn load_address(m: &clap::ArgMatches<'_>) -> u16 {
+    if m.is_present("eti-660") {
+        return ETI_660_PROGRAM_START;
+    }
+
+    match m.value_of("load-addr") {
+        Some(a) => parse_address(a).unwrap(),
+        _ => PROGRAM_START,
+    }
+}
+
+pub fn parse_address(s: &str) -> Result<u16, String> {
+    match u16::from_str_radix(s.trim_start_matches("0x"), 16) {
+        Ok(addr) if addr < 0x1000 => Ok(addr),
+        _ => Err(format!("invalid address '{}'", s)),
+    }
+}
+
+// Parses the font address; the font has to fit into the interpreter area below 0x200.
+pub fn parse_font_address(s: &str) -> Result<u16, String> {
+    let addr = parse_address(s)?;
+    if addr as usize + FONT_SIZE > 0x200 {
+        return Err(format!(
+            "font at {:03X} doesn't fit below 200, it takes {} bytes",
+            addr, FONT_SIZE
+        ));
+    }
+    Ok(addr)
+}
+
+// Parses a blob to be loaded, given as the address in hex and the path, e.g. "300:sprites.bin".
+pub fn parse_blob(s: &str) -> Result<(u16, String), String> {
+    match s.split_once(':') {
+        Some((addr, path)) if !path.is_empty() => Ok((parse_address(addr)?, path.to_string())),
+        _ => Err(format!("invalid blob '{}', expected ADDR:FILE", s)),
+    }
 }
@@ -0,0 +1,145 @@
+use std::io::BufRead;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use crate::interpreter::cheats::{parse_cheat, Cheat};
+use crate::interpreter::search::{Filter, Location};
+use crate::interpreter::symbols::Symbols;
+
+pub const CONSOLE_HELP: &str = "
+Console commands:
+- search - starts a new search over all registers and memory
+- search = | != | > | < - keeps values unchanged / changed / increased / decreased since the last step
+- search VALUE - keeps values equal to VALUE
+- list - shows the search candidates
+- pin LOCATION VALUE - keeps LOCATION (V0-VF, label or hex address) at VALUE
+- unpin LOCATION - stops keeping LOCATION at its value
+- pins - shows the pinned values
+- cheats - lists the cheats of the ROM
+- cheat N - enables / disables N-th cheat
+- cheat add NAME: EFFECTS - adds an enabled cheat, e.g. 'cheat add Lives: 3F0=9, patch 200=1240'
+- help - shows this help
+Values are decimal, or hex with the 0x prefix.";
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Search,
+    Filter(Filter),
+    List,
+    Pin(Location, u8),
+    Unpin(Location),
+    Pins,
+    Cheats,
+    ToggleCheat(usize),
+    AddCheat(Cheat),
+    Help,
+}
+
+// Reads debug commands from the standard input in the background,
+// so the emulation keeps running while waiting for them.
+pub struct Console {
+    lines: Receiver<String>,
+}
+
+impl Console {
+    pub fn spawn() -> Console {
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            for line in std::io::stdin().lock().lines() {
+                let sent = match line {
+                    Ok(l) => tx.send(l).is_ok(),
+                    Err(_) => false,
+                };
+                if !sent {
+                    break;
+                }
+            }
+        });
+
+        Console { lines: rx }
+    }
+
+    // Lines entered since the last call.
+    pub fn pending(&self) -> Vec<String> {
+        self.lines.try_iter().collect()
+    }
+}
+
+pub fn parse_command(line: &str, symbols: &Symbols) -> Result<Command, String> {
+    if let Some(cheat) = line.trim().strip_prefix("cheat add ") {
+        return Ok(Command::AddCheat(parse_cheat(cheat, true, symbols)?));
+    }
+
+    let args: Vec<&str> = line.split_whitespace().collect();
+    let location =
+        |s: &str| Location::parse(s, symbols).ok_or_else(|| format!("unknown location '{}'", s));
+
+    match args[..] {
+        ["search"] => Ok(Command::Search),
+        ["search", "="] => Ok(Command::Filter(Filter::Unchanged)),
+        ["search", "!="] => Ok(Command::Filter(Filter::Changed)),
+        ["search", ">"] => Ok(Command::Filter(Filter::Increased)),
+        ["search", "<"] => Ok(Command::Filter(Filter::Decreased)),
+        ["search", val] => Ok(Command::Filter(Filter::Value(parse_value(val)?))),
+        ["list"] => Ok(Command::List),
+        ["pin", loc, val] => Ok(Command::Pin(location(loc)?, parse_value(val)?)),
+        ["unpin", loc] => Ok(Command::Unpin(location(loc)?)),
+        ["pins"] => Ok(Command::Pins),
+        ["cheats"] => Ok(Command::Cheats),
+        ["cheat", n] => match n.parse::<usize>() {
+            Ok(n) if n > 0 => Ok(Command::ToggleCheat(n - 1)),
+            _ => Err(format!("invalid cheat number '{}'", n)),
+        },
+        ["help"] => Ok(Command::Help),
+        _ => Err(format!(
+            "unknown command '{}'; type 'help' for help",
+            line.trim()
+        )),
+    }
+}
+
+// A byte value, decimal or hex with the 0x prefix.
+pub fn parse_value(s: &str) -> Result<u8, String> {
+    let parsed = match s.strip_prefix("0x") {
+        Some(hex) => u8::from_str_radix(hex, 16),
+        None => s.parse::<u8>(),
+    };
+    parsed.map_err(|e| format!("invalid value '{}': {}", s, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_command, Command};
+    use crate::interpreter::search::{Filter, Location};
+    use crate::interpreter::symbols::Symbols;
+
+    #[test]
+    fn commands_are_parsed() {
+        let symbols = Symbols::parse("lives 3F0").unwrap();
+        let parse = |l| parse_command(l, &symbols);
+
+        assert_eq!(parse("search"), Ok(Command::Search));
+        assert_eq!(parse(" search  > "), Ok(Command::Filter(Filter::Increased)));
+        assert_eq!(parse("search 0x10"), Ok(Command::Filter(Filter::Value(16))));
+        assert_eq!(
+            parse("pin lives 9"),
+            Ok(Command::Pin(Location::Memory(0x3F0), 9))
+        );
+        assert_eq!(
+            parse("unpin VF"),
+            Ok(Command::Unpin(Location::Register(15)))
+        );
+
+        assert_eq!(parse("cheat 2"), Ok(Command::ToggleCheat(1)));
+        match parse("cheat add Lives: V3=9") {
+            Ok(Command::AddCheat(c)) => assert_eq!(c.name, "Lives"),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        assert!(parse("search 256").is_err());
+        assert!(parse("cheat 0").is_err());
+        assert!(parse("pin nowhere 1").is_err());
+        assert!(parse("").is_err());
+    }
+}
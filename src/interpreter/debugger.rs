@@ -0,0 +1,247 @@
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+use sdl2::VideoSubsystem;
+
+use crate::interpreter::chip8::{disassemble, Chip8};
+use crate::interpreter::osd::{draw_text, register_lines, GLYPH_HEIGHT, GLYPH_WIDTH};
+use crate::interpreter::symbols::Symbols;
+use crate::InterpErr;
+
+const WINDOW_WIDTH: u32 = 1000;
+const WINDOW_HEIGHT: u32 = 740;
+
+// Text is drawn with the OSD font, at twice its size.
+const SCALE: u32 = 2;
+const CHAR_WIDTH: i32 = ((GLYPH_WIDTH + 1) * SCALE) as i32;
+const LINE_HEIGHT: i32 = ((GLYPH_HEIGHT + 3) * SCALE) as i32;
+const MARGIN: i32 = 10;
+
+// Instructions shown before and after the program counter.
+const DISASSEMBLY_CONTEXT: i32 = 8;
+
+// Deeper calls are cut off in the call stack pane.
+const MAX_STACK_LINES: usize = 8;
+
+// Memory rows (of 16 bytes) shown, and how many precede the one containing I.
+const MEMORY_ROWS: i32 = 16;
+const MEMORY_ROWS_BEFORE_I: i32 = 4;
+
+// Bytes highlighted from I onwards; covers the largest sprite and FX55 / FX65.
+const I_REGION_LEN: usize = 16;
+
+// Size of a single sprite point in the sprite pane.
+const SPRITE_POINT: u32 = 8;
+
+const BACKGROUND_COLOR: Color = Color::RGB(16, 16, 24);
+const TEXT_COLOR: Color = Color::RGB(220, 220, 220);
+const HEADER_COLOR: Color = Color::RGB(0, 255, 102);
+const HIGHLIGHT_COLOR: Color = Color::RGB(60, 60, 110);
+
+// Second window with the live state of the machine:
+// disassembly around the program counter, registers,
+// call stack, memory around I and the sprite at I.
+pub struct DebuggerWindow {
+    canvas: Canvas<Window>,
+}
+
+impl DebuggerWindow {
+    pub fn new(video_subsys: &VideoSubsystem) -> Result<DebuggerWindow, InterpErr> {
+        let win = video_subsys
+            .window(
+                &format!("{} - debugger", crate_name!()),
+                WINDOW_WIDTH,
+                WINDOW_HEIGHT,
+            )
+            .build()?;
+
+        Ok(DebuggerWindow {
+            canvas: win.into_canvas().software().build()?,
+        })
+    }
+
+    pub fn window_id(&self) -> u32 {
+        self.canvas.window().id()
+    }
+
+    pub fn render(&mut self, machine: &Chip8, symbols: &Symbols) -> Result<(), InterpErr> {
+        self.canvas.set_draw_color(BACKGROUND_COLOR);
+        self.canvas.clear();
+
+        let column = WINDOW_WIDTH as i32 / 2;
+        let memory_top = MARGIN + LINE_HEIGHT * (2 * DISASSEMBLY_CONTEXT + 3);
+
+        self.render_disassembly(machine, symbols, MARGIN, MARGIN)?;
+        let y = self.render_registers(machine, column, MARGIN)? + LINE_HEIGHT;
+        self.render_stack(machine, symbols, column, y)?;
+        self.render_sprite(machine, column + WINDOW_WIDTH as i32 / 4, y)?;
+        self.render_memory(machine, MARGIN, memory_top)?;
+
+        self.canvas.present();
+        Ok(())
+    }
+
+    fn render_disassembly(
+        &mut self,
+        machine: &Chip8,
+        symbols: &Symbols,
+        x: i32,
+        y: i32,
+    ) -> Result<(), InterpErr> {
+        let pc = machine.registers().pc as i32;
+        match symbols.source(pc as u16) {
+            Some(source) => self.header(&format!("DISASSEMBLY  {}", source), x, y)?,
+            None => self.header("DISASSEMBLY", x, y)?,
+        }
+
+        let memory = machine.memory();
+
+        for n in -DISASSEMBLY_CONTEXT..=DISASSEMBLY_CONTEXT {
+            let addr = pc + 2 * n;
+            let line_y = y + LINE_HEIGHT * (n + DISASSEMBLY_CONTEXT + 1);
+            if addr < 0 || addr as usize + 1 >= memory.len() {
+                continue;
+            }
+
+            let raw = u16::from_be_bytes([memory[addr as usize], memory[addr as usize + 1]]);
+            if n == 0 {
+                self.highlight(x, line_y, 39)?;
+            }
+
+            let instr = disassemble(raw, |a| symbols.label(a));
+            let line = match symbols.label(addr as u16) {
+                Some(label) => format!("{:03X}  {:04X}  {}: {}", addr, raw, label, instr),
+                None => format!("{:03X}  {:04X}  {}", addr, raw, instr),
+            };
+            self.text(&line, x, line_y)?;
+        }
+
+        Ok(())
+    }
+
+    // Returns y of the line following the pane.
+    fn render_registers(&mut self, machine: &Chip8, x: i32, y: i32) -> Result<i32, InterpErr> {
+        self.header("REGISTERS", x, y)?;
+
+        let lines = register_lines(&machine.registers());
+        for (n, line) in lines.iter().enumerate() {
+            self.text(line, x, y + LINE_HEIGHT * (n as i32 + 1))?;
+        }
+
+        Ok(y + LINE_HEIGHT * (lines.len() as i32 + 1))
+    }
+
+    fn render_stack(
+        &mut self,
+        machine: &Chip8,
+        symbols: &Symbols,
+        x: i32,
+        y: i32,
+    ) -> Result<(), InterpErr> {
+        self.header("CALL STACK", x, y)?;
+
+        let stack = machine.stack();
+        if stack.is_empty() {
+            return self.text("(empty)", x, y + LINE_HEIGHT);
+        }
+
+        // innermost call first
+        for (n, ret) in stack.iter().rev().take(MAX_STACK_LINES).enumerate() {
+            let line = match symbols.nearest_label(*ret) {
+                Some(label) => format!("#{:<2} {:03X} {}", n, ret, label),
+                None => format!("#{:<2} {:03X}", n, ret),
+            };
+            self.text(&line, x, y + LINE_HEIGHT * (n as i32 + 1))?;
+        }
+        if stack.len() > MAX_STACK_LINES {
+            let line_y = y + LINE_HEIGHT * (MAX_STACK_LINES as i32 + 1);
+            self.text("...", x, line_y)?;
+        }
+
+        Ok(())
+    }
+
+    fn render_sprite(&mut self, machine: &Chip8, x: i32, y: i32) -> Result<(), InterpErr> {
+        self.header("SPRITE AT I", x, y)?;
+
+        let i = machine.registers().i as usize;
+        let top = y + LINE_HEIGHT;
+        let mut points = Vec::new();
+
+        for (row, byte) in machine.memory().iter().skip(i).take(15).enumerate() {
+            for col in 0..8 {
+                if byte & (0x80 >> col) != 0 {
+                    points.push(Rect::new(
+                        x + (col * SPRITE_POINT) as i32,
+                        top + (row as u32 * SPRITE_POINT) as i32,
+                        SPRITE_POINT - 1,
+                        SPRITE_POINT - 1,
+                    ));
+                }
+            }
+        }
+
+        self.canvas.set_draw_color(HIGHLIGHT_COLOR);
+        self.canvas.draw_rect(Rect::new(
+            x - 1,
+            top - 1,
+            8 * SPRITE_POINT + 1,
+            15 * SPRITE_POINT + 1,
+        ))?;
+        self.canvas.set_draw_color(HEADER_COLOR);
+        self.canvas.fill_rects(&points)?;
+        Ok(())
+    }
+
+    fn render_memory(&mut self, machine: &Chip8, x: i32, y: i32) -> Result<(), InterpErr> {
+        self.header("MEMORY", x, y)?;
+
+        let memory = machine.memory();
+        let i = machine.registers().i as usize;
+        let last_row = (memory.len() / 16) as i32 - MEMORY_ROWS;
+        let first_row = (i as i32 / 16 - MEMORY_ROWS_BEFORE_I).clamp(0, last_row);
+
+        for n in 0..MEMORY_ROWS {
+            let addr = ((first_row + n) * 16) as usize;
+            let line_y = y + LINE_HEIGHT * (n + 1);
+
+            // highlight the bytes I points to
+            for offset in 0..16 {
+                if (i..i + I_REGION_LEN).contains(&(addr + offset)) {
+                    self.highlight(x + CHAR_WIDTH * (5 + 3 * offset as i32), line_y, 2)?;
+                }
+            }
+
+            let bytes: Vec<String> = memory[addr..addr + 16]
+                .iter()
+                .map(|b| format!("{:02X}", b))
+                .collect();
+            self.text(&format!("{:03X}: {}", addr, bytes.join(" ")), x, line_y)?;
+        }
+
+        Ok(())
+    }
+
+    fn header(&mut self, title: &str, x: i32, y: i32) -> Result<(), InterpErr> {
+        self.canvas.set_draw_color(HEADER_COLOR);
+        draw_text(&mut self.canvas, title, SCALE, x, y)
+    }
+
+    fn text(&mut self, text: &str, x: i32, y: i32) -> Result<(), InterpErr> {
+        self.canvas.set_draw_color(TEXT_COLOR);
+        draw_text(&mut self.canvas, text, SCALE, x, y)
+    }
+
+    // Highlights a line area of given length (in characters).
+    fn highlight(&mut self, x: i32, y: i32, chars: i32) -> Result<(), InterpErr> {
+        self.canvas.set_draw_color(HIGHLIGHT_COLOR);
+        self.canvas.fill_rect(Rect::new(
+            x - SCALE as i32,
+            y - SCALE as i32,
+            (chars * CHAR_WIDTH) as u32,
+            LINE_HEIGHT as u32 - SCALE,
+        ))?;
+        Ok(())
+    }
+}
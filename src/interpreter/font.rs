@@ -0,0 +1,225 @@
+use std::str::FromStr;
+
+use crate::InterpErr;
+
+// 16 small characters of 5 bytes each, and 16 large digits of 10 bytes each.
+pub const SMALL_FONT_SIZE: usize = 0x10 * 5;
+pub const BIG_FONT_SIZE: usize = 0x10 * 10;
+
+// Bytes taken in memory by a font, the large digits following the small characters.
+pub const FONT_SIZE: usize = SMALL_FONT_SIZE + BIG_FONT_SIZE;
+
+// Fonts of the interpreters of the CHIP-8 family.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FontSet {
+    Vip,
+    Chip48,
+    Schip,
+    Octo,
+    Dream6800,
+}
+
+impl FromStr for FontSet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<FontSet, String> {
+        match s {
+            "vip" => Ok(FontSet::Vip),
+            "chip48" => Ok(FontSet::Chip48),
+            "schip" => Ok(FontSet::Schip),
+            "octo" => Ok(FontSet::Octo),
+            "dream6800" => Ok(FontSet::Dream6800),
+            _ => Err(format!("unknown font '{}'", s)),
+        }
+    }
+}
+
+// Hexadecimal characters used by FX29, and large digits used by FX30.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Font {
+    pub small: [u8; SMALL_FONT_SIZE],
+    pub big: [u8; BIG_FONT_SIZE],
+}
+
+impl Default for Font {
+    fn default() -> Font {
+        Font::builtin(FontSet::Schip)
+    }
+}
+
+impl Font {
+    // Fonts without large digits of their own get those of SCHIP.
+    pub fn builtin(set: FontSet) -> Font {
+        let (small, big) = match set {
+            FontSet::Vip => (&VIP, &SCHIP_BIG),
+            FontSet::Chip48 | FontSet::Schip => (&CHIP48, &SCHIP_BIG),
+            FontSet::Octo => (&CHIP48, &OCTO_BIG),
+            FontSet::Dream6800 => (&DREAM6800, &SCHIP_BIG),
+        };
+        Font {
+            small: *small,
+            big: *big,
+        }
+    }
+
+    // A font file holds the small characters, optionally followed by the
+    // large digits: 10 of them (as in SCHIP) or 16.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Font, String> {
+        let mut font = Font::default();
+        let (small, big) = bytes.split_at(bytes.len().min(SMALL_FONT_SIZE));
+
+        match big.len() {
+            _ if small.len() < SMALL_FONT_SIZE => {
+                return Err(format!(
+                    "font is {} bytes, the small characters take {}",
+                    bytes.len(),
+                    SMALL_FONT_SIZE
+                ))
+            }
+            0 => {}
+            100 => {
+                font.big = [0; BIG_FONT_SIZE];
+                font.big[..100].copy_from_slice(big);
+            }
+            BIG_FONT_SIZE => font.big.copy_from_slice(big),
+            _ => {
+                return Err(format!(
+                    "font is {} bytes, expected {}, {} or {}",
+                    bytes.len(),
+                    SMALL_FONT_SIZE,
+                    SMALL_FONT_SIZE + 100,
+                    FONT_SIZE
+                ))
+            }
+        }
+        font.small.copy_from_slice(small);
+
+        Ok(font)
+    }
+
+    // Loads a built-in font by name, or a font file.
+    pub fn load(name_or_path: &str) -> Result<Font, InterpErr> {
+        match name_or_path.parse::<FontSet>() {
+            Ok(set) => Ok(Font::builtin(set)),
+            Err(_) => {
+                let bytes =
+                    std::fs::read(name_or_path).map_err(|e| format!("{}: {}", name_or_path, e))?;
+                Ok(Font::from_bytes(&bytes).map_err(|e| format!("{}: {}", name_or_path, e))?)
+            }
+        }
+    }
+}
+
+const VIP: [u8; SMALL_FONT_SIZE] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x60, 0x20, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0xA0, 0xA0, 0xF0, 0x20, 0x20, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x10, 0x10, 0x10, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xF0, 0x50, 0x70, 0x50, 0xF0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xF0, 0x50, 0x50, 0x50, 0xF0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+// Also used by SCHIP and Octo.
+const CHIP48: [u8; SMALL_FONT_SIZE] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+const DREAM6800: [u8; SMALL_FONT_SIZE] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+    0x40, 0x40, 0x40, 0x40, 0x40, // 1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+    0x80, 0xA0, 0xA0, 0xE0, 0x20, // 4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+    0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0xC0, 0xA0, 0xE0, 0xA0, 0xC0, // B
+    0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+    0xC0, 0xA0, 0xA0, 0xA0, 0xC0, // D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+// SCHIP has large digits 0 - 9 only.
+const SCHIP_BIG: [u8; BIG_FONT_SIZE] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+];
+
+const OCTO_BIG: [u8; BIG_FONT_SIZE] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+#[cfg(test)]
+mod tests {
+    use super::{Font, FontSet, SMALL_FONT_SIZE};
+
+    #[test]
+    fn font_files_are_checked() {
+        let small = [0xAA; SMALL_FONT_SIZE];
+        let font = Font::from_bytes(&small).unwrap();
+        assert_eq!(font.small, small);
+        assert_eq!(font.big, Font::builtin(FontSet::Schip).big);
+
+        let mut schip = small.to_vec();
+        schip.extend_from_slice(&[0x55; 100]);
+        let font = Font::from_bytes(&schip).unwrap();
+        assert_eq!((font.big[99], font.big[100]), (0x55, 0));
+
+        assert!(Font::from_bytes(&small[..79]).is_err());
+        assert!(Font::from_bytes(&schip[..120]).is_err());
+    }
+}
@@ -0,0 +1,584 @@
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use crate::interpreter::breakpoints::Breakpoints;
+use crate::interpreter::chip8::Chip8;
+use crate::interpreter::loader::decode_hex;
+use crate::InterpErr;
+
+// Signals reported to GDB when the target stops.
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+
+// Register numbers, as given in the target description:
+// V0 - VF, followed by I, PC, DT and ST.
+const REG_I: usize = 16;
+const REG_PC: usize = 17;
+const REG_DT: usize = 18;
+const REG_ST: usize = 19;
+const REG_COUNT: usize = 20;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.yarchi.chip8.core">
+    <reg name="v0" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="v1" bitsize="8" type="uint8"/>
+    <reg name="v2" bitsize="8" type="uint8"/>
+    <reg name="v3" bitsize="8" type="uint8"/>
+    <reg name="v4" bitsize="8" type="uint8"/>
+    <reg name="v5" bitsize="8" type="uint8"/>
+    <reg name="v6" bitsize="8" type="uint8"/>
+    <reg name="v7" bitsize="8" type="uint8"/>
+    <reg name="v8" bitsize="8" type="uint8"/>
+    <reg name="v9" bitsize="8" type="uint8"/>
+    <reg name="va" bitsize="8" type="uint8"/>
+    <reg name="vb" bitsize="8" type="uint8"/>
+    <reg name="vc" bitsize="8" type="uint8"/>
+    <reg name="vd" bitsize="8" type="uint8"/>
+    <reg name="ve" bitsize="8" type="uint8"/>
+    <reg name="vf" bitsize="8" type="uint8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="dt" bitsize="8" type="uint8"/>
+    <reg name="st" bitsize="8" type="uint8"/>
+  </feature>
+</target>
+"#;
+
+// What the interpreter should do after handling GDB's requests.
+#[derive(Debug, PartialEq)]
+pub enum GdbEvent {
+    None,
+    // execute a single instruction, then report the stop
+    Step,
+    // revert a single instruction, then report the stop
+    ReverseStep,
+    // revert instructions until a breakpoint, then report the stop
+    ReverseContinue,
+    Kill,
+    Detach,
+}
+
+#[derive(Debug, PartialEq)]
+enum Action {
+    Reply(String),
+    Continue,
+    Step,
+    ReverseStep,
+    ReverseContinue,
+    Kill,
+    Detach,
+}
+
+// Server side of the GDB remote serial protocol, over a TCP connection.
+// The machine is halted until GDB continues or steps it.
+pub struct GdbStub {
+    stream: TcpStream,
+    buf: Vec<u8>,
+    session: Session,
+    halted: bool,
+}
+
+struct Session {
+    breakpoints: Breakpoints,
+    no_ack: bool,
+}
+
+impl GdbStub {
+    pub fn listen(port: u16) -> Result<GdbStub, InterpErr> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        println!("Waiting for GDB connection on port {}...", port);
+
+        let (stream, addr) = listener.accept()?;
+        stream.set_nodelay(true)?;
+        println!("GDB connected from {}", addr);
+
+        Ok(GdbStub {
+            stream,
+            buf: Vec::new(),
+            session: Session {
+                breakpoints: Breakpoints::default(),
+                no_ack: false,
+            },
+            halted: true,
+        })
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    // Handles all pending requests from GDB.
+    pub fn poll(&mut self, machine: &mut Chip8) -> Result<GdbEvent, InterpErr> {
+        if !self.receive()? {
+            println!("GDB disconnected");
+            return Ok(GdbEvent::Detach);
+        }
+
+        while !self.buf.is_empty() {
+            match self.buf[0] {
+                b'+' | b'-' => {
+                    self.buf.remove(0);
+                }
+                // Ctrl-C
+                0x03 => {
+                    self.buf.remove(0);
+                    if !self.halted {
+                        self.stop(SIGINT, false)?;
+                    }
+                }
+                b'$' => {
+                    let (packet, len) = match parse_packet(&self.buf) {
+                        Some(p) => p,
+                        None => break, // incomplete, wait for the rest
+                    };
+                    self.buf.drain(..len);
+
+                    let packet = match packet {
+                        Some(p) => p,
+                        None => {
+                            self.stream.write_all(b"-")?;
+                            continue;
+                        }
+                    };
+                    if !self.session.no_ack {
+                        self.stream.write_all(b"+")?;
+                    }
+
+                    match self.session.handle(&packet, machine) {
+                        Action::Reply(r) => self.send(&r)?,
+                        Action::Continue => {
+                            self.halted = false;
+                            self.session.breakpoints.resume();
+                        }
+                        Action::Step => return Ok(GdbEvent::Step),
+                        Action::ReverseStep => return Ok(GdbEvent::ReverseStep),
+                        Action::ReverseContinue => return Ok(GdbEvent::ReverseContinue),
+                        Action::Kill => return Ok(GdbEvent::Kill),
+                        Action::Detach => {
+                            self.send("OK")?;
+                            return Ok(GdbEvent::Detach);
+                        }
+                    }
+                }
+                // garbage between packets
+                _ => {
+                    self.buf.remove(0);
+                }
+            }
+        }
+
+        Ok(GdbEvent::None)
+    }
+
+    // Called before each instruction while running; halts
+    // and notifies GDB if a breakpoint is hit at given address.
+    pub fn check_breakpoint(&mut self, pc: u16) -> Result<bool, InterpErr> {
+        if !self.session.breakpoints.hit(pc) {
+            return Ok(false);
+        }
+
+        self.stop(SIGTRAP, true)?;
+        Ok(true)
+    }
+
+    pub fn is_breakpoint(&self, pc: u16) -> bool {
+        self.session.breakpoints.contains(pc)
+    }
+
+    // Notifies GDB that a step (forward or backward) is done,
+    // or a reverse-continue stopped at a breakpoint.
+    pub fn report_stop(&mut self, breakpoint: bool) -> Result<(), InterpErr> {
+        Ok(self.stop(SIGTRAP, breakpoint)?)
+    }
+
+    fn stop(&mut self, signal: u8, breakpoint: bool) -> io::Result<()> {
+        self.halted = true;
+        self.send(&stop_reply(signal, breakpoint))
+    }
+
+    // Reads everything available; returns false if the connection was closed.
+    fn receive(&mut self) -> io::Result<bool> {
+        self.stream.set_nonblocking(true)?;
+        let mut chunk = [0u8; 4096];
+
+        let result = loop {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => break Ok(false),
+                Ok(n) => self.buf.extend_from_slice(&chunk[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break Ok(true),
+                Err(e) => break Err(e),
+            }
+        };
+
+        self.stream.set_nonblocking(false)?;
+        result
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        self.stream
+            .write_all(format!("${}#{:02x}", data, checksum(data.as_bytes())).as_bytes())
+    }
+}
+
+impl Session {
+    fn handle(&mut self, packet: &str, machine: &mut Chip8) -> Action {
+        let reply = |r: &str| Action::Reply(r.to_string());
+        let (cmd, args) = packet.split_at(packet.chars().next().map_or(0, |c| c.len_utf8()));
+
+        match cmd {
+            "?" => Action::Reply(stop_reply(SIGTRAP, false)),
+            "g" => Action::Reply(read_registers(machine)),
+            "G" => match write_registers(machine, args) {
+                Some(_) => reply("OK"),
+                None => reply("E01"),
+            },
+            "p" => match usize::from_str_radix(args, 16)
+                .ok()
+                .and_then(|n| read_register(machine, n))
+            {
+                Some(r) => Action::Reply(r),
+                None => reply("E01"),
+            },
+            "P" => match write_register(machine, args) {
+                Some(_) => reply("OK"),
+                None => reply("E01"),
+            },
+            "m" => match read_memory(machine, args) {
+                Some(r) => Action::Reply(r),
+                None => reply("E01"),
+            },
+            "M" => match write_memory(machine, args) {
+                Some(_) => reply("OK"),
+                None => reply("E01"),
+            },
+            "c" | "s" => {
+                // optional address to resume at
+                if let Ok(addr) = u16::from_str_radix(args, 16) {
+                    let mut regs = machine.registers();
+                    regs.pc = addr;
+                    machine.set_registers(&regs);
+                }
+                if cmd == "c" {
+                    Action::Continue
+                } else {
+                    Action::Step
+                }
+            }
+            "Z" | "z" => match self.set_breakpoint(args, cmd == "Z") {
+                Some(_) => reply("OK"),
+                None => reply(""),
+            },
+            "b" => match args {
+                "s" => Action::ReverseStep,
+                "c" => Action::ReverseContinue,
+                _ => reply(""),
+            },
+            "k" => Action::Kill,
+            "D" => Action::Detach,
+            "H" => reply("OK"),
+            "q" | "Q" | "v" => self.handle_query(packet),
+            _ => reply(""),
+        }
+    }
+
+    fn handle_query(&mut self, packet: &str) -> Action {
+        let reply = |r: &str| Action::Reply(r.to_string());
+
+        if packet.starts_with("qSupported") {
+            return reply("PacketSize=1000;qXfer:features:read+;swbreak+;QStartNoAckMode+;ReverseStep+;ReverseContinue+");
+        }
+        if let Some(args) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            return match read_chunk(TARGET_XML, args) {
+                Some(r) => Action::Reply(r),
+                None => reply("E01"),
+            };
+        }
+
+        match packet {
+            "QStartNoAckMode" => {
+                self.no_ack = true;
+                reply("OK")
+            }
+            "qAttached" => reply("1"),
+            "qC" => reply("QC1"),
+            "qfThreadInfo" => reply("m1"),
+            "qsThreadInfo" => reply("l"),
+            "vCont?" => reply("vCont;c;s"),
+            "vCont;c" | "vCont;c:1" => Action::Continue,
+            "vCont;s" | "vCont;s:1" => Action::Step,
+            "vKill;1" => Action::Kill,
+            _ => reply(""),
+        }
+    }
+
+    // Z0 / Z1 (software / hardware) breakpoints are treated alike.
+    fn set_breakpoint(&mut self, args: &str, insert: bool) -> Option<()> {
+        let mut parts = args.split(',');
+        let kind = parts.next()?;
+        if kind != "0" && kind != "1" {
+            return None;
+        }
+
+        let addr = u16::from_str_radix(parts.next()?, 16).ok()?;
+        if insert {
+            self.breakpoints.insert(addr);
+        } else {
+            self.breakpoints.remove(addr);
+        }
+        Some(())
+    }
+}
+
+// Parses the packet at the start of the buffer. Returns None when the
+// packet is not complete yet, otherwise its contents (None when the
+// checksum doesn't match) and the number of bytes it took.
+fn parse_packet(buf: &[u8]) -> Option<(Option<String>, usize)> {
+    let end = buf.iter().position(|&b| b == b'#')?;
+    if buf.len() < end + 3 {
+        return None;
+    }
+
+    let data = &buf[1..end];
+    let expected = std::str::from_utf8(&buf[end + 1..end + 3])
+        .ok()
+        .and_then(|cs| u8::from_str_radix(cs, 16).ok());
+
+    let packet = match expected {
+        Some(cs) if cs == checksum(data) => Some(String::from_utf8_lossy(data).into_owned()),
+        _ => None,
+    };
+    Some((packet, end + 3))
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |acc, &b| acc.wrapping_add(b))
+}
+
+// Stops at breakpoints are reported as such, as promised by "swbreak+".
+fn stop_reply(signal: u8, breakpoint: bool) -> String {
+    if breakpoint {
+        format!("T{:02x}swbreak:;", signal)
+    } else {
+        format!("S{:02x}", signal)
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Registers are transferred in target (little endian) byte order.
+fn register_bytes(machine: &Chip8, n: usize) -> Option<Vec<u8>> {
+    let regs = machine.registers();
+    match n {
+        0..=15 => Some(vec![regs.v[n]]),
+        REG_I => Some(regs.i.to_le_bytes().to_vec()),
+        REG_PC => Some(regs.pc.to_le_bytes().to_vec()),
+        REG_DT => Some(vec![regs.delay_timer]),
+        REG_ST => Some(vec![regs.sound_timer]),
+        _ => None,
+    }
+}
+
+fn read_registers(machine: &Chip8) -> String {
+    (0..REG_COUNT)
+        .filter_map(|n| register_bytes(machine, n))
+        .map(|b| to_hex(&b))
+        .collect()
+}
+
+fn read_register(machine: &Chip8, n: usize) -> Option<String> {
+    register_bytes(machine, n).map(|b| to_hex(&b))
+}
+
+fn set_register(machine: &mut Chip8, n: usize, bytes: &[u8]) -> Option<()> {
+    let mut regs = machine.registers();
+    let word = || Some(u16::from_le_bytes([*bytes.first()?, *bytes.get(1)?]));
+
+    match n {
+        0..=15 => regs.v[n] = *bytes.first()?,
+        REG_I => regs.i = word()?,
+        REG_PC => regs.pc = word()?,
+        REG_DT => regs.delay_timer = *bytes.first()?,
+        REG_ST => regs.sound_timer = *bytes.first()?,
+        _ => return None,
+    }
+
+    machine.set_registers(&regs);
+    Some(())
+}
+
+fn write_registers(machine: &mut Chip8, hex: &str) -> Option<()> {
+    let bytes = decode_hex(hex)?;
+    let mut offset = 0;
+
+    for n in 0..REG_COUNT {
+        let len = register_bytes(machine, n)?.len();
+        set_register(machine, n, bytes.get(offset..offset + len)?)?;
+        offset += len;
+    }
+    Some(())
+}
+
+// P<n>=<value>
+fn write_register(machine: &mut Chip8, args: &str) -> Option<()> {
+    let (n, val) = args.split_once('=')?;
+    let n = usize::from_str_radix(n, 16).ok()?;
+    set_register(machine, n, &decode_hex(val)?)
+}
+
+// m<addr>,<length>
+fn read_memory(machine: &Chip8, args: &str) -> Option<String> {
+    let (addr, len) = args.split_once(',')?;
+    let addr = usize::from_str_radix(addr, 16).ok()?;
+    let len = usize::from_str_radix(len, 16).ok()?;
+
+    let memory = machine.memory();
+    let end = addr.checked_add(len)?.min(memory.len());
+    if addr >= memory.len() {
+        return None;
+    }
+    Some(to_hex(&memory[addr..end]))
+}
+
+// M<addr>,<length>:<bytes>
+fn write_memory(machine: &mut Chip8, args: &str) -> Option<()> {
+    let (range, data) = args.split_once(':')?;
+    let (addr, len) = range.split_once(',')?;
+    let addr = u16::from_str_radix(addr, 16).ok()?;
+    let len = usize::from_str_radix(len, 16).ok()?;
+
+    let bytes = decode_hex(data)?;
+    if bytes.len() != len || addr as usize + len > machine.memory().len() {
+        return None;
+    }
+
+    for (offset, b) in bytes.into_iter().enumerate() {
+        machine.poke(addr + offset as u16, b);
+    }
+    Some(())
+}
+
+// qXfer reads are done in chunks: <offset>,<length>
+fn read_chunk(doc: &str, args: &str) -> Option<String> {
+    let (offset, len) = args.split_once(',')?;
+    let offset = usize::from_str_radix(offset, 16).ok()?;
+    let len = usize::from_str_radix(len, 16).ok()?;
+
+    if offset >= doc.len() {
+        return Some("l".to_string());
+    }
+    // the length comes from the client, so it may be anything
+    let end = offset.saturating_add(len).min(doc.len());
+    let prefix = if end == doc.len() { "l" } else { "m" };
+    Some(format!("{}{}", prefix, &doc[offset..end]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{checksum, parse_packet, stop_reply, Action, Session, SIGTRAP};
+    use crate::interpreter::breakpoints::Breakpoints;
+    use crate::interpreter::chip8::Chip8;
+
+    fn session() -> Session {
+        Session {
+            breakpoints: Breakpoints::default(),
+            no_ack: false,
+        }
+    }
+
+    fn reply(s: &str) -> Action {
+        Action::Reply(s.to_string())
+    }
+
+    #[test]
+    fn packets_are_parsed() {
+        assert_eq!(checksum(b"g"), 0x67);
+        assert_eq!(parse_packet(b"$g#67+"), Some((Some("g".to_string()), 5)));
+        assert_eq!(parse_packet(b"$g#00"), Some((None, 5)));
+        assert_eq!(parse_packet(b"$m200,"), None);
+        assert_eq!(parse_packet(b"$g#6"), None);
+    }
+
+    #[test]
+    fn registers_are_read_and_written() {
+        let mut machine = Chip8::new(false);
+        let mut s = session();
+
+        assert_eq!(s.handle("P3=2a", &mut machine), reply("OK"));
+        assert_eq!(s.handle("P10=3412", &mut machine), reply("OK"));
+        assert_eq!(machine.registers().v[3], 0x2A);
+        assert_eq!(machine.registers().i, 0x1234);
+
+        assert_eq!(s.handle("p11", &mut machine), reply("0002"));
+        assert_eq!(
+            s.handle("g", &mut machine),
+            reply("0000002a000000000000000000000000341200020000")
+        );
+
+        let regs = "0102030405060708090a0b0c0d0e0f10" // V0 - VF
+            .to_string()
+            + "5000" // I
+            + "0402" // PC
+            + "0709"; // DT, ST
+        assert_eq!(s.handle(&format!("G{}", regs), &mut machine), reply("OK"));
+        assert_eq!(s.handle("g", &mut machine), Action::Reply(regs));
+        assert_eq!(machine.registers().pc, 0x204);
+        assert_eq!(machine.registers().sound_timer, 9);
+    }
+
+    #[test]
+    fn memory_is_read_and_written() {
+        let mut machine = Chip8::new(false);
+        let mut s = session();
+
+        assert_eq!(s.handle("M300,3:0a0b0c", &mut machine), reply("OK"));
+        assert_eq!(s.handle("m2ff,5", &mut machine), reply("000a0b0c00"));
+        assert_eq!(s.handle("M300,2:0a", &mut machine), reply("E01"));
+        assert_eq!(s.handle("m1000,1", &mut machine), reply("E01"));
+    }
+
+    #[test]
+    fn breakpoints_and_execution_control_are_handled() {
+        let mut machine = Chip8::new(false);
+        let mut s = session();
+
+        assert_eq!(s.handle("Z0,20a,2", &mut machine), reply("OK"));
+        assert!(s.breakpoints.contains(0x20A));
+        assert_eq!(s.handle("z0,20a,2", &mut machine), reply("OK"));
+        assert!(!s.breakpoints.contains(0x20A));
+        assert_eq!(s.handle("Z2,20a,2", &mut machine), reply(""));
+
+        assert_eq!(s.handle("c", &mut machine), Action::Continue);
+        assert_eq!(s.handle("s300", &mut machine), Action::Step);
+        assert_eq!(machine.registers().pc, 0x300);
+        assert_eq!(s.handle("bs", &mut machine), Action::ReverseStep);
+        assert_eq!(s.handle("bc", &mut machine), Action::ReverseContinue);
+        assert_eq!(s.handle("?", &mut machine), reply("S05"));
+        assert_eq!(stop_reply(SIGTRAP, true), "T05swbreak:;");
+    }
+
+    #[test]
+    fn target_description_is_served_in_chunks() {
+        let mut machine = Chip8::new(false);
+        let mut s = session();
+
+        match s.handle("qXfer:features:read:target.xml:0,10", &mut machine) {
+            Action::Reply(r) => assert_eq!(r, "m<?xml version=\"1"),
+            other => panic!("unexpected {:?}", other),
+        }
+        match s.handle("qXfer:features:read:target.xml:0,ffff", &mut machine) {
+            Action::Reply(r) => assert!(r.starts_with('l') && r.contains("name=\"pc\"")),
+            other => panic!("unexpected {:?}", other),
+        }
+        match s.handle(
+            "qXfer:features:read:target.xml:10,ffffffffffffffff",
+            &mut machine,
+        ) {
+            Action::Reply(r) => assert!(r.starts_with('l') && r.contains("name=\"pc\"")),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
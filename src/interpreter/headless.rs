@@ -0,0 +1,119 @@
+use std::convert::Infallible;
+use std::ops::Range;
+
+use crate::interpreter::chip8::{Chip8, Jit, Screen, VipFrames};
+use crate::interpreter::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Timing {
+    InstructionsPerFrame(u32),
+    // cycle-accurate COSMAC VIP timing, as with --vip-timing
+    Vip,
+}
+
+// A key held down during given frames, counted from the first one run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyPress {
+    pub key: u8,
+    pub frames: Range<u32>,
+}
+
+// Runs the machine frame by frame, without any window, audio or
+// syncing to real time, e.g. for testing against known results.
+pub struct Headless {
+    pub machine: Chip8,
+    timing: Timing,
+    cycle_credit: i64,
+    jit: Option<Jit>,
+    keys: Vec<KeyPress>,
+    frame: u32,
+}
+
+impl Headless {
+    pub fn new(machine: Chip8, timing: Timing) -> Headless {
+        Headless {
+            machine,
+            timing,
+            cycle_credit: 0,
+            jit: None,
+            keys: Vec::new(),
+            frame: 0,
+        }
+    }
+
+    // Presses the keys as scripted; with a script, the machine's input is
+    // replaced at the start of every frame by the keys held in it.
+    pub fn with_keys(mut self, keys: Vec<KeyPress>) -> Headless {
+        self.keys = keys;
+        self
+    }
+
+    // Runs the machine with the dynamic recompiler, for much faster batch runs.
+    // VIP timing charges each instruction separately, so it's still interpreted.
+    pub fn with_jit(mut self) -> Headless {
+        self.jit = Some(Jit::default());
+        self
+    }
+
+    pub fn run_frames(&mut self, frames: u32) {
+        for _ in 0..frames {
+            if !self.keys.is_empty() {
+                self.machine.input = self
+                    .keys
+                    .iter()
+                    .filter(|k| k.frames.contains(&self.frame))
+                    .map(|k| k.key)
+                    .collect();
+            }
+
+            match self.timing {
+                Timing::InstructionsPerFrame(ipf) => match &mut self.jit {
+                    Some(jit) => jit.run(&mut self.machine, ipf as usize),
+                    None => {
+                        for _ in 0..ipf {
+                            self.machine.run_instruction(false);
+                        }
+                    }
+                },
+                Timing::Vip => match self.run_vip_frame() {
+                    Ok(()) => (),
+                    Err(never) => match never {},
+                },
+            }
+            self.machine.tick_timers();
+            self.frame += 1;
+        }
+    }
+}
+
+impl VipFrames for Headless {
+    type Error = Infallible;
+
+    fn machine(&self) -> &Chip8 {
+        &self.machine
+    }
+
+    fn cycle_credit(&mut self) -> &mut i64 {
+        &mut self.cycle_credit
+    }
+
+    fn execute_next(&mut self) -> Result<bool, Infallible> {
+        self.machine.run_instruction(false);
+        Ok(true)
+    }
+}
+
+// Text rendering of the display, a line per row, '#' for a lit pixel
+// and '.' for an unlit one.
+pub fn display_to_text(screen: &Screen) -> String {
+    screen
+        .display
+        .chunks(DISPLAY_WIDTH)
+        .take(DISPLAY_HEIGHT)
+        .map(|row| {
+            let mut line: String = row.iter().map(|&p| if p { '#' } else { '.' }).collect();
+            line.push('\n');
+            line
+        })
+        .collect()
+}
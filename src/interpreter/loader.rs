@@ -0,0 +1,185 @@
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+use crate::InterpErr;
+
+mod cartridge;
+mod octo;
+
+// Reads the ROM from stdin.
+pub const STDIN: &str = "-";
+
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+const GIF_MAGIC: &[u8] = b"GIF8";
+
+// File extensions of hex-text listings, and of ROMs looked for in archives.
+const HEX_EXTENSIONS: &[&str] = &["hex", "txt"];
+const ROM_EXTENSIONS: &[&str] = &["ch8", "c8", "rom", "sc8", "xo8", "bin"];
+
+// Reads a ROM from a file (or stdin, given "-"): a plain binary, a hex-text
+// listing (by the .hex / .txt extension, or on stdin by its content), an Octo
+// cartridge, whose program is assembled, or a zip archive, from which
+// `zip_entry` is taken, or the only ROM in it if there's no entry given.
+pub fn read_rom(path: &str, zip_entry: Option<&str>) -> Result<Vec<u8>, InterpErr> {
+    let bytes = if path == STDIN {
+        let mut bytes = Vec::new();
+        std::io::stdin().read_to_end(&mut bytes)?;
+        bytes
+    } else {
+        std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?
+    };
+
+    let rom = if bytes.starts_with(ZIP_MAGIC) {
+        from_zip(&bytes, zip_entry)
+    } else if bytes.starts_with(GIF_MAGIC) {
+        cartridge::from_cartridge(&bytes).map_err(InterpErr::from)
+    } else if has_extension(path, HEX_EXTENSIONS) || (path == STDIN && is_text(&bytes)) {
+        let text = String::from_utf8(bytes).map_err(|_| "hex listing isn't valid text")?;
+        parse_hex(&text).map_err(InterpErr::from)
+    } else {
+        Ok(bytes)
+    };
+
+    rom.map_err(|e| format!("{}: {}", path, e).into())
+}
+
+fn has_extension(path: &str, extensions: &[&str]) -> bool {
+    Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| extensions.contains(&e.to_lowercase().as_str()))
+}
+
+// Binary ROMs practically always hold bytes outside of printable ASCII
+// (e.g. the 0x00 of 00E0), so anything else is taken for a hex listing.
+fn is_text(bytes: &[u8]) -> bool {
+    !bytes.is_empty()
+        && bytes
+            .iter()
+            .all(|b| b.is_ascii_graphic() || b.is_ascii_whitespace())
+}
+
+fn from_zip(bytes: &[u8], entry: Option<&str>) -> Result<Vec<u8>, InterpErr> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes))?;
+    let names: Vec<String> = archive
+        .file_names()
+        .filter(|n| !n.ends_with('/'))
+        .map(String::from)
+        .collect();
+
+    let name = match entry {
+        Some(entry) if names.iter().any(|n| n == entry) => entry.to_string(),
+        Some(entry) => return Err(format!("no entry '{}' in the archive", entry).into()),
+        None => {
+            let roms: Vec<&String> = names
+                .iter()
+                .filter(|n| has_extension(n, ROM_EXTENSIONS))
+                .collect();
+            match (roms.as_slice(), names.as_slice()) {
+                ([rom], _) => rom.to_string(),
+                ([], [only]) => only.clone(),
+                _ => {
+                    return Err(format!(
+                        "choose the ROM in the archive with --zip-entry, one of: {}",
+                        names.join(", ")
+                    )
+                    .into())
+                }
+            }
+        }
+    };
+
+    let mut rom = Vec::new();
+    archive.by_name(&name)?.read_to_end(&mut rom)?;
+    Ok(rom)
+}
+
+// Parses a hex-text listing: hex bytes, optionally prefixed with "0x" and
+// separated by whitespace or commas, or written together as words (e.g. "00E0");
+// "#", ";" and "//" start comments, and an address ending with ":" may begin a line.
+pub fn parse_hex(text: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+
+    for (n, line) in text.lines().enumerate() {
+        let line = ["#", ";", "//"]
+            .iter()
+            .fold(line, |l, c| l.split(c).next().unwrap_or_default());
+        let line = match line.split_once(':') {
+            Some((_, rest)) => rest,
+            None => line,
+        };
+
+        for token in line.split(|c: char| c.is_whitespace() || c == ',') {
+            let digits = token.trim_start_matches("0x").trim_start_matches("0X");
+            if digits.is_empty() {
+                continue;
+            }
+            match decode_hex(digits) {
+                Some(decoded) => bytes.extend(decoded),
+                None => return Err(format!("line {}: invalid hex '{}'", n + 1, token)),
+            }
+        }
+    }
+
+    Ok(bytes)
+}
+
+// Decodes hex digits, two per byte, e.g. "00E0"; shared with the cheat files
+// and the GDB stub.
+pub fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_hex, from_zip, is_text, parse_hex};
+    use std::io::{Cursor, Write};
+
+    #[test]
+    fn hex_listings_are_parsed() {
+        let text = "# title\n200: 00E0 A22A ; clear\n0x60, 0x0C // LD V0, 0C\n\n";
+        assert_eq!(
+            parse_hex(text).unwrap(),
+            [0x00, 0xE0, 0xA2, 0x2A, 0x60, 0x0C]
+        );
+        assert!(parse_hex("00E").is_err());
+        assert!(parse_hex("00EG").is_err());
+    }
+
+    #[test]
+    fn hex_digits_are_decoded() {
+        assert_eq!(decode_hex("00e0A2"), Some(vec![0x00, 0xE0, 0xA2]));
+        assert_eq!(decode_hex(""), Some(vec![]));
+        assert_eq!(decode_hex("0"), None);
+        assert_eq!(decode_hex("+1"), None);
+        assert_eq!(decode_hex("1G"), None);
+    }
+
+    #[test]
+    fn text_is_told_from_binary_roms() {
+        assert!(is_text(b"200: 00E0 A22A\r\n"));
+        assert!(!is_text(&[0x00, 0xE0, 0xA2, 0x2A]));
+        assert!(!is_text(b""));
+    }
+
+    #[test]
+    fn rom_is_found_in_zip() {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::FileOptions::default();
+        zip.start_file("README", options).unwrap();
+        zip.write_all(b"readme").unwrap();
+        zip.start_file("game.ch8", options).unwrap();
+        zip.write_all(&[0x12, 0x00]).unwrap();
+        let bytes = zip.finish().unwrap().into_inner();
+
+        assert_eq!(from_zip(&bytes, None).unwrap(), [0x12, 0x00]);
+        assert_eq!(from_zip(&bytes, Some("README")).unwrap(), b"readme");
+        assert!(from_zip(&bytes, Some("other.ch8")).is_err());
+    }
+}
@@ -0,0 +1,98 @@
+use std::io::Cursor;
+
+use super::octo;
+
+// Reads the program out of an Octo cartridge and assembles it.
+//
+// A cartridge is a GIF whose image data carries a payload in the low two bits
+// of each pixel's palette index, four pixels per byte, most significant bits
+// first, through all frames. The payload is a 32-bit big-endian length followed
+// by that many bytes of UTF-8 JSON, whose "program" field holds the Octo source.
+pub fn from_cartridge(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    let payload = payload(bytes)?;
+    let json: serde_json::Value =
+        serde_json::from_slice(&payload).map_err(|e| format!("invalid cartridge data: {}", e))?;
+    let source = json
+        .get("program")
+        .and_then(|p| p.as_str())
+        .ok_or("the cartridge holds no program")?;
+
+    octo::assemble(source).map_err(|e| format!("cartridge program, {}", e))
+}
+
+fn payload(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::Indexed);
+    let mut decoder = options
+        .read_info(Cursor::new(bytes))
+        .map_err(|e| format!("invalid GIF: {}", e))?;
+
+    let mut indices = Vec::new();
+    while let Some(frame) = decoder
+        .read_next_frame()
+        .map_err(|e| format!("invalid GIF: {}", e))?
+    {
+        indices.extend_from_slice(&frame.buffer);
+    }
+
+    let data: Vec<u8> = indices
+        .chunks_exact(4)
+        .map(|p| p.iter().fold(0, |byte, index| byte << 2 | index & 3))
+        .collect();
+    if data.len() < 4 {
+        return Err("no data in the cartridge".to_string());
+    }
+    let len = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+    data.get(4..4 + len)
+        .map(<[u8]>::to_vec)
+        .ok_or_else(|| "the cartridge data is cut short".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::from_cartridge;
+
+    // Builds a single-frame cartridge carrying the payload, on a label
+    // image using palette entries 0 and 4.
+    fn cartridge(payload: &[u8]) -> Vec<u8> {
+        let (width, height) = (32, 32);
+        let mut data = (payload.len() as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(payload);
+
+        let mut pixels: Vec<u8> = (0..width * height)
+            .map(|p| (p % 3 == 0) as u8 * 4)
+            .collect();
+        for (i, byte) in data.iter().enumerate() {
+            for j in 0..4 {
+                pixels[i * 4 + j] |= byte >> (6 - 2 * j) & 3;
+            }
+        }
+
+        let palette: Vec<u8> = (0..8u8).flat_map(|c| [c * 30, c * 30, c * 30]).collect();
+        let mut gif = Vec::new();
+        {
+            let mut encoder = gif::Encoder::new(&mut gif, width, height, &palette).unwrap();
+            let frame = gif::Frame::from_indexed_pixels(width, height, pixels, None);
+            encoder.write_frame(&frame).unwrap();
+        }
+        gif
+    }
+
+    #[test]
+    fn program_is_assembled_from_cartridge() {
+        let json = r#"{"key":"","program":": main\n  v0 := 5\n  jump main","options":{}}"#;
+        assert_eq!(
+            from_cartridge(&cartridge(json.as_bytes())).unwrap(),
+            [0x12, 0x02, 0x60, 0x05, 0x12, 0x02]
+        );
+    }
+
+    #[test]
+    fn invalid_cartridges_are_rejected() {
+        assert!(from_cartridge(b"GIF89a").is_err());
+        assert!(from_cartridge(&cartridge(b"{}"))
+            .unwrap_err()
+            .contains("no program"));
+        assert!(from_cartridge(&cartridge(b"not json")).is_err());
+    }
+}
@@ -0,0 +1,1110 @@
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
+
+// Octo programs are assembled for this address, which holds a jump to `main`.
+const ORIGIN: u16 = 0x200;
+
+// Macros expanding to macros are expanded this deep at most, which stops a
+// macro using itself.
+const MAX_MACRO_DEPTH: usize = 64;
+
+// Assembles Octo source code, as kept in Octo cartridges, to a CHIP-8 program.
+// Covers the CHIP-8 part of the language with SCHIP's screen instructions and
+// `i := bighex`: labels, `:const`, `:alias`, `:unpack`, `:next`, `:org`, `:byte`,
+// `:pointer`, `:call`, `:macro`, `:calc` and the structured `if` / `loop` forms.
+// `:calc` has arithmetic, bitwise and comparison operators only. XO-CHIP
+// instructions, `:stringmode`, `:assert` and the math functions of `:calc`
+// (`sin`, `pow`, `PI` and so on) are rejected.
+pub fn assemble(source: &str) -> Result<Vec<u8>, String> {
+    let mut assembler = Assembler::new(source);
+    assembler.run()?;
+    assembler.finish()
+}
+
+#[derive(Clone)]
+struct Token {
+    text: String,
+    line: usize,
+    // how many macro expansions it comes from
+    depth: usize,
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Token>,
+}
+
+#[derive(Clone, Copy)]
+enum Fixup {
+    // low 12 bits of the instruction
+    Nnn,
+    // a data byte
+    Byte,
+    // a 16-bit data word
+    Pointer,
+    // the high / low byte of an address loaded by `:unpack`
+    UnpackHigh(u8),
+    UnpackLow,
+}
+
+// Open control structures, holding the addresses of jumps to be patched.
+enum Flow {
+    Begin(u16),
+    Else(u16),
+    Loop { start: u16, exits: Vec<u16> },
+}
+
+// An `if` / `while` condition: the instructions it needs first, and the skip
+// instructions skipping the next one if the condition is false / true.
+struct Condition {
+    setup: Vec<u16>,
+    skip_if_false: u16,
+    skip_if_true: u16,
+}
+
+struct Assembler {
+    tokens: VecDeque<Token>,
+    rom: Vec<u8>,
+    here: u16,
+    line: usize,
+    depth: usize,
+    labels: HashMap<String, u16>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<(u16, String, Fixup, usize)>,
+    flow: Vec<Flow>,
+    // labels given by `:next` to the immediate byte of the next instruction
+    next: Vec<String>,
+}
+
+impl Assembler {
+    fn new(source: &str) -> Assembler {
+        let tokens = source
+            .lines()
+            .enumerate()
+            .flat_map(|(n, line)| {
+                line.split('#')
+                    .next()
+                    .unwrap_or_default()
+                    .split_whitespace()
+                    .map(move |t| Token {
+                        text: t.to_string(),
+                        line: n + 1,
+                        depth: 0,
+                    })
+            })
+            .collect();
+
+        Assembler {
+            tokens,
+            rom: vec![0; 2],
+            here: ORIGIN + 2,
+            line: 0,
+            depth: 0,
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            fixups: Vec::new(),
+            flow: Vec::new(),
+            next: Vec::new(),
+        }
+    }
+
+    fn error(&self, msg: String) -> String {
+        format!("line {}: {}", self.line, msg)
+    }
+
+    fn next_token(&mut self) -> Result<String, String> {
+        match self.tokens.pop_front() {
+            Some(token) => {
+                self.line = token.line;
+                self.depth = token.depth;
+                Ok(token.text)
+            }
+            None => Err(self.error("unexpected end of the program".to_string())),
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.front().map(|t| t.text.as_str())
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        let token = self.next_token()?;
+        if token != expected {
+            return Err(self.error(format!("expected '{}', found '{}'", expected, token)));
+        }
+        Ok(())
+    }
+
+    fn run(&mut self) -> Result<(), String> {
+        while !self.tokens.is_empty() {
+            let token = self.next_token()?;
+            self.statement(&token)?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Vec<u8>, String> {
+        if !self.flow.is_empty() {
+            return Err("unclosed 'begin' or 'loop' at the end of the program".to_string());
+        }
+        let main = *self
+            .labels
+            .get("main")
+            .ok_or("the program doesn't define 'main'")?;
+        self.patch(ORIGIN, 0x1000 | main);
+
+        for (addr, name, fixup, line) in std::mem::take(&mut self.fixups) {
+            let value = match self.labels.get(&name) {
+                Some(&value) => value,
+                None => return Err(format!("line {}: undefined name '{}'", line, name)),
+            };
+            let at = (addr - ORIGIN) as usize;
+            match fixup {
+                Fixup::Nnn => {
+                    self.rom[at] = self.rom[at] & 0xF0 | (value >> 8 & 0xF) as u8;
+                    self.rom[at + 1] = value as u8;
+                }
+                Fixup::Byte if value > 0xFF => {
+                    return Err(format!("line {}: '{}' doesn't fit in a byte", line, name))
+                }
+                Fixup::Byte => self.rom[at] = value as u8,
+                Fixup::Pointer => self.rom[at..at + 2].copy_from_slice(&value.to_be_bytes()),
+                Fixup::UnpackHigh(nibble) => {
+                    self.rom[at + 1] = nibble << 4 | (value >> 8 & 0xF) as u8
+                }
+                Fixup::UnpackLow => self.rom[at + 1] = value as u8,
+            }
+        }
+
+        Ok(self.rom)
+    }
+
+    fn emit_byte(&mut self, byte: u8) -> Result<(), String> {
+        let at = (self.here - ORIGIN) as usize;
+        if self.here > 0xFFF {
+            return Err(self.error("the program doesn't fit in memory".to_string()));
+        }
+        if at >= self.rom.len() {
+            self.rom.resize(at + 1, 0);
+        }
+        self.rom[at] = byte;
+        self.here += 1;
+        Ok(())
+    }
+
+    fn emit(&mut self, word: u16) -> Result<(), String> {
+        for name in std::mem::take(&mut self.next) {
+            self.labels.insert(name, self.here + 1);
+        }
+        self.emit_byte((word >> 8) as u8)?;
+        self.emit_byte(word as u8)
+    }
+
+    // Emits the instruction with its address operand, which may be defined later.
+    fn emit_with_address(&mut self, op: u16, token: &str) -> Result<(), String> {
+        let addr = match self.known_value(token)? {
+            Some(addr) => self.address(addr, token)?,
+            None => {
+                self.fixup(token, Fixup::Nnn)?;
+                0
+            }
+        };
+        self.emit(op | addr)
+    }
+
+    fn fixup(&mut self, name: &str, fixup: Fixup) -> Result<(), String> {
+        if !is_name(name) {
+            return Err(self.error(format!("invalid value '{}'", name)));
+        }
+        self.fixups
+            .push((self.here, name.to_string(), fixup, self.line));
+        Ok(())
+    }
+
+    fn patch(&mut self, addr: u16, word: u16) {
+        let at = (addr - ORIGIN) as usize;
+        self.rom[at..at + 2].copy_from_slice(&word.to_be_bytes());
+    }
+
+    fn statement(&mut self, token: &str) -> Result<(), String> {
+        if let Some(reg) = self.register(token) {
+            return self.register_statement(reg);
+        }
+
+        match token {
+            ":" => {
+                let name = self.next_token()?;
+                self.define_label(name)
+            }
+            ":const" => {
+                let name = self.next_token()?;
+                let token = self.next_token()?;
+                let value = self.required_value(&token)?;
+                self.constants.insert(name, value);
+                Ok(())
+            }
+            ":calc" => {
+                let name = self.next_token()?;
+                let value = self.braced_expression()?;
+                self.constants.insert(name, value);
+                Ok(())
+            }
+            ":alias" => {
+                let name = self.next_token()?;
+                let token = self.next_token()?;
+                let reg = self.required_register(&token)?;
+                self.aliases.insert(name, reg);
+                Ok(())
+            }
+            ":unpack" => {
+                let token = self.next_token()?;
+                let nibble = self.byte(&token)?;
+                if nibble > 0xF {
+                    return Err(self.error(format!("'{}' isn't a nibble", token)));
+                }
+                let name = self.next_token()?;
+                match self.known_value(&name)? {
+                    Some(value) => {
+                        let addr = self.address(value, &name)?;
+                        self.emit(0x6000 | (nibble as u16) << 4 | addr >> 8)?;
+                        self.emit(0x6100 | (addr & 0xFF))
+                    }
+                    None => {
+                        self.fixup(&name, Fixup::UnpackHigh(nibble))?;
+                        self.emit(0x6000)?;
+                        self.fixup(&name, Fixup::UnpackLow)?;
+                        self.emit(0x6100)
+                    }
+                }
+            }
+            ":next" => {
+                let name = self.next_token()?;
+                self.next.push(name);
+                Ok(())
+            }
+            ":org" => {
+                let token = self.next_token()?;
+                let value = self.required_value(&token)?;
+                let addr = self.address(value, &token)?;
+                if addr < ORIGIN {
+                    return Err(self.error(format!("can't assemble below {:03X}", ORIGIN)));
+                }
+                self.here = addr;
+                Ok(())
+            }
+            ":byte" => {
+                if self.peek() == Some("{") {
+                    let value = self.braced_expression()?;
+                    return self.emit_byte(value.floor() as i64 as u8);
+                }
+                let token = self.next_token()?;
+                match self.known_value(&token)? {
+                    Some(_) => {
+                        let byte = self.byte(&token)?;
+                        self.emit_byte(byte)
+                    }
+                    None => {
+                        self.fixup(&token, Fixup::Byte)?;
+                        self.emit_byte(0)
+                    }
+                }
+            }
+            ":pointer" => {
+                let token = self.next_token()?;
+                let addr = match self.known_value(&token)? {
+                    Some(value) => self.address(value, &token)?,
+                    None => {
+                        self.fixup(&token, Fixup::Pointer)?;
+                        0
+                    }
+                };
+                self.emit_byte((addr >> 8) as u8)?;
+                self.emit_byte(addr as u8)
+            }
+            ":call" => {
+                let token = self.next_token()?;
+                self.emit_with_address(0x2000, &token)
+            }
+            ":macro" => self.define_macro(),
+            ":proto" | ":breakpoint" => self.next_token().map(|_| ()),
+            ":monitor" => {
+                self.next_token()?;
+                self.next_token().map(|_| ())
+            }
+            "clear" => self.emit(0x00E0),
+            "return" | ";" => self.emit(0x00EE),
+            "scroll-down" => {
+                let token = self.next_token()?;
+                let n = self.byte(&token)?;
+                if n > 0xF {
+                    return Err(self.error(format!("'{}' isn't a nibble", token)));
+                }
+                self.emit(0x00C0 | n as u16)
+            }
+            "scroll-right" => self.emit(0x00FB),
+            "scroll-left" => self.emit(0x00FC),
+            "exit" => self.emit(0x00FD),
+            "lores" => self.emit(0x00FE),
+            "hires" => self.emit(0x00FF),
+            "jump" => {
+                let token = self.next_token()?;
+                self.emit_with_address(0x1000, &token)
+            }
+            "jump0" => {
+                let token = self.next_token()?;
+                self.emit_with_address(0xB000, &token)
+            }
+            "native" => {
+                let token = self.next_token()?;
+                self.emit_with_address(0x0000, &token)
+            }
+            "sprite" => {
+                let x = self.next_register()?;
+                let y = self.next_register()?;
+                let token = self.next_token()?;
+                let n = self.byte(&token)?;
+                if n > 0xF {
+                    return Err(self.error(format!("sprites are at most 15 rows, not {}", n)));
+                }
+                self.emit(0xD000 | reg_x(x) | reg_y(y) | n as u16)
+            }
+            "load" => {
+                let x = self.next_register()?;
+                self.emit(0xF065 | reg_x(x))
+            }
+            "save" => {
+                let x = self.next_register()?;
+                self.emit(0xF055 | reg_x(x))
+            }
+            "bcd" => {
+                let x = self.next_register()?;
+                self.emit(0xF033 | reg_x(x))
+            }
+            "delay" | "buzzer" => {
+                self.expect(":=")?;
+                let x = self.next_register()?;
+                self.emit(if token == "delay" { 0xF015 } else { 0xF018 } | reg_x(x))
+            }
+            "i" => self.i_statement(),
+            "if" => self.if_statement(),
+            "else" => match self.flow.pop() {
+                Some(Flow::Begin(jump)) => {
+                    let skip = self.here;
+                    self.emit(0x1000)?;
+                    self.patch(jump, 0x1000 | self.here);
+                    self.flow.push(Flow::Else(skip));
+                    Ok(())
+                }
+                _ => Err(self.error("'else' without 'begin'".to_string())),
+            },
+            "end" => match self.flow.pop() {
+                Some(Flow::Begin(jump)) | Some(Flow::Else(jump)) => {
+                    self.patch(jump, 0x1000 | self.here);
+                    Ok(())
+                }
+                _ => Err(self.error("'end' without 'begin'".to_string())),
+            },
+            "loop" => {
+                self.flow.push(Flow::Loop {
+                    start: self.here,
+                    exits: Vec::new(),
+                });
+                Ok(())
+            }
+            "while" => {
+                let cond = self.condition()?;
+                for op in &cond.setup {
+                    self.emit(*op)?;
+                }
+                self.emit(cond.skip_if_true)?;
+                let exit = self.here;
+                self.emit(0x1000)?;
+                match self.flow.iter_mut().rev().find_map(|f| match f {
+                    Flow::Loop { exits, .. } => Some(exits),
+                    _ => None,
+                }) {
+                    Some(exits) => {
+                        exits.push(exit);
+                        Ok(())
+                    }
+                    None => Err(self.error("'while' outside of a loop".to_string())),
+                }
+            }
+            "again" => match self.flow.pop() {
+                Some(Flow::Loop { start, exits }) => {
+                    self.emit(0x1000 | start)?;
+                    for exit in exits {
+                        self.patch(exit, 0x1000 | self.here);
+                    }
+                    Ok(())
+                }
+                _ => Err(self.error("'again' without 'loop'".to_string())),
+            },
+            ":stringmode" | ":assert" | "long" | "plane" | "audio" | "pitch" | "scroll-up" => {
+                Err(self.error(format!("'{}' isn't supported", token)))
+            }
+            _ if self.macros.contains_key(token) => self.expand_macro(token),
+            _ => match parse_number(token) {
+                Some(value) => {
+                    let byte = self.byte_value(value, token)?;
+                    self.emit_byte(byte)
+                }
+                // a bare label calls the subroutine
+                None if is_name(token) && !token.starts_with(':') => {
+                    self.emit_with_address(0x2000, token)
+                }
+                None => Err(self.error(format!("unknown token '{}'", token))),
+            },
+        }
+    }
+
+    fn define_label(&mut self, name: String) -> Result<(), String> {
+        if !is_name(&name) || self.register(&name).is_some() {
+            return Err(self.error(format!("invalid label '{}'", name)));
+        }
+        if self.labels.contains_key(&name) {
+            return Err(self.error(format!("label '{}' is defined twice", name)));
+        }
+        self.labels.insert(name, self.here);
+        Ok(())
+    }
+
+    fn register_statement(&mut self, x: u8) -> Result<(), String> {
+        let op = self.next_token()?;
+        let rhs = self.next_token()?;
+        let x16 = reg_x(x);
+
+        if let Some(y) = self.register(&rhs) {
+            let n = match op.as_str() {
+                ":=" => 0x0,
+                "|=" => 0x1,
+                "&=" => 0x2,
+                "^=" => 0x3,
+                "+=" => 0x4,
+                "-=" => 0x5,
+                ">>=" => 0x6,
+                "=-" => 0x7,
+                "<<=" => 0xE,
+                _ => return Err(self.error(format!("unknown operator '{}'", op))),
+            };
+            return self.emit(0x8000 | x16 | reg_y(y) | n);
+        }
+
+        match (op.as_str(), rhs.as_str()) {
+            (":=", "key") => self.emit(0xF00A | x16),
+            (":=", "delay") => self.emit(0xF007 | x16),
+            (":=", "random") => {
+                let token = self.next_token()?;
+                let mask = self.byte(&token)?;
+                self.emit(0xC000 | x16 | mask as u16)
+            }
+            (":=", _) => {
+                let kk = self.byte(&rhs)?;
+                self.emit(0x6000 | x16 | kk as u16)
+            }
+            ("+=", _) => {
+                let kk = self.byte(&rhs)?;
+                self.emit(0x7000 | x16 | kk as u16)
+            }
+            ("-=", _) => {
+                let kk = self.byte(&rhs)?;
+                self.emit(0x7000 | x16 | kk.wrapping_neg() as u16)
+            }
+            _ => Err(self.error(format!(
+                "'{}' can't be used with '{}', only with a register",
+                op, rhs
+            ))),
+        }
+    }
+
+    fn i_statement(&mut self) -> Result<(), String> {
+        let op = self.next_token()?;
+        let rhs = self.next_token()?;
+        match (op.as_str(), rhs.as_str()) {
+            ("+=", _) => {
+                let x = self.required_register(&rhs)?;
+                self.emit(0xF01E | reg_x(x))
+            }
+            (":=", "hex") => {
+                let x = self.next_register()?;
+                self.emit(0xF029 | reg_x(x))
+            }
+            (":=", "bighex") => {
+                let x = self.next_register()?;
+                self.emit(0xF030 | reg_x(x))
+            }
+            (":=", "long") => Err(self.error("'i := long' isn't supported".to_string())),
+            (":=", _) => self.emit_with_address(0xA000, &rhs),
+            _ => Err(self.error(format!("unknown operator '{}'", op))),
+        }
+    }
+
+    fn if_statement(&mut self) -> Result<(), String> {
+        let cond = self.condition()?;
+        for op in &cond.setup {
+            self.emit(*op)?;
+        }
+        match self.next_token()?.as_str() {
+            "then" => self.emit(cond.skip_if_false),
+            "begin" => {
+                self.emit(cond.skip_if_true)?;
+                self.flow.push(Flow::Begin(self.here));
+                self.emit(0x1000)
+            }
+            other => Err(self.error(format!("expected 'then' or 'begin', found '{}'", other))),
+        }
+    }
+
+    fn condition(&mut self) -> Result<Condition, String> {
+        let x = self.next_register()?;
+        let op = self.next_token()?;
+        let x16 = reg_x(x);
+
+        let (on_false, on_true) = match op.as_str() {
+            "key" => (0xE0A1 | x16, 0xE09E | x16),
+            "-key" => (0xE09E | x16, 0xE0A1 | x16),
+            "==" | "!=" => {
+                let rhs = self.next_token()?;
+                let (eq, ne) = match self.register(&rhs) {
+                    Some(y) => (0x5000 | x16 | reg_y(y), 0x9000 | x16 | reg_y(y)),
+                    None => {
+                        let kk = self.byte(&rhs)? as u16;
+                        (0x3000 | x16 | kk, 0x4000 | x16 | kk)
+                    }
+                };
+                if op == "==" {
+                    (ne, eq)
+                } else {
+                    (eq, ne)
+                }
+            }
+            "<" | ">" | "<=" | ">=" => return self.comparison(x, &op),
+            _ => return Err(self.error(format!("unknown condition '{}'", op))),
+        };
+
+        Ok(Condition {
+            setup: Vec::new(),
+            skip_if_false: on_false,
+            skip_if_true: on_true,
+        })
+    }
+
+    // Comparisons are done by subtracting in VF: afterwards, VF is 1 when
+    // there was no borrow, i.e. the minuend was at least the subtrahend.
+    fn comparison(&mut self, x: u8, op: &str) -> Result<Condition, String> {
+        let rhs = self.next_token()?;
+        let rhs = match self.register(&rhs) {
+            Some(y) => Ok(y),
+            None => Err(self.byte(&rhs)?),
+        };
+
+        // x >= y and x < y check x - y, x <= y and x > y check y - x
+        let x_minuend = matches!(op, ">=" | "<");
+        let setup = match (rhs, x_minuend) {
+            (Ok(y), true) => vec![0x8F00 | reg_y(x), 0x8F05 | reg_y(y)],
+            (Ok(y), false) => vec![0x8F00 | reg_y(y), 0x8F05 | reg_y(x)],
+            (Err(kk), true) => vec![0x6F00 | kk as u16, 0x8F07 | reg_y(x)],
+            (Err(kk), false) => vec![0x6F00 | kk as u16, 0x8F05 | reg_y(x)],
+        };
+        let (skip_if_vf_0, skip_if_vf_1) = (0x4F01, 0x3F01);
+
+        // true with VF == 1 for >= and <=, with VF == 0 for < and >
+        let (skip_if_false, skip_if_true) = if matches!(op, ">=" | "<=") {
+            (skip_if_vf_0, skip_if_vf_1)
+        } else {
+            (skip_if_vf_1, skip_if_vf_0)
+        };
+        Ok(Condition {
+            setup,
+            skip_if_false,
+            skip_if_true,
+        })
+    }
+
+    fn define_macro(&mut self) -> Result<(), String> {
+        let name = self.next_token()?;
+        let mut params = Vec::new();
+        loop {
+            let token = self.next_token()?;
+            if token == "{" {
+                break;
+            }
+            params.push(token);
+        }
+        let body = self.braced_tokens()?;
+        self.macros.insert(name, Macro { params, body });
+        Ok(())
+    }
+
+    fn expand_macro(&mut self, name: &str) -> Result<(), String> {
+        if self.depth >= MAX_MACRO_DEPTH {
+            return Err(self.error(format!(
+                "macro '{}' is expanded more than {} levels deep",
+                name, MAX_MACRO_DEPTH
+            )));
+        }
+        let depth = self.depth + 1;
+        let count = self.macros[name].params.len();
+        let mut args = HashMap::new();
+        for i in 0..count {
+            let arg = self.next_token()?;
+            args.insert(self.macros[name].params[i].clone(), arg);
+        }
+
+        let line = self.line;
+        let expanded: Vec<Token> = self.macros[name]
+            .body
+            .iter()
+            .map(|t| Token {
+                text: args.get(&t.text).unwrap_or(&t.text).clone(),
+                line,
+                depth,
+            })
+            .collect();
+        for token in expanded.into_iter().rev() {
+            self.tokens.push_front(token);
+        }
+        Ok(())
+    }
+
+    // Tokens up to the matching "}", the "{" having been read.
+    fn braced_tokens(&mut self) -> Result<Vec<Token>, String> {
+        let mut tokens = Vec::new();
+        let mut depth = 1;
+        loop {
+            let text = self.next_token()?;
+            match text.as_str() {
+                "{" => depth += 1,
+                "}" => depth -= 1,
+                _ => (),
+            }
+            if depth == 0 {
+                return Ok(tokens);
+            }
+            tokens.push(Token {
+                text,
+                line: self.line,
+                depth: 0,
+            });
+        }
+    }
+
+    // Evaluates a `:calc` expression in braces. As in Octo, operators have no
+    // precedence and are evaluated from right to left, unless parenthesized.
+    fn braced_expression(&mut self) -> Result<f64, String> {
+        self.expect("{")?;
+        let tokens: Vec<String> = self.braced_tokens()?.into_iter().map(|t| t.text).collect();
+        let mut pos = 0;
+        let value = self.expression(&tokens, &mut pos)?;
+        if pos != tokens.len() {
+            return Err(self.error(format!("unexpected '{}' in expression", tokens[pos])));
+        }
+        Ok(value)
+    }
+
+    fn expression(&self, tokens: &[String], pos: &mut usize) -> Result<f64, String> {
+        let lhs = self.term(tokens, pos)?;
+        let op = match tokens.get(*pos) {
+            Some(op) if op != ")" => op.clone(),
+            _ => return Ok(lhs),
+        };
+        *pos += 1;
+        let rhs = self.expression(tokens, pos)?;
+
+        let (a, b) = (lhs.floor() as i64, rhs.floor() as i64);
+        Ok(match op.as_str() {
+            "+" => lhs + rhs,
+            "-" => lhs - rhs,
+            "*" => lhs * rhs,
+            "/" => lhs / rhs,
+            "%" => lhs % rhs,
+            "&" => (a & b) as f64,
+            "|" => (a | b) as f64,
+            "^" => (a ^ b) as f64,
+            "<<" | ">>" => {
+                let shifted = u32::try_from(b).ok().and_then(|b| {
+                    if op == "<<" {
+                        a.checked_shl(b)
+                    } else {
+                        a.checked_shr(b)
+                    }
+                });
+                match shifted {
+                    Some(value) => value as f64,
+                    None => return Err(self.error(format!("can't shift by {}", b))),
+                }
+            }
+            "<" => (lhs < rhs) as u8 as f64,
+            ">" => (lhs > rhs) as u8 as f64,
+            "<=" => (lhs <= rhs) as u8 as f64,
+            ">=" => (lhs >= rhs) as u8 as f64,
+            "==" => (lhs == rhs) as u8 as f64,
+            "!=" => (lhs != rhs) as u8 as f64,
+            "pow" | "min" | "max" => return Err(self.error(format!("'{}' isn't supported", op))),
+            _ => return Err(self.error(format!("unknown operator '{}'", op))),
+        })
+    }
+
+    fn term(&self, tokens: &[String], pos: &mut usize) -> Result<f64, String> {
+        let token = tokens
+            .get(*pos)
+            .ok_or_else(|| self.error("incomplete expression".to_string()))?;
+        *pos += 1;
+
+        let unary: Option<fn(f64) -> f64> = match token.as_str() {
+            "(" => {
+                let value = self.expression(tokens, pos)?;
+                if tokens.get(*pos).map(String::as_str) != Some(")") {
+                    return Err(self.error("missing ')' in expression".to_string()));
+                }
+                *pos += 1;
+                return Ok(value);
+            }
+            "-" => Some(|v| -v),
+            "~" => Some(|v| !(v.floor() as i64) as f64),
+            "!" => Some(|v| (v == 0.0) as u8 as f64),
+            "sin" | "cos" | "tan" | "exp" | "log" | "abs" | "sqrt" | "sign" | "ceil" | "floor"
+            | "PI" | "E" => return Err(self.error(format!("'{}' isn't supported", token))),
+            _ => None,
+        };
+        if let Some(f) = unary {
+            return Ok(f(self.term(tokens, pos)?));
+        }
+
+        match token.as_str() {
+            "HERE" => Ok(self.here as f64),
+            _ => self.required_value(token),
+        }
+    }
+
+    fn register(&self, token: &str) -> Option<u8> {
+        if let Some(&reg) = self.aliases.get(token) {
+            return Some(reg);
+        }
+        match token.as_bytes() {
+            [b'v' | b'V', digit] => (*digit as char).to_digit(16).map(|d| d as u8),
+            _ => None,
+        }
+    }
+
+    fn required_register(&self, token: &str) -> Result<u8, String> {
+        self.register(token)
+            .ok_or_else(|| self.error(format!("expected a register, found '{}'", token)))
+    }
+
+    fn next_register(&mut self) -> Result<u8, String> {
+        let token = self.next_token()?;
+        self.required_register(&token)
+    }
+
+    // A number, a constant or a label defined so far; None for other names,
+    // which may be labels defined later.
+    fn known_value(&self, token: &str) -> Result<Option<f64>, String> {
+        if let Some(value) = parse_number(token) {
+            return Ok(Some(value as f64));
+        }
+        if let Some(&value) = self.constants.get(token) {
+            return Ok(Some(value));
+        }
+        Ok(self.labels.get(token).map(|&v| v as f64))
+    }
+
+    fn required_value(&self, token: &str) -> Result<f64, String> {
+        self.known_value(token)?
+            .ok_or_else(|| self.error(format!("undefined name '{}'", token)))
+    }
+
+    fn byte(&self, token: &str) -> Result<u8, String> {
+        let value = self.required_value(token)?;
+        self.byte_value(value.floor() as i64, token)
+    }
+
+    fn byte_value(&self, value: i64, token: &str) -> Result<u8, String> {
+        if !(-128..=255).contains(&value) {
+            return Err(self.error(format!("'{}' doesn't fit in a byte", token)));
+        }
+        Ok(value as u8)
+    }
+
+    fn address(&self, value: f64, token: &str) -> Result<u16, String> {
+        let value = value.floor() as i64;
+        if !(0..=0xFFF).contains(&value) {
+            return Err(self.error(format!("'{}' isn't an address", token)));
+        }
+        Ok(value as u16)
+    }
+}
+
+fn reg_x(x: u8) -> u16 {
+    (x as u16) << 8
+}
+
+fn reg_y(y: u8) -> u16 {
+    (y as u16) << 4
+}
+
+fn parse_number(token: &str) -> Option<i64> {
+    let (negative, digits) = match token.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, token),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(bin) = digits.strip_prefix("0b") {
+        i64::from_str_radix(bin, 2).ok()?
+    } else if !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -value } else { value })
+}
+
+fn is_name(token: &str) -> bool {
+    !token.is_empty() && !token.contains(|c: char| c.is_whitespace() || c == '{' || c == '}')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::assemble;
+    use crate::interpreter::chip8::Chip8;
+
+    fn words(rom: &[u8]) -> Vec<u16> {
+        rom.chunks(2)
+            .map(|w| (w[0] as u16) << 8 | *w.get(1).unwrap_or(&0) as u16)
+            .collect()
+    }
+
+    #[test]
+    fn statements_are_assembled() {
+        let source = "
+            :const SPEED 2
+            :alias px v4
+            : main          # entry point
+                clear
+                px := 10  v5 := SPEED  px += v5  v5 -= 1
+                i := sprite-data
+                sprite px v5 3
+                v0 := key  v1 := random 0x0F  delay := v0
+                i := hex v1  bcd v1  save v2  load v2
+                draw
+                jump main
+            : draw
+                i += v3 ;
+            : sprite-data 0x80 0x40 -1
+        ";
+        assert_eq!(
+            words(&assemble(source).unwrap()),
+            [
+                0x1202, 0x00E0, 0x640A, 0x6502, 0x8454, 0x75FF, 0xA226, 0xD453, 0xF00A, 0xC10F,
+                0xF015, 0xF129, 0xF133, 0xF255, 0xF265, 0x2222, 0x1202, 0xF31E, 0x00EE, 0x8040,
+                0xFF00,
+            ]
+        );
+    }
+
+    #[test]
+    fn control_structures_are_assembled() {
+        let source = "
+            : main
+                if v0 == 3 then v1 := 1
+                if v0 != v2 begin
+                    v1 := 2
+                else
+                    v1 := 3
+                end
+                loop
+                    while v0 key
+                    if v1 < 5 then v2 := 0
+                again
+        ";
+        assert_eq!(
+            words(&assemble(source).unwrap()),
+            [
+                0x1202, 0x4003, 0x6101, 0x9020, 0x120E, 0x6102, 0x1210, 0x6103, 0xE09E, 0x121E,
+                0x6F05, 0x8F17, 0x3F01, 0x6200, 0x1210,
+            ]
+        );
+    }
+
+    #[test]
+    fn comparisons_hold_when_run() {
+        for op in ["<", ">", "<=", ">="] {
+            for (a, b) in [(3u8, 5u8), (5, 5), (5, 3)] {
+                let expected = match op {
+                    "<" => a < b,
+                    ">" => a > b,
+                    "<=" => a <= b,
+                    _ => a >= b,
+                };
+                for rhs in ["v1".to_string(), b.to_string()] {
+                    let source = format!(
+                        ": main v0 := {} v1 := {} v2 := 0 if v0 {} {} then v2 := 1",
+                        a, b, op, rhs
+                    );
+                    let mut machine = Chip8::new(false).load_program(&assemble(&source).unwrap());
+                    for _ in 0..8 {
+                        machine.run_instruction(false);
+                    }
+                    assert_eq!(machine.registers().v[2] == 1, expected, "{}", source);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn directives_and_macros_are_assembled() {
+        let source = "
+            :macro set-both A B { v0 := A v1 := B }
+            :calc DOUBLE { 2 * 1 + 3 }
+            : main
+                set-both 1 DOUBLE
+                :unpack 0xA data
+                :next target v2 := 0
+                i := target
+            :org 0x220
+            : data :byte { 255 } :pointer data
+        ";
+        let rom = assemble(source).unwrap();
+        assert_eq!(
+            words(&rom[..14]),
+            [0x1202, 0x6001, 0x6108, 0x60A2, 0x6120, 0x6200, 0xA20B]
+        );
+        assert_eq!(rom[0x20..], [0xFF, 0x02, 0x20]);
+    }
+
+    #[test]
+    fn errors_are_reported() {
+        assert!(assemble(": start clear").unwrap_err().contains("main"));
+        assert!(assemble(": main jump nowhere")
+            .unwrap_err()
+            .contains("undefined name 'nowhere'"));
+        assert!(assemble(": main v0 := 256").unwrap_err().contains("line 1"));
+        assert!(assemble(": main loop").unwrap_err().contains("unclosed"));
+        assert!(
+            assemble(":macro again-and-again { again-and-again } : main again-and-again")
+                .unwrap_err()
+                .contains("more than 64 levels deep")
+        );
+        assert!(assemble(":calc X { 1 << 64 } : main")
+            .unwrap_err()
+            .contains("can't shift by 64"));
+        assert!(assemble(":calc X { 1 >> -1 } : main")
+            .unwrap_err()
+            .contains("can't shift by -1"));
+        assert!(assemble(": main plane 1")
+            .unwrap_err()
+            .contains("isn't supported"));
+    }
+
+    #[test]
+    fn schip_and_other_statements_are_assembled() {
+        let source = "
+            : main
+                hires lores scroll-down 4 scroll-left scroll-right exit
+                i := bighex v2
+                jump0 table
+                native 0x123
+                :proto foo :breakpoint here :monitor v0 4
+                v3 >>= v4 v3 <<= v4 v3 =- v4 v3 |= v4
+                v3 &= v4 v3 ^= v4 v3 := v4 v3 -= v4
+                if v1 -key then return
+                :call table
+            : table :pointer later :byte { HERE - table }
+            : later
+        ";
+        let rom = assemble(source).unwrap();
+        assert_eq!(
+            words(&rom[..42]),
+            [
+                0x1202, 0x00FF, 0x00FE, 0x00C4, 0x00FC, 0x00FB, 0x00FD, 0xF230, 0xB22A, 0x0123,
+                0x8346, 0x834E, 0x8347, 0x8341, 0x8342, 0x8343, 0x8340, 0x8345, 0xE19E, 0x00EE,
+                0x222A,
+            ]
+        );
+        assert_eq!(rom[42..], [0x02, 0x2D, 0x02]);
+    }
+
+    #[test]
+    fn calc_is_evaluated_from_right_to_left() {
+        let source = "
+            :calc A { 2 * 1 + 3 }
+            :calc B { ( 2 * 1 ) + 3 }
+            :calc C { 0xF0 >> 4 | 1 }
+            :calc D { 7 / 2 }
+            :calc F { ~ 0 & 0xFF }
+            :calc G { 3 > 2 }
+            :calc H { - 1 + 2 }
+            : main :byte A :byte B :byte C :byte D :byte F :byte G :byte H
+        ";
+        assert_eq!(assemble(source).unwrap()[2..], [8, 5, 7, 3, 255, 1, 1]);
+    }
+
+    #[test]
+    fn loops_and_subroutines_run() {
+        let source = "
+            : add v0 += v1 ;
+            : main
+                v0 := 0 v1 := 0
+                loop
+                    v1 += 1
+                    add
+                    while v1 != 10
+                again
+            : done jump done
+        ";
+        let mut machine = Chip8::new(false).load_program(&assemble(source).unwrap());
+        for _ in 0..100 {
+            machine.run_instruction(false);
+        }
+        assert_eq!(machine.registers().v[0], 55);
+    }
+
+    #[test]
+    fn unsupported_parts_of_octo_are_rejected() {
+        for source in [
+            ": main i := long 0x300",
+            ": main plane 1",
+            ": main audio",
+            ": main pitch := v0",
+            ": main scroll-up 1",
+            ":stringmode x \"x\" { } : main",
+            ":assert \"x\" { 1 } : main",
+            ":calc X { sin 1 } : main",
+            ":calc X { PI } : main",
+            ":calc X { 2 pow 3 } : main",
+        ] {
+            assert!(
+                assemble(source).unwrap_err().contains("isn't supported"),
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn mistakes_are_reported() {
+        for (source, error) in [
+            (": main : main", "defined twice"),
+            (": main else", "'else' without 'begin'"),
+            (": main end", "'end' without 'begin'"),
+            (": main again", "'again' without 'loop'"),
+            (": main while v0 key", "outside of a loop"),
+            (": main sprite v0 v1 16", "at most 15 rows"),
+            (": main :byte later : later", "doesn't fit in a byte"),
+            (": main :org 0xFFE 1 2 3", "doesn't fit in memory"),
+            (": main :org 0x100", "can't assemble below 200"),
+            (": main v0 += 0x1FF", "doesn't fit in a byte"),
+            (": main i := 0x1000", "'0x1000' isn't an address"),
+            (": main if v0 ~ 1 then", "unknown condition"),
+            (":calc X { ( 1 } : main", "missing ')'"),
+        ] {
+            let actual = assemble(source).unwrap_err();
+            assert!(actual.contains(error), "{}: {}", source, actual);
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use std::collections::BTreeSet;
+
+use crate::interpreter::chip8::{MemoryWrite, Registers};
+use crate::interpreter::font::FONT_SIZE;
+use crate::interpreter::symbols::Symbols;
+
+const MEMORY_SIZE: usize = 4096;
+
+// Where the value of a memory byte came from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Tag {
+    Unwritten,
+    Font,
+    Rom,
+    // by the program, at runtime
+    Written,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Problem {
+    ExecutesUnwritten,
+    ExecutesFont,
+    SelfModifying,
+    SpriteUnwritten,
+    LoadsUnwritten,
+    WritesInterpreterArea,
+    PointsIntoInterpreterArea,
+}
+
+// Memory diagnostics: tags each byte of memory by where its value came
+// from and warns about suspicious uses, such as executing memory never
+// written, code overwriting itself, or sprites read from memory which
+// holds neither the ROM nor anything the program wrote.
+pub struct MemCheck {
+    tags: Vec<Tag>,
+    // bytes of executed instructions
+    executed: Vec<bool>,
+    // each problem is reported once per instruction address
+    reported: BTreeSet<(Problem, u16)>,
+    // the program's load address; memory below belongs to the interpreter
+    load_addr: u16,
+}
+
+impl MemCheck {
+    pub fn new(font_addr: u16, load_addr: u16) -> MemCheck {
+        let mut tags = vec![Tag::Unwritten; MEMORY_SIZE];
+        let font = font_addr as usize;
+        tags[font..font + FONT_SIZE].fill(Tag::Font);
+
+        MemCheck {
+            tags,
+            executed: vec![false; MEMORY_SIZE],
+            reported: BTreeSet::new(),
+            load_addr,
+        }
+    }
+
+    // Tags the bytes of the ROM, or another blob, loaded at given address.
+    pub fn loaded(mut self, addr: u16, len: usize) -> MemCheck {
+        let end = (addr as usize + len).min(MEMORY_SIZE);
+        self.tags[addr as usize..end].fill(Tag::Rom);
+        self
+    }
+
+    pub fn tag(&self, addr: u16) -> Tag {
+        self.tags[addr as usize % MEMORY_SIZE]
+    }
+
+    // Checks an instruction executed with the registers in state `before`;
+    // returns the warnings about problems not reported before.
+    pub fn check(
+        &mut self,
+        raw: u16,
+        before: &Registers,
+        after: &Registers,
+        writes: &[MemoryWrite],
+        symbols: &Symbols,
+    ) -> Vec<String> {
+        let pc = before.pc;
+        let mut problems = Vec::new();
+
+        let code = [pc, pc.wrapping_add(1)];
+        if code.iter().any(|&a| self.tag(a) == Tag::Unwritten) {
+            problems.push((
+                Problem::ExecutesUnwritten,
+                "executes memory never written".to_string(),
+            ));
+        } else if code.iter().any(|&a| self.tag(a) == Tag::Font) {
+            problems.push((Problem::ExecutesFont, "executes font data".to_string()));
+        }
+        for &a in &code {
+            self.executed[a as usize % MEMORY_SIZE] = true;
+        }
+
+        let x = raw >> 8 & 0xF;
+        let read = match (raw >> 12, raw & 0xFF) {
+            (0xD, _) => Some((raw & 0xF, Problem::SpriteUnwritten, "reads sprite")),
+            (0xF, 0x65) => Some((x + 1, Problem::LoadsUnwritten, "loads registers")),
+            _ => None,
+        };
+        if let Some((len, problem, what)) = read {
+            if (0..len).any(|n| self.tag(before.i.wrapping_add(n)) == Tag::Unwritten) {
+                problems.push((
+                    problem,
+                    format!("{} from memory never written at {:03X}", what, before.i),
+                ));
+            }
+        }
+
+        for w in writes {
+            let addr = w.addr as usize % MEMORY_SIZE;
+            if self.executed[addr] {
+                problems.push((
+                    Problem::SelfModifying,
+                    format!("writes to code at {:03X} (self-modifying code)", w.addr),
+                ));
+            }
+            if w.addr < self.load_addr {
+                problems.push((
+                    Problem::WritesInterpreterArea,
+                    format!(
+                        "writes to {:03X}, in the interpreter area below {:03X}",
+                        w.addr, self.load_addr
+                    ),
+                ));
+            }
+            self.tags[addr] = Tag::Written;
+        }
+
+        // FX29 / FX30 point I at the font, which is what they're for
+        let is_font = matches!(raw & 0xF0FF, 0xF029 | 0xF030);
+        if after.i != before.i && after.i < self.load_addr && !is_font {
+            problems.push((
+                Problem::PointsIntoInterpreterArea,
+                format!(
+                    "sets I to {:03X}, in the interpreter area below {:03X}",
+                    after.i, self.load_addr
+                ),
+            ));
+        }
+
+        problems
+            .into_iter()
+            .filter(|&(problem, _)| self.reported.insert((problem, pc)))
+            .map(|(_, message)| format!("{}: {}", symbols.describe(pc), message))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MemCheck, Tag};
+    use crate::interpreter::chip8::{Chip8, Registers, FONT_ADDR};
+    use crate::interpreter::symbols::Symbols;
+
+    // Runs the program loaded at given address, returning the warnings.
+    fn run(program: &[u8], load_addr: u16, instructions: usize) -> (MemCheck, Vec<String>) {
+        let mut machine = Chip8::new(false)
+            .with_load_address(load_addr)
+            .load_program(program);
+        let mut memcheck = MemCheck::new(FONT_ADDR, load_addr).loaded(load_addr, program.len());
+        let mut warnings = Vec::new();
+
+        for _ in 0..instructions {
+            let (raw, before) = (machine.next_opcode(), machine.registers());
+            machine.run_instruction(false);
+            let after: Registers = machine.registers();
+            warnings.extend(memcheck.check(
+                raw,
+                &before,
+                &after,
+                machine.memory_writes(),
+                &Symbols::default(),
+            ));
+        }
+        (memcheck, warnings)
+    }
+
+    #[test]
+    fn suspicious_memory_uses_are_reported_once() {
+        let program = [
+            0xA3, 0x00, // 200: LD I, 300
+            0xD0, 0x01, // 202: DRW V0, V0, 1
+            0xA2, 0x00, // 204: LD I, 200
+            0xF0, 0x55, // 206: LD [I], V0
+            0xA0, 0x10, // 208: LD I, 010
+            0x12, 0x00, // 20A: JP 200
+        ];
+        let (memcheck, warnings) = run(&program, 0x200, 12);
+
+        assert_eq!(
+            warnings,
+            [
+                "202: reads sprite from memory never written at 300",
+                "206: writes to code at 200 (self-modifying code)",
+                "208: sets I to 010, in the interpreter area below 200",
+            ]
+        );
+        assert_eq!(memcheck.tag(0x200), Tag::Written);
+        assert_eq!(memcheck.tag(0x201), Tag::Rom);
+        assert_eq!(memcheck.tag(0x050), Tag::Font);
+    }
+
+    #[test]
+    fn executing_unwritten_memory_is_reported() {
+        // JP 300
+        let (_, warnings) = run(&[0x13, 0x00], 0x200, 2);
+        assert_eq!(warnings, ["300: executes memory never written"]);
+    }
+
+    #[test]
+    fn interpreter_area_ends_at_the_load_address() {
+        let program = [
+            0xA4, 0x00, // 600: LD I, 400
+            0xF0, 0x55, // 602: LD [I], V0
+            0x16, 0x00, // 604: JP 600
+        ];
+        let (_, warnings) = run(&program, 0x600, 3);
+        assert_eq!(
+            warnings,
+            [
+                "600: sets I to 400, in the interpreter area below 600",
+                "602: writes to 400, in the interpreter area below 600",
+                "602: sets I to 401, in the interpreter area below 600",
+            ]
+        );
+
+        // LD I, 100; LD [I], V0
+        let (_, warnings) = run(&[0xA1, 0x00, 0xF0, 0x55], 0x000, 2);
+        assert!(warnings.is_empty());
+    }
+}
@@ -0,0 +1,337 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Canvas};
+use sdl2::video::Window;
+
+use crate::interpreter::chip8::Registers;
+use crate::InterpErr;
+
+// Glyphs are 5x7 points, each row stored in the lower 5 bits.
+pub const GLYPH_WIDTH: u32 = 5;
+pub const GLYPH_HEIGHT: u32 = 7;
+
+// How long a transient message stays on screen.
+const MESSAGE_DURATION: Duration = Duration::from_secs(2);
+
+// At most this many messages are displayed at once.
+const MAX_MESSAGES: usize = 4;
+
+const TEXT_COLOR: Color = Color::RGB(255, 255, 255);
+const PANEL_COLOR: Color = Color::RGBA(0, 0, 0, 170);
+
+// On-screen display, rendered over the emulated screen. Shows transient
+// messages, optionally the frame / instruction rate and the registers.
+pub struct Osd {
+    messages: VecDeque<(String, Instant)>,
+    pub show_fps: bool,
+    pub show_registers: bool,
+    counter: RateCounter,
+}
+
+struct RateCounter {
+    since: Instant,
+    frames: u32,
+    instructions: u64,
+    fps: u32,
+    ips: u64,
+}
+
+impl Osd {
+    pub fn new(show_fps: bool, show_registers: bool) -> Osd {
+        Osd {
+            messages: VecDeque::new(),
+            show_fps,
+            show_registers,
+            counter: RateCounter {
+                since: Instant::now(),
+                frames: 0,
+                instructions: 0,
+                fps: 0,
+                ips: 0,
+            },
+        }
+    }
+
+    // Shows a message for a short while; repeating a message
+    // which is already displayed only prolongs it.
+    pub fn message<S: Into<String>>(&mut self, msg: S) {
+        let msg = msg.into();
+        let expires = Instant::now() + MESSAGE_DURATION;
+
+        self.messages.retain(|(m, _)| *m != msg);
+        self.messages.push_back((msg, expires));
+        if self.messages.len() > MAX_MESSAGES {
+            self.messages.pop_front();
+        }
+    }
+
+    pub fn record_instructions(&mut self, count: u32) {
+        self.counter.instructions += count as u64;
+    }
+
+    // Whether anything should be drawn, i.e. screen needs to be redrawn every frame.
+    pub fn is_active(&mut self) -> bool {
+        self.expire_messages(Instant::now());
+        self.show_fps || self.show_registers || !self.messages.is_empty()
+    }
+
+    fn expire_messages(&mut self, now: Instant) {
+        self.messages.retain(|(_, expires)| *expires > now);
+    }
+
+    fn update_counter(&mut self) {
+        let c = &mut self.counter;
+        c.frames += 1;
+
+        let elapsed = c.since.elapsed();
+        if elapsed >= Duration::from_secs(1) {
+            c.fps = (c.frames as f64 / elapsed.as_secs_f64()).round() as u32;
+            c.ips = (c.instructions as f64 / elapsed.as_secs_f64()).round() as u64;
+            c.frames = 0;
+            c.instructions = 0;
+            c.since = Instant::now();
+        }
+    }
+
+    pub fn render(
+        &mut self,
+        canvas: &mut Canvas<Window>,
+        scale: u32,
+        regs: &Registers,
+    ) -> Result<(), InterpErr> {
+        self.update_counter();
+        let line_height = (GLYPH_HEIGHT + 2) * scale;
+        let (width, height) = canvas.output_size()?;
+
+        // top left: messages
+        let lines: Vec<&str> = self.messages.iter().map(|(m, _)| m.as_str()).collect();
+        draw_panel(canvas, &lines, scale, 0, 0)?;
+
+        // top right: rate counter
+        if self.show_fps {
+            let line = format!("FPS {} IPS {}", self.counter.fps, self.counter.ips);
+            let x = width as i32 - text_width(&line, scale) as i32 - 2 * scale as i32;
+            draw_panel(canvas, &[line.as_str()], scale, x, 0)?;
+        }
+
+        // bottom left: registers
+        if self.show_registers {
+            let lines = register_lines(regs);
+            let lines: Vec<&str> = lines.iter().map(|l| l.as_str()).collect();
+            let y = height as i32 - (lines.len() as u32 * line_height + 2 * scale) as i32;
+            draw_panel(canvas, &lines, scale, 0, y)?;
+        }
+
+        Ok(())
+    }
+}
+
+pub fn register_lines(regs: &Registers) -> Vec<String> {
+    let mut lines = vec![format!(
+        "PC {:03X} I {:03X} DT {:02X} ST {:02X}",
+        regs.pc, regs.i, regs.delay_timer, regs.sound_timer
+    )];
+
+    for (n, vals) in regs.v.chunks(4).enumerate() {
+        let line: Vec<String> = vals
+            .iter()
+            .enumerate()
+            .map(|(i, v)| format!("V{:X} {:02X}", n * 4 + i, v))
+            .collect();
+        lines.push(line.join(" "));
+    }
+
+    lines
+}
+
+pub fn text_width(text: &str, scale: u32) -> u32 {
+    text.chars().count() as u32 * (GLYPH_WIDTH + 1) * scale
+}
+
+// Draws lines of text on a translucent panel, with its top left corner at x, y.
+fn draw_panel(
+    canvas: &mut Canvas<Window>,
+    lines: &[&str],
+    scale: u32,
+    x: i32,
+    y: i32,
+) -> Result<(), InterpErr> {
+    if lines.is_empty() {
+        return Ok(());
+    }
+
+    let line_height = (GLYPH_HEIGHT + 2) * scale;
+    let width = lines
+        .iter()
+        .map(|l| text_width(l, scale))
+        .max()
+        .unwrap_or(0);
+
+    canvas.set_blend_mode(BlendMode::Blend);
+    canvas.set_draw_color(PANEL_COLOR);
+    canvas.fill_rect(Rect::new(
+        x,
+        y,
+        width + 3 * scale,
+        lines.len() as u32 * line_height + 2 * scale,
+    ))?;
+    canvas.set_blend_mode(BlendMode::None);
+
+    canvas.set_draw_color(TEXT_COLOR);
+    for (n, line) in lines.iter().enumerate() {
+        draw_text(
+            canvas,
+            line,
+            scale,
+            x + 2 * scale as i32,
+            y + (2 * scale + n as u32 * line_height) as i32,
+        )?;
+    }
+
+    Ok(())
+}
+
+pub fn draw_text(
+    canvas: &mut Canvas<Window>,
+    text: &str,
+    scale: u32,
+    x: i32,
+    y: i32,
+) -> Result<(), InterpErr> {
+    let mut rects = Vec::new();
+
+    for (n, c) in text.chars().enumerate() {
+        let gx = x + (n as u32 * (GLYPH_WIDTH + 1) * scale) as i32;
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..GLYPH_WIDTH {
+                if bits & (0x10 >> col) != 0 {
+                    rects.push(Rect::new(
+                        gx + (col * scale) as i32,
+                        y + (row as u32 * scale) as i32,
+                        scale,
+                        scale,
+                    ));
+                }
+            }
+        }
+    }
+
+    canvas.fill_rects(&rects)?;
+    Ok(())
+}
+
+// Returns the bitmap of given character; lower case letters are
+// displayed as upper case, unknown characters as '?'.
+pub fn glyph(c: char) -> [u8; 7] {
+    match c.to_ascii_uppercase() {
+        ' ' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        'A' => [0x0E, 0x11, 0x11, 0x11, 0x1F, 0x11, 0x11],
+        'B' => [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E],
+        'C' => [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+        'D' => [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C],
+        'E' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F],
+        'F' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10],
+        'G' => [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F],
+        'H' => [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'I' => [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F],
+        'M' => [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'P' => [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10],
+        'Q' => [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D],
+        'R' => [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11],
+        'S' => [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E],
+        'T' => [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A],
+        'X' => [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04],
+        'Z' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C],
+        ',' => [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08],
+        ':' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00],
+        ';' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x04, 0x08],
+        '-' => [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+        '+' => [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00],
+        '=' => [0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00],
+        '/' => [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+        '%' => [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03],
+        '(' => [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02],
+        ')' => [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08],
+        '[' => [0x0E, 0x08, 0x08, 0x08, 0x08, 0x08, 0x0E],
+        ']' => [0x0E, 0x02, 0x02, 0x02, 0x02, 0x02, 0x0E],
+        '<' => [0x02, 0x04, 0x08, 0x10, 0x08, 0x04, 0x02],
+        '>' => [0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08],
+        '!' => [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04],
+        '\'' => [0x04, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00],
+        '_' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F],
+        '#' => [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A],
+        '*' => [0x00, 0x04, 0x15, 0x0E, 0x15, 0x04, 0x00],
+        _ => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{glyph, register_lines, text_width, Osd};
+    use crate::interpreter::chip8::Registers;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn unknown_characters_are_displayed_as_question_mark() {
+        let question_mark = [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04];
+        assert_eq!(glyph('?'), question_mark);
+        assert_eq!(glyph('~'), question_mark);
+        assert_eq!(glyph('a'), glyph('A'));
+        assert_eq!(text_width("ab", 2), 2 * 6 * 2);
+    }
+
+    #[test]
+    fn messages_are_deduplicated_and_expire() {
+        let mut osd = Osd::new(false, false);
+        assert!(!osd.is_active());
+
+        osd.message("Paused");
+        osd.message("Paused");
+        osd.message(String::from("Speed: 8"));
+        assert!(osd.is_active());
+        assert_eq!(osd.messages.len(), 2);
+        assert_eq!(osd.messages.back().unwrap().0, "Speed: 8");
+
+        osd.expire_messages(Instant::now() + Duration::from_secs(5));
+        assert!(!osd.is_active());
+    }
+
+    #[test]
+    fn registers_are_formatted() {
+        let mut regs = Registers {
+            pc: 0x200,
+            v: [0; 16],
+            i: 0x50,
+            delay_timer: 1,
+            sound_timer: 0,
+        };
+        regs.v[15] = 0xAB;
+
+        let lines = register_lines(&regs);
+        assert_eq!(lines[0], "PC 200 I 050 DT 01 ST 00");
+        assert_eq!(lines[4], "VC 00 VD 00 VE 00 VF AB");
+    }
+}
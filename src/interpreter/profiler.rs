@@ -0,0 +1,322 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use crate::interpreter::analysis::Rom;
+use crate::interpreter::chip8::disassemble;
+use crate::interpreter::symbols::Symbols;
+use crate::InterpErr;
+
+const MEMORY_SIZE: usize = 4096;
+
+// Addresses listed as hot spots.
+const HOT_SPOTS: usize = 20;
+
+// Buckets of the instructions per frame histogram, and the length of its longest bar.
+const HISTOGRAM_BUCKETS: u64 = 10;
+const BAR_WIDTH: u64 = 40;
+
+// Counts executed instructions by address and opcode, and per frame;
+// on exit, writes a report of where the time went and which parts of
+// the ROM never ran, followed by the ROM's disassembly annotated with
+// the counts.
+pub struct Profiler {
+    rom: Vec<u8>,
+    load_addr: u16,
+    counts: Vec<u64>,
+    opcodes: BTreeMap<&'static str, u64>,
+    // instructions executed in the current frame
+    frame: u64,
+    // number of frames by instructions executed in them
+    frames: BTreeMap<u64, u64>,
+}
+
+impl Profiler {
+    pub fn new(rom: &[u8], load_addr: u16) -> Profiler {
+        Profiler {
+            rom: rom.to_vec(),
+            load_addr,
+            counts: vec![0; MEMORY_SIZE],
+            opcodes: BTreeMap::new(),
+            frame: 0,
+            frames: BTreeMap::new(),
+        }
+    }
+
+    pub fn record(&mut self, pc: u16, raw: u16) {
+        self.counts[pc as usize % MEMORY_SIZE] += 1;
+        *self.opcodes.entry(opcode_pattern(raw)).or_insert(0) += 1;
+        self.frame += 1;
+    }
+
+    pub fn end_frame(&mut self) {
+        *self.frames.entry(self.frame).or_insert(0) += 1;
+        self.frame = 0;
+    }
+
+    pub fn write_report(&self, path: &str, symbols: &Symbols) -> Result<(), InterpErr> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.report(&mut out, symbols)?;
+        Ok(out.flush()?)
+    }
+
+    pub fn report<W: Write>(&self, out: &mut W, symbols: &Symbols) -> io::Result<()> {
+        let total: u64 = self.counts.iter().sum();
+        let executed = self.counts.iter().filter(|&&c| c > 0).count();
+        writeln!(
+            out,
+            "{} instructions in {} frames, at {} addresses",
+            total,
+            self.frames.values().sum::<u64>(),
+            executed
+        )?;
+
+        writeln!(out, "\nHot spots:")?;
+        let mut hot: Vec<(u16, u64)> = (0..self.counts.len() as u16)
+            .map(|a| (a, self.counts[a as usize]))
+            .filter(|&(_, c)| c > 0)
+            .collect();
+        hot.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        let rom = Rom::at(&self.rom, self.load_addr);
+        for &(addr, count) in hot.iter().take(HOT_SPOTS) {
+            let instr = match rom.opcode_at(addr) {
+                Some(raw) => disassemble(raw, |a| symbols.label(a)),
+                None => String::new(),
+            };
+            let label = symbols
+                .nearest_label(addr)
+                .map(|l| format!("  ({})", l))
+                .unwrap_or_default();
+            writeln!(
+                out,
+                "{:>10}  {:>5.1}%  {:03X}  {}{}",
+                count,
+                percent(count, total),
+                addr,
+                instr,
+                label
+            )?;
+        }
+
+        writeln!(out, "\nOpcodes:")?;
+        let mut opcodes: Vec<(&str, u64)> = self.opcodes.iter().map(|(&p, &c)| (p, c)).collect();
+        opcodes.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+        for (pattern, count) in opcodes {
+            writeln!(
+                out,
+                "{:>10}  {:>5.1}%  {}",
+                count,
+                percent(count, total),
+                pattern
+            )?;
+        }
+
+        writeln!(out, "\nNever executed:")?;
+        for (start, end, reachable) in self.unexecuted_regions() {
+            writeln!(
+                out,
+                "{:03X}-{:03X}  {:>4} bytes  {}",
+                start,
+                end,
+                end - start + 1,
+                if reachable {
+                    "code"
+                } else {
+                    "data or dead code"
+                }
+            )?;
+        }
+
+        writeln!(out, "\nInstructions per frame:")?;
+        let histogram = self.histogram();
+        let most = histogram.iter().map(|b| b.2).max().unwrap_or(1);
+        for (low, high, frames) in histogram {
+            let bar = "#".repeat((frames * BAR_WIDTH).div_ceil(most) as usize);
+            let line = format!("{:>7}-{:<7} {:>8}  {}", low, high, frames, bar);
+            writeln!(out, "{}", line.trim_end())?;
+        }
+
+        writeln!(out, "\nDisassembly:")?;
+        self.annotated_disassembly(out, symbols)
+    }
+
+    // Byte ranges of the ROM (inclusive) not covered by any executed
+    // instruction, and whether they hold statically reachable code.
+    fn unexecuted_regions(&self) -> Vec<(u16, u16, bool)> {
+        let rom = Rom::at(&self.rom, self.load_addr);
+        let reachable = rom.reachable();
+        let covered = |addr: u16| {
+            self.counts[addr as usize] > 0
+                || self.counts[(addr as usize + MEMORY_SIZE - 1) % MEMORY_SIZE] > 0
+        };
+
+        let mut regions: Vec<(u16, u16, bool)> = Vec::new();
+        for addr in rom.start()..rom.end() as u16 {
+            if covered(addr) {
+                continue;
+            }
+            let code = reachable.contains_key(&addr);
+            match regions.last_mut() {
+                Some((_, end, r)) if *end + 1 == addr => {
+                    *end = addr;
+                    *r |= code;
+                }
+                _ => regions.push((addr, addr, code)),
+            }
+        }
+
+        regions
+    }
+
+    // Frames counted into buckets of equal ranges of instructions per frame,
+    // as (lowest, highest, frames).
+    fn histogram(&self) -> Vec<(u64, u64, u64)> {
+        let (min, max) = match (self.frames.keys().next(), self.frames.keys().next_back()) {
+            (Some(&min), Some(&max)) => (min, max),
+            _ => return Vec::new(),
+        };
+        let width = (max - min + 1).div_ceil(HISTOGRAM_BUCKETS);
+
+        let mut buckets = Vec::new();
+        let mut low = min;
+        while low <= max {
+            let high = low + width - 1;
+            let frames = self.frames.range(low..=high).map(|(_, &f)| f).sum();
+            buckets.push((low, high, frames));
+            low += width;
+        }
+        buckets
+    }
+
+    fn annotated_disassembly<W: Write>(&self, out: &mut W, symbols: &Symbols) -> io::Result<()> {
+        let rom = Rom::at(&self.rom, self.load_addr);
+        let mut addr = rom.start();
+
+        while let Some(raw) = rom.opcode_at(addr) {
+            if let Some(label) = symbols.label(addr) {
+                writeln!(out, "{}:", label)?;
+            }
+            let count = match self.counts[addr as usize] {
+                0 => "-".to_string(),
+                c => c.to_string(),
+            };
+            writeln!(
+                out,
+                "{:>10}  {:03X}  {:04X}  {}",
+                count,
+                addr,
+                raw,
+                disassemble(raw, |a| symbols.label(a))
+            )?;
+
+            // an instruction executed at an odd address is listed as well
+            addr += if self.counts[addr as usize + 1] > 0 {
+                1
+            } else {
+                2
+            };
+        }
+
+        Ok(())
+    }
+}
+
+fn percent(count: u64, total: u64) -> f64 {
+    100.0 * count as f64 / total.max(1) as f64
+}
+
+// Opcode with its operands replaced by their placeholders, e.g. "DXYN".
+fn opcode_pattern(raw: u16) -> &'static str {
+    match (raw >> 12, raw & 0xFF) {
+        (0x0, 0xE0) if raw == 0x00E0 => "00E0",
+        (0x0, 0xEE) if raw == 0x00EE => "00EE",
+        (0x0, _) => "0NNN",
+        (0x1, _) => "1NNN",
+        (0x2, _) => "2NNN",
+        (0x3, _) => "3XKK",
+        (0x4, _) => "4XKK",
+        (0x5, _) => "5XY0",
+        (0x6, _) => "6XKK",
+        (0x7, _) => "7XKK",
+        (0x8, _) => match raw & 0xF {
+            0x0 => "8XY0",
+            0x1 => "8XY1",
+            0x2 => "8XY2",
+            0x3 => "8XY3",
+            0x4 => "8XY4",
+            0x5 => "8XY5",
+            0x6 => "8XY6",
+            0x7 => "8XY7",
+            0xE => "8XYE",
+            _ => "8XY?",
+        },
+        (0x9, _) => "9XY0",
+        (0xA, _) => "ANNN",
+        (0xB, _) => "BNNN",
+        (0xC, _) => "CXKK",
+        (0xD, _) => "DXYN",
+        (0xE, 0x9E) => "EX9E",
+        (0xE, 0xA1) => "EXA1",
+        (0xF, 0x07) => "FX07",
+        (0xF, 0x0A) => "FX0A",
+        (0xF, 0x15) => "FX15",
+        (0xF, 0x18) => "FX18",
+        (0xF, 0x1E) => "FX1E",
+        (0xF, 0x29) => "FX29",
+        (0xF, 0x30) => "FX30",
+        (0xF, 0x33) => "FX33",
+        (0xF, 0x55) => "FX55",
+        (0xF, 0x65) => "FX65",
+        _ => "unknown",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Profiler;
+    use crate::interpreter::symbols::Symbols;
+
+    #[test]
+    fn report_shows_hot_spots_and_unexecuted_code() {
+        let rom = [
+            0x60, 0x01, // 200: LD V0, 01
+            0x30, 0x00, // 202: SE V0, 00
+            0x12, 0x00, // 204: JP 200
+            0x12, 0x08, // 206: JP 208
+            0xFF, 0xFF, // 208: data
+        ];
+        let mut profiler = Profiler::new(&rom, 0x200);
+        for frame in 0..3 {
+            for _ in 0..=frame {
+                profiler.record(0x200, 0x6001);
+                profiler.record(0x202, 0x3000);
+                profiler.record(0x204, 0x1200);
+            }
+            profiler.end_frame();
+        }
+
+        let mut out = Vec::new();
+        profiler.report(&mut out, &Symbols::default()).unwrap();
+        let report = String::from_utf8(out).unwrap();
+
+        assert!(report.starts_with("18 instructions in 3 frames, at 3 addresses\n"));
+        assert!(report.contains("         6   33.3%  200  LD V0, 01\n"));
+        assert!(report.contains("         6   33.3%  6XKK\n"));
+        assert!(report.contains("206-209     4 bytes  code\n"));
+        assert!(report.contains("      4-4              0\n"));
+        assert!(report.contains("         -  206  1208  JP 208\n"));
+    }
+
+    #[test]
+    fn program_can_be_loaded_at_zero() {
+        let mut profiler = Profiler::new(&[0x10, 0x00, 0xFF, 0xFF], 0x000);
+        profiler.record(0x000, 0x1000);
+        profiler.end_frame();
+
+        let mut out = Vec::new();
+        profiler.report(&mut out, &Symbols::default()).unwrap();
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .contains("002-003     2 bytes  data or dead code\n"));
+    }
+}
@@ -0,0 +1,36 @@
+use sdl2::rect::Rect;
+
+use crate::interpreter::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+// Window rectangles of the lit display pixels, each display pixel
+// being a square of `scale` window pixels.
+pub fn display_rects(display: &[bool], scale: u32) -> Vec<Rect> {
+    display
+        .iter()
+        .take(DISPLAY_WIDTH * DISPLAY_HEIGHT)
+        .enumerate()
+        .filter(|(_, &lit)| lit)
+        .map(|(xy, _)| {
+            let (x, y) = ((xy % DISPLAY_WIDTH) as u32, (xy / DISPLAY_WIDTH) as u32);
+            Rect::new((x * scale) as i32, (y * scale) as i32, scale, scale)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::display_rects;
+    use sdl2::rect::Rect;
+
+    #[test]
+    fn lit_pixels_are_scaled_to_rects() {
+        let mut display = [false; 64 * 32];
+        display[64 + 2] = true;
+        display[64 * 32 - 1] = true;
+
+        assert_eq!(
+            display_rects(&display, 10),
+            [Rect::new(20, 10, 10, 10), Rect::new(630, 310, 10, 10)]
+        );
+    }
+}
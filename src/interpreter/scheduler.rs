@@ -0,0 +1,131 @@
+use std::time::Duration;
+
+// CHIP-8 delay and sound timers always count down at 60 Hz.
+pub const TIMER_FREQUENCY: u32 = 60;
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+// If the host stalls (window dragged, machine suspended etc.) for
+// longer than this, the remaining time is dropped instead of being
+// caught up, so the emulation doesn't suddenly fast-forward.
+const MAX_CATCH_UP_FRAMES: u32 = 5;
+
+// Work which should be done to catch up with the real time elapsed.
+#[derive(Debug, PartialEq)]
+pub struct Tick {
+    pub timer_ticks: u32,
+    pub instructions: u32,
+}
+
+// Converts real elapsed time into timer ticks and instructions to execute.
+// Both are tracked with separate accumulators (in units of ns * frequency),
+// so no fractions are lost between frames and the rates are exact over time,
+// independently of how often the main loop actually runs.
+pub struct Scheduler {
+    instruction_rate: u32,
+    timer_acc: u128,
+    instruction_acc: u128,
+}
+
+impl Scheduler {
+    pub fn new(instruction_rate: u32) -> Scheduler {
+        Scheduler {
+            instruction_rate,
+            timer_acc: 0,
+            instruction_acc: 0,
+        }
+    }
+
+    pub fn set_instruction_rate(&mut self, instruction_rate: u32) {
+        self.instruction_rate = instruction_rate;
+    }
+
+    pub fn advance(&mut self, elapsed: Duration) -> Tick {
+        let max_elapsed =
+            (NANOS_PER_SEC * MAX_CATCH_UP_FRAMES as u128).div_ceil(TIMER_FREQUENCY as u128);
+        let nanos = elapsed.as_nanos().min(max_elapsed);
+
+        self.timer_acc += nanos * TIMER_FREQUENCY as u128;
+        self.instruction_acc += nanos * self.instruction_rate as u128;
+
+        let tick = Tick {
+            timer_ticks: (self.timer_acc / NANOS_PER_SEC) as u32,
+            instructions: (self.instruction_acc / NANOS_PER_SEC) as u32,
+        };
+
+        self.timer_acc %= NANOS_PER_SEC;
+        self.instruction_acc %= NANOS_PER_SEC;
+        tick
+    }
+
+    // Time remaining until the timers are due to tick again.
+    pub fn until_next_timer_tick(&self) -> Duration {
+        let remaining = (NANOS_PER_SEC - self.timer_acc) / TIMER_FREQUENCY as u128;
+        Duration::from_nanos(remaining as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Scheduler, Tick};
+    use std::time::Duration;
+
+    #[test]
+    fn timers_tick_at_60_hz() {
+        let mut sched = Scheduler::new(250);
+        let mut timer_ticks = 0;
+        let mut instructions = 0;
+
+        // uneven host frames, adding up to exactly one second
+        for _ in 0..100 {
+            let tick = sched.advance(Duration::from_millis(7));
+            timer_ticks += tick.timer_ticks;
+            instructions += tick.instructions;
+        }
+        for _ in 0..30 {
+            let tick = sched.advance(Duration::from_millis(10));
+            timer_ticks += tick.timer_ticks;
+            instructions += tick.instructions;
+        }
+
+        assert_eq!(timer_ticks, 60);
+        assert_eq!(instructions, 250);
+    }
+
+    #[test]
+    fn fractions_are_carried_over() {
+        let mut sched = Scheduler::new(1000);
+
+        // 1.5 instructions per call
+        assert_eq!(sched.advance(Duration::from_micros(1500)).instructions, 1);
+        assert_eq!(sched.advance(Duration::from_micros(1500)).instructions, 2);
+    }
+
+    #[test]
+    fn long_stalls_are_not_caught_up() {
+        let mut sched = Scheduler::new(600);
+
+        assert_eq!(
+            sched.advance(Duration::from_secs(3)),
+            Tick {
+                timer_ticks: 5,
+                instructions: 50
+            }
+        );
+    }
+
+    #[test]
+    fn time_until_next_tick_is_reported() {
+        let mut sched = Scheduler::new(600);
+        assert_eq!(
+            sched.until_next_timer_tick(),
+            Duration::from_nanos(16_666_666)
+        );
+
+        sched.advance(Duration::from_millis(10));
+        assert_eq!(
+            sched.until_next_timer_tick(),
+            Duration::from_nanos(6_666_666)
+        );
+    }
+}
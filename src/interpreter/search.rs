@@ -0,0 +1,205 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::interpreter::chip8::Chip8;
+use crate::interpreter::symbols::Symbols;
+
+// A byte of the machine state which can be searched and pinned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Location {
+    Register(usize),
+    Memory(u16),
+}
+
+impl Location {
+    // Parses a register (V0 - VF), a label or a memory address in hex.
+    pub fn parse(s: &str, symbols: &Symbols) -> Option<Location> {
+        let mut chars = s.chars();
+        if let (Some('V' | 'v'), Some(x), None) = (chars.next(), chars.next(), chars.next()) {
+            if let Some(x) = x.to_digit(16) {
+                return Some(Location::Register(x as usize));
+            }
+        }
+
+        symbols.resolve(s).map(Location::Memory)
+    }
+
+    pub fn read(&self, machine: &Chip8) -> u8 {
+        match *self {
+            Location::Register(x) => machine.registers().v[x],
+            Location::Memory(addr) => machine.memory()[addr as usize],
+        }
+    }
+
+    pub fn write(&self, machine: &mut Chip8, val: u8) {
+        match *self {
+            Location::Register(x) => {
+                let mut regs = machine.registers();
+                regs.v[x] = val;
+                machine.set_registers(&regs);
+            }
+            Location::Memory(addr) => machine.poke(addr, val),
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Location::Register(x) => write!(f, "V{:X}", x),
+            Location::Memory(addr) => write!(f, "{:03X}", addr),
+        }
+    }
+}
+
+// How the current values of the candidates are compared
+// with their values at the previous search step.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    Unchanged,
+    Changed,
+    Increased,
+    Decreased,
+    Value(u8),
+}
+
+impl Filter {
+    fn keeps(&self, previous: u8, current: u8) -> bool {
+        match *self {
+            Filter::Unchanged => current == previous,
+            Filter::Changed => current != previous,
+            Filter::Increased => current > previous,
+            Filter::Decreased => current < previous,
+            Filter::Value(v) => current == v,
+        }
+    }
+}
+
+// Cheat finder: narrows down the registers and memory addresses
+// holding a game variable (lives, score...) by repeatedly filtering
+// them by how their values changed while the game was running.
+#[derive(Default)]
+pub struct RamSearch {
+    // candidate locations with their values at the last step
+    candidates: Vec<(Location, u8)>,
+}
+
+impl RamSearch {
+    // Starts a new search with all registers and memory as candidates.
+    pub fn start(&mut self, machine: &Chip8) {
+        let registers = (0..16).map(Location::Register);
+        let memory = (0..machine.memory().len() as u16).map(Location::Memory);
+
+        self.candidates = registers
+            .chain(memory)
+            .map(|l| (l, l.read(machine)))
+            .collect();
+    }
+
+    // Keeps only the candidates passing given filter; returns how many are left.
+    pub fn filter(&mut self, machine: &Chip8, filter: Filter) -> usize {
+        self.candidates.retain_mut(|(location, value)| {
+            let current = location.read(machine);
+            let keep = filter.keeps(*value, current);
+            *value = current;
+            keep
+        });
+
+        self.candidates.len()
+    }
+
+    pub fn candidates(&self) -> &[(Location, u8)] {
+        &self.candidates
+    }
+}
+
+// Values written to their locations every frame, so the program can't change them.
+#[derive(Default)]
+pub struct Pins {
+    pins: BTreeMap<Location, u8>,
+}
+
+impl Pins {
+    pub fn pin(&mut self, location: Location, val: u8) {
+        self.pins.insert(location, val);
+    }
+
+    // Returns false if the location wasn't pinned.
+    pub fn unpin(&mut self, location: Location) -> bool {
+        self.pins.remove(&location).is_some()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Location, &u8)> {
+        self.pins.iter()
+    }
+
+    pub fn apply(&self, machine: &mut Chip8) {
+        for (location, &val) in &self.pins {
+            location.write(machine, val);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Filter, Location, Pins, RamSearch};
+    use crate::interpreter::chip8::Chip8;
+    use crate::interpreter::symbols::Symbols;
+
+    #[test]
+    fn candidates_are_narrowed_down_by_filters() {
+        let mut machine = Chip8::new(false);
+        let lives = Location::Memory(0x300);
+        let mut search = RamSearch::default();
+
+        lives.write(&mut machine, 3);
+        search.start(&machine);
+
+        lives.write(&mut machine, 2);
+        Location::Register(4).write(&mut machine, 5);
+        assert_eq!(search.filter(&machine, Filter::Changed), 2);
+
+        assert_eq!(search.filter(&machine, Filter::Unchanged), 2);
+
+        lives.write(&mut machine, 1);
+        assert_eq!(search.filter(&machine, Filter::Decreased), 1);
+        assert_eq!(search.candidates(), &[(lives, 1)]);
+
+        assert_eq!(search.filter(&machine, Filter::Value(7)), 0);
+    }
+
+    #[test]
+    fn pinned_values_are_restored() {
+        let mut machine = Chip8::new(false);
+        let mut pins = Pins::default();
+
+        pins.pin(Location::Register(0xA), 9);
+        pins.pin(Location::Memory(0x300), 3);
+        pins.apply(&mut machine);
+        assert_eq!(machine.registers().v[0xA], 9);
+        assert_eq!(machine.memory()[0x300], 3);
+
+        assert!(pins.unpin(Location::Register(0xA)));
+        assert!(!pins.unpin(Location::Register(0xA)));
+    }
+
+    #[test]
+    fn locations_are_parsed() {
+        let symbols = Symbols::parse("lives 3F0").unwrap();
+
+        assert_eq!(
+            Location::parse("VA", &symbols),
+            Some(Location::Register(0xA))
+        );
+        assert_eq!(Location::parse("v0", &symbols), Some(Location::Register(0)));
+        assert_eq!(
+            Location::parse("lives", &symbols),
+            Some(Location::Memory(0x3F0))
+        );
+        assert_eq!(
+            Location::parse("2A0", &symbols),
+            Some(Location::Memory(0x2A0))
+        );
+        assert_eq!(Location::parse("VG", &symbols), None);
+    }
+}
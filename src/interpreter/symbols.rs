@@ -0,0 +1,220 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use crate::InterpErr;
+
+// Labels further than this from the nearest preceding one
+// are not shown relative to it (e.g. as "main+4").
+const MAX_LABEL_OFFSET: u16 = 0x100;
+
+// Label names and source locations of a ROM, loaded from a symbol file
+// as exported by Octo or an assembler. Each line holds a label and its
+// address in hex, in either order, optionally separated by '=':
+//
+//   main 0x200
+//   0x2A0 draw_player
+//   sprite_ball = 0x3F0
+//
+// As labels like "bad" are valid hex too, the address is the part with
+// a "0x" or "$" prefix, or else the one starting with a decimal digit,
+// which labels can't; lines where that doesn't decide it are rejected.
+//
+// Lines where the second part has the form FILE:LINE map the address
+// to its source location instead, e.g. "0x202 game.8o:14".
+// Empty lines and lines starting with '#' or ';' are ignored.
+#[derive(Debug, Default)]
+pub struct Symbols {
+    labels: BTreeMap<u16, String>,
+    addresses: HashMap<String, u16>,
+    sources: BTreeMap<u16, String>,
+}
+
+impl Symbols {
+    pub fn load(path: &str) -> Result<Symbols, InterpErr> {
+        let content = std::fs::read_to_string(path)?;
+        Ok(Symbols::parse(&content).map_err(|e| format!("{}: {}", path, e))?)
+    }
+
+    // Symbol file accompanying the ROM, if any: the ROM path with
+    // its extension replaced by (or, if there is none, extended with) ".sym".
+    pub fn find_for_rom(rom: &str) -> Option<String> {
+        let path = Path::new(rom).with_extension("sym");
+        if path.is_file() {
+            return path.to_str().map(String::from);
+        }
+        None
+    }
+
+    pub fn parse(content: &str) -> Result<Symbols, String> {
+        let mut symbols = Symbols::default();
+
+        for (n, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            let parts: Vec<&str> = line
+                .split(|c: char| c.is_whitespace() || c == '=')
+                .filter(|p| !p.is_empty())
+                .collect();
+            let (addr, name) = match parts[..] {
+                [a, b] => match (parse_symbol_address(a), parse_symbol_address(b)) {
+                    (Some((addr, true)), Some((_, false))) | (Some((addr, _)), None) => (addr, b),
+                    (Some((_, false)), Some((addr, true))) | (None, Some((addr, _))) => (addr, a),
+                    (None, None) => {
+                        return Err(format!("line {}: no valid address in '{}'", n + 1, line))
+                    }
+                    _ => {
+                        return Err(format!(
+                            "line {}: can't tell the address from the label in '{}'",
+                            n + 1,
+                            line
+                        ))
+                    }
+                },
+                _ => {
+                    return Err(format!(
+                        "line {}: expected label and address, got '{}'",
+                        n + 1,
+                        line
+                    ))
+                }
+            };
+
+            if is_source_location(name) {
+                symbols.sources.insert(addr, name.to_string());
+            } else {
+                symbols.labels.insert(addr, name.to_string());
+                symbols.addresses.insert(name.to_string(), addr);
+            }
+        }
+
+        Ok(symbols)
+    }
+
+    // Label at exactly given address.
+    pub fn label(&self, addr: u16) -> Option<&str> {
+        self.labels.get(&addr).map(String::as_str)
+    }
+
+    pub fn address(&self, label: &str) -> Option<u16> {
+        self.addresses.get(label).copied()
+    }
+
+    pub fn source(&self, addr: u16) -> Option<&str> {
+        self.sources.get(&addr).map(String::as_str)
+    }
+
+    // Address relative to the nearest preceding label, e.g. "main+4",
+    // if there's a label close enough.
+    pub fn nearest_label(&self, addr: u16) -> Option<String> {
+        match self.labels.range(..=addr).next_back() {
+            Some((&a, name)) if a == addr => Some(name.clone()),
+            Some((&a, name)) if addr - a <= MAX_LABEL_OFFSET => {
+                Some(format!("{}+{:X}", name, addr - a))
+            }
+            _ => None,
+        }
+    }
+
+    // The nearest label as above, otherwise the address in hex.
+    pub fn describe(&self, addr: u16) -> String {
+        self.nearest_label(addr)
+            .unwrap_or_else(|| format!("{:03X}", addr))
+    }
+
+    // Resolves a label name or an address in hex.
+    pub fn resolve(&self, s: &str) -> Option<u16> {
+        self.address(s).or_else(|| parse_address(s))
+    }
+}
+
+// Address in hex, with an optional "0x" or "$" prefix.
+fn parse_address(s: &str) -> Option<u16> {
+    let hex = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix('$'))
+        .unwrap_or(s);
+
+    match u16::from_str_radix(hex, 16) {
+        Ok(addr) if addr <= 0xFFF => Some(addr),
+        _ => None,
+    }
+}
+
+// Address in a symbol file, and whether it's marked as one by a
+// prefix or a leading decimal digit (as opposed to e.g. "bad").
+fn parse_symbol_address(s: &str) -> Option<(u16, bool)> {
+    let marked =
+        s.starts_with("0x") || s.starts_with('$') || s.starts_with(|c: char| c.is_ascii_digit());
+    parse_address(s).map(|addr| (addr, marked))
+}
+
+fn is_source_location(s: &str) -> bool {
+    match s.rsplit_once(':') {
+        Some((file, line)) => {
+            !file.is_empty() && !line.is_empty() && line.chars().all(|c| c.is_ascii_digit())
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Symbols;
+
+    #[test]
+    fn symbol_files_are_parsed() {
+        let symbols = Symbols::parse(
+            "# symbols\n\
+             main 0x200\n\
+             0x2A0 draw_player\n\
+             sprite_ball = $3F0\n\
+             beef 204\n\
+             0x202 game.8o:14\n",
+        )
+        .unwrap();
+
+        assert_eq!(symbols.label(0x200), Some("main"));
+        assert_eq!(symbols.address("draw_player"), Some(0x2A0));
+        assert_eq!(symbols.address("sprite_ball"), Some(0x3F0));
+        assert_eq!(symbols.address("beef"), Some(0x204));
+        assert_eq!(symbols.source(0x202), Some("game.8o:14"));
+        assert_eq!(symbols.label(0x202), None);
+    }
+
+    #[test]
+    fn invalid_lines_are_rejected() {
+        assert!(Symbols::parse("main\n").is_err());
+        assert!(Symbols::parse("main loop\n").is_err());
+        assert!(Symbols::parse("main 0x200 extra\n").is_err());
+        assert!(Symbols::parse("cab fed\n").is_err());
+        assert!(Symbols::parse("0x200 0x300\n").is_err());
+    }
+
+    #[test]
+    fn hex_looking_labels_are_not_taken_for_addresses() {
+        let symbols = Symbols::parse("0x2A0 bad\n200 add\ncab = $300\nfed 2F0\n").unwrap();
+
+        assert_eq!(symbols.address("bad"), Some(0x2A0));
+        assert_eq!(symbols.address("add"), Some(0x200));
+        assert_eq!(symbols.address("cab"), Some(0x300));
+        assert_eq!(symbols.address("fed"), Some(0x2F0));
+    }
+
+    #[test]
+    fn addresses_are_described_relative_to_labels() {
+        let symbols = Symbols::parse("main 200\nloop 210\n").unwrap();
+
+        assert_eq!(symbols.describe(0x200), "main");
+        assert_eq!(symbols.describe(0x20A), "main+A");
+        assert_eq!(symbols.describe(0x212), "loop+2");
+        assert_eq!(symbols.describe(0x1FE), "1FE");
+        assert_eq!(symbols.describe(0x400), "400");
+
+        assert_eq!(symbols.resolve("loop"), Some(0x210));
+        assert_eq!(symbols.resolve("2A0"), Some(0x2A0));
+        assert_eq!(symbols.resolve("nowhere"), None);
+    }
+}
@@ -0,0 +1,366 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::str::FromStr;
+
+use crate::interpreter::chip8::{disassemble, MemoryWrite, Registers};
+use crate::interpreter::symbols::Symbols;
+use crate::InterpErr;
+
+// Binary traces start with the magic and a format version.
+const BINARY_MAGIC: &[u8; 4] = b"YTRC";
+const BINARY_VERSION: u8 = 1;
+
+// Register ids used in binary traces.
+const REG_I: u8 = 16;
+const REG_DT: u8 = 17;
+const REG_ST: u8 = 18;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TraceFormat {
+    Text,
+    Binary,
+}
+
+impl FromStr for TraceFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<TraceFormat, String> {
+        match s {
+            "text" => Ok(TraceFormat::Text),
+            "binary" => Ok(TraceFormat::Binary),
+            _ => Err(format!("unknown trace format '{}'", s)),
+        }
+    }
+}
+
+// Restricts which instructions are written to the trace;
+// an instruction has to pass both the address range and the opcode classes.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TraceFilter {
+    // Inclusive range of program counter values.
+    pub range: Option<(u16, u16)>,
+    // Opcode classes, identified by the first nibble of the opcode.
+    pub opcode_classes: Option<Vec<u8>>,
+}
+
+impl TraceFilter {
+    fn accepts(&self, pc: u16, raw: u16) -> bool {
+        let in_range = self
+            .range
+            .is_none_or(|(start, end)| (start..=end).contains(&pc));
+        let in_class = self
+            .opcode_classes
+            .as_ref()
+            .is_none_or(|c| c.contains(&((raw >> 12) as u8)));
+
+        in_range && in_class
+    }
+}
+
+// Parses an address range in hex, such as "200-2FF".
+pub fn parse_address_range(s: &str) -> Result<(u16, u16), String> {
+    let (start, end) = s
+        .split_once('-')
+        .ok_or_else(|| format!("expected range in format START-END, got '{}'", s))?;
+    let parse = |a: &str| {
+        u16::from_str_radix(a.trim_start_matches("0x"), 16)
+            .map_err(|e| format!("invalid address '{}': {}", a, e))
+    };
+
+    let (start, end) = (parse(start)?, parse(end)?);
+    if start > end {
+        return Err(format!(
+            "range start {:X} is after its end {:X}",
+            start, end
+        ));
+    }
+    Ok((start, end))
+}
+
+// Parses a comma separated list of opcode classes, such as "1,2,D".
+pub fn parse_opcode_classes(s: &str) -> Result<Vec<u8>, String> {
+    s.split(',')
+        .map(|c| match u8::from_str_radix(c.trim(), 16) {
+            Ok(n) if n <= 0xF => Ok(n),
+            _ => Err(format!("invalid opcode class '{}', expected 0-F", c)),
+        })
+        .collect()
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceConfig {
+    pub path: String,
+    pub format: TraceFormat,
+    pub filter: TraceFilter,
+}
+
+// Writes every executed instruction along with the register changes
+// and memory writes it caused, so that runs can be diffed later.
+//
+// The text format has one line per instruction:
+//   PC  OPCODE  MNEMONIC  [REG=VALUE ...] [[ADDR]=VALUE ...]
+//
+// The binary format starts with "YTRC" and a version byte, followed by records:
+//   PC (u16), OPCODE (u16),
+//   count (u8) of register changes, each: id (u8: 0-15 V, 16 I, 17 DT, 18 ST), value (u16),
+//   count (u8) of memory writes, each: address (u16), value (u8)
+// with all multi-byte values big endian.
+pub struct Tracer<W: Write> {
+    out: W,
+    format: TraceFormat,
+    filter: TraceFilter,
+}
+
+impl Tracer<BufWriter<File>> {
+    pub fn create(config: &TraceConfig) -> Result<Tracer<BufWriter<File>>, InterpErr> {
+        let out = BufWriter::new(File::create(&config.path)?);
+        Ok(Tracer::new(out, config.format, config.filter.clone())?)
+    }
+}
+
+impl<W: Write> Tracer<W> {
+    pub fn new(mut out: W, format: TraceFormat, filter: TraceFilter) -> io::Result<Tracer<W>> {
+        if format == TraceFormat::Binary {
+            out.write_all(BINARY_MAGIC)?;
+            out.write_all(&[BINARY_VERSION])?;
+        }
+
+        Ok(Tracer {
+            out,
+            format,
+            filter,
+        })
+    }
+
+    // Records an instruction executed with the registers in state `before`.
+    pub fn record(
+        &mut self,
+        raw: u16,
+        before: &Registers,
+        after: &Registers,
+        writes: &[MemoryWrite],
+        symbols: &Symbols,
+    ) -> io::Result<()> {
+        if !self.filter.accepts(before.pc, raw) {
+            return Ok(());
+        }
+
+        let changes = register_changes(before, after);
+        match self.format {
+            TraceFormat::Text => self.write_text(before.pc, raw, &changes, writes, symbols),
+            TraceFormat::Binary => self.write_binary(before.pc, raw, &changes, writes),
+        }
+    }
+
+    #[cfg(test)]
+    fn into_inner(self) -> W {
+        self.out
+    }
+
+    fn write_text(
+        &mut self,
+        pc: u16,
+        raw: u16,
+        changes: &[(u8, u16)],
+        writes: &[MemoryWrite],
+        symbols: &Symbols,
+    ) -> io::Result<()> {
+        let instr = disassemble(raw, |a| symbols.label(a));
+        let mut line = format!("{:03X}  {:04X}  {:<16}", pc, raw, instr);
+
+        for &(reg, val) in changes {
+            line.push_str(&match reg {
+                REG_I => format!(" I={:03X}", val),
+                REG_DT => format!(" DT={:02X}", val),
+                REG_ST => format!(" ST={:02X}", val),
+                _ => format!(" V{:X}={:02X}", reg, val),
+            });
+        }
+        for w in writes {
+            line.push_str(&format!(" [{:03X}]={:02X}", w.addr, w.value));
+        }
+
+        writeln!(self.out, "{}", line.trim_end())
+    }
+
+    fn write_binary(
+        &mut self,
+        pc: u16,
+        raw: u16,
+        changes: &[(u8, u16)],
+        writes: &[MemoryWrite],
+    ) -> io::Result<()> {
+        let mut record = Vec::with_capacity(6 + 3 * (changes.len() + writes.len()));
+        record.extend_from_slice(&pc.to_be_bytes());
+        record.extend_from_slice(&raw.to_be_bytes());
+
+        record.push(changes.len() as u8);
+        for &(reg, val) in changes {
+            record.push(reg);
+            record.extend_from_slice(&val.to_be_bytes());
+        }
+
+        // a single instruction writes at most 16 bytes
+        record.push(writes.len() as u8);
+        for w in writes {
+            record.extend_from_slice(&w.addr.to_be_bytes());
+            record.push(w.value);
+        }
+
+        self.out.write_all(&record)
+    }
+}
+
+// Registers (other than the program counter) changed by an instruction.
+fn register_changes(before: &Registers, after: &Registers) -> Vec<(u8, u16)> {
+    let mut changes: Vec<(u8, u16)> = (0..16)
+        .filter(|&x| before.v[x] != after.v[x])
+        .map(|x| (x as u8, after.v[x] as u16))
+        .collect();
+
+    if before.i != after.i {
+        changes.push((REG_I, after.i));
+    }
+    if before.delay_timer != after.delay_timer {
+        changes.push((REG_DT, after.delay_timer as u16));
+    }
+    if before.sound_timer != after.sound_timer {
+        changes.push((REG_ST, after.sound_timer as u16));
+    }
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registers(pc: u16) -> Registers {
+        Registers {
+            pc,
+            v: [0; 16],
+            i: 0,
+            delay_timer: 0,
+            sound_timer: 0,
+        }
+    }
+
+    fn write(addr: u16, value: u8) -> MemoryWrite {
+        MemoryWrite {
+            addr,
+            value,
+            previous: 0,
+        }
+    }
+
+    #[test]
+    fn instructions_are_traced_as_text() {
+        let mut tracer =
+            Tracer::new(Vec::new(), TraceFormat::Text, TraceFilter::default()).unwrap();
+
+        let symbols = Symbols::parse("buffer 300").unwrap();
+        let before = registers(0x200);
+        let mut after = registers(0x202);
+        after.v[0xA] = 0x02;
+        tracer
+            .record(0x6A02, &before, &after, &[], &symbols)
+            .unwrap();
+        tracer
+            .record(
+                0xF155,
+                &after,
+                &registers(0x204),
+                &[write(0x300, 0x01), write(0x301, 0x00)],
+                &symbols,
+            )
+            .unwrap();
+        let mut last = registers(0x206);
+        last.i = 0x300;
+        tracer
+            .record(0xA300, &registers(0x204), &last, &[], &symbols)
+            .unwrap();
+
+        let out = String::from_utf8(tracer.into_inner()).unwrap();
+        assert_eq!(
+            out,
+            "200  6A02  LD VA, 02        VA=02\n\
+             202  F155  LD [I], V1       VA=00 [300]=01 [301]=00\n\
+             204  A300  LD I, buffer     I=300\n"
+        );
+    }
+
+    #[test]
+    fn instructions_are_traced_as_binary() {
+        let mut tracer =
+            Tracer::new(Vec::new(), TraceFormat::Binary, TraceFilter::default()).unwrap();
+
+        let mut after = registers(0x202);
+        after.i = 0x123;
+        tracer
+            .record(
+                0xA123,
+                &registers(0x200),
+                &after,
+                &[write(0x300, 0xFF)],
+                &Symbols::default(),
+            )
+            .unwrap();
+
+        assert_eq!(
+            tracer.into_inner(),
+            [
+                b'Y', b'T', b'R', b'C', 1, // header
+                0x02, 0x00, 0xA1, 0x23, // pc, opcode
+                1, REG_I, 0x01, 0x23, // register changes
+                1, 0x03, 0x00, 0xFF, // memory writes
+            ]
+        );
+    }
+
+    #[test]
+    fn filtered_instructions_are_not_traced() {
+        let filter = TraceFilter {
+            range: Some(parse_address_range("200-2FF").unwrap()),
+            opcode_classes: Some(parse_opcode_classes("1,d").unwrap()),
+        };
+        let mut tracer = Tracer::new(Vec::new(), TraceFormat::Text, filter).unwrap();
+
+        tracer
+            .record(
+                0x1200,
+                &registers(0x300),
+                &registers(0x200),
+                &[],
+                &Symbols::default(),
+            )
+            .unwrap();
+        tracer
+            .record(
+                0x6001,
+                &registers(0x200),
+                &registers(0x202),
+                &[],
+                &Symbols::default(),
+            )
+            .unwrap();
+        tracer
+            .record(
+                0xD015,
+                &registers(0x202),
+                &registers(0x204),
+                &[],
+                &Symbols::default(),
+            )
+            .unwrap();
+
+        let out = String::from_utf8(tracer.into_inner()).unwrap();
+        assert_eq!(out, "202  D015  DRW V0, V1, 5\n");
+    }
+
+    #[test]
+    fn invalid_filters_are_rejected() {
+        assert!(parse_address_range("2FF-200").is_err());
+        assert!(parse_address_range("200").is_err());
+        assert!(parse_opcode_classes("1,10").is_err());
+    }
+}
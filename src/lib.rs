@@ -0,0 +1,20 @@
+#![warn(clippy::all, rust_2018_idioms)]
+
+#[macro_use]
+extern crate clap;
+
+pub mod interpreter;
+
+pub const DEBUG_MSG: &str = "
+Available actions upon pressing:
+- P - prints current state of CHIP-8
+- End - pause/resume emulation
+- PgDown - executes next cycle (4 instructions; possible only if emulation is paused)
+- PgUp - steps back one instruction (possible only if emulation is paused)
+- Home - steps back to the previous breakpoint hit (possible only if emulation is paused)
+- B - sets / clears a breakpoint at the current instruction
+Commands (e.g. RAM search) can be entered into the terminal; type 'help' to list them.
+";
+
+pub type InterpErr = Box<dyn std::error::Error>;
+pub type InterpResult<T> = Result<T, InterpErr>;
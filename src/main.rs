@@ -3,32 +3,32 @@
 #[macro_use]
 extern crate clap;
 
-use crate::interpreter::config::Config;
-use crate::interpreter::Interpreter;
+use yarchi::interpreter::analysis::{lint, Cfg};
+use yarchi::interpreter::config::{
+    load_address, parse_address, parse_blob, parse_font_address, Config, MAX_INSTRUCTIONS_PER_FRAME,
+};
+use yarchi::interpreter::loader::read_rom;
+use yarchi::interpreter::trace::{parse_address_range, parse_opcode_classes};
+use yarchi::interpreter::Interpreter;
+use yarchi::{InterpResult, DEBUG_MSG};
 
-use clap::{App, Arg, crate_authors, crate_description};
+use clap::{crate_authors, crate_description, App, AppSettings, Arg, ArgMatches, SubCommand};
 
-mod interpreter;
-
-const DEBUG_MSG: &str = "
-Available actions upon pressing:
-- P - prints current state of CHIP-8
-- End - pause/resume emulation
-- PgDown - executes next cycle (4 instructions; possible only if emulation is paused)
+const SPEED_MSG: &str = "
+Emulation speed can be changed at runtime:
+- Tab (hold) - turbo, runs as fast as possible while held
+- ` (backquote) - toggles slow motion (quarter speed)
+- + / - - increases / decreases instructions per frame
 ";
 
-type InterpErr = Box<dyn std::error::Error>;
-type InterpResult<T> = Result<T, InterpErr>;
-
 fn main() -> InterpResult<()> {
     let long_debug_msg = format!(
-        "{}\n{}",
-        "Enables debug mode, which allows for pausing emulation and executing cycles step-by-step.",
+        "Enables debug mode, which allows for pausing emulation and executing cycles step-by-step.\n{}",
         DEBUG_MSG
     );
 
     let about_with_controls = format!(
-        "{}\n{}",
+        "{}\n{}{}",
         crate_description!(),
         "
 To control the interpreter, use the left side of your keyboard.
@@ -38,13 +38,18 @@ The controls are mapped to the following keys:
 |Q|W|E|R|
 |A|S|D|F|
 |Z|X|C|V|
+
+Press M to mute / unmute the audio, F1 to show / hide the frame and
+instruction rate and F2 to show / hide the registers on screen.
 ",
+        SPEED_MSG
     );
 
     let app = App::new(crate_name!())
         .version(crate_version!())
         .about(about_with_controls.as_str())
         .author(crate_authors!())
+        .setting(AppSettings::SubcommandsNegateReqs)
         .arg(
             Arg::with_name("screen-size")
                 .takes_value(true)
@@ -58,9 +63,17 @@ The controls are mapped to the following keys:
                 .takes_value(true)
                 .long("interpreter-frequency")
                 .short("f")
-                .help("Adjusts emulation speed to specified Hz value. Valid values: 200-1000 Hz. Default: 500 Hz.")
+                .help("Adjusts emulation speed to specified Hz value. Valid values: 200 Hz and more. Default: 500 Hz.")
                 .validator(is_valid_emu_frequency)
         )
+        .arg(
+            Arg::with_name("ipf")
+                .takes_value(true)
+                .long("instructions-per-frame")
+                .help("Adjusts emulation speed to specified number of instructions per frame (1/60 s), as typically needed by SCHIP / XO-CHIP programs.")
+                .validator(is_valid_ipf)
+                .conflicts_with("freq")
+        )
         .arg(
             Arg::with_name("debug")
                 .long("debug")
@@ -69,11 +82,74 @@ The controls are mapped to the following keys:
 For all available commands, print information with --help.")
                 .long_help(long_debug_msg.as_str())
         )
+        .arg(
+            Arg::with_name("debugger-window")
+                .long("debugger-window")
+                .help("Enables debug mode and opens a second window showing disassembly, registers, call stack and memory.")
+        )
+        .arg(
+            Arg::with_name("gdb")
+                .takes_value(true)
+                .long("gdb")
+                .value_name("PORT")
+                .help("Waits for a GDB connection on specified local TCP port and lets GDB control the emulation.")
+                .validator(is_valid_port)
+        )
+        .arg(
+            Arg::with_name("symbols")
+                .takes_value(true)
+                .long("symbols")
+                .value_name("FILE")
+                .help("Loads labels from specified symbol file. Default: ROM path with .sym extension, if it exists.")
+        )
+        .arg(
+            Arg::with_name("break")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .long("break")
+                .value_name("LOCATION")
+                .help("Enables debug mode and sets a breakpoint at specified label or address (hex). Can be used multiple times.")
+        )
+        .arg(
+            Arg::with_name("console")
+                .long("console")
+                .help("Reads console commands (RAM search, cheats) from the terminal without entering debug mode.")
+        )
+        .arg(
+            Arg::with_name("cheat-dir")
+                .takes_value(true)
+                .long("cheat-dir")
+                .value_name("DIR")
+                .help("Sets the directory with the cheat files. Default: 'cheats' directory next to the ROM.")
+        )
         .arg(
             Arg::with_name("c48")
                 .long("chip-48-mode")
                 .short("c")
-                .help("Executes certain instructions in a mode compatible with CHIP-48. Required for some programs.")
+                .help("Executes certain instructions (shifts, BNNN, logic ops, FX55 / FX65) in a mode compatible with CHIP-48. Required for some programs.")
+        )
+        .arg(
+            Arg::with_name("fx1e-overflow")
+                .long("fx1e-overflow")
+                .help("Sets VF when FX1E moves I beyond the addressable memory, like the Amiga interpreter. Required for a few programs.")
+        )
+        .arg(
+            Arg::with_name("vip-timing")
+                .long("vip-timing")
+                .help("Charges each instruction its real COSMAC VIP machine-cycle cost, instead of running at a fixed frequency.
+Timing-sensitive programs written for the original hardware then run at authentic speed.")
+                .conflicts_with_all(&["freq", "ipf"])
+        )
+        .arg(
+            Arg::with_name("show-fps")
+                .long("show-fps")
+                .help("Shows frames and instructions per second on screen. Can be toggled with F1.")
+        )
+        .arg(
+            Arg::with_name("show-registers")
+                .long("show-registers")
+                .help("Shows the register panel on screen. Can be toggled with F2.")
         )
         .arg(
             Arg::with_name("fg-color")
@@ -89,9 +165,149 @@ For all available commands, print information with --help.")
             .help("Changes background color to specified RGB value. Format: R,G,B")
             .validator(is_valid_rgb_color)
         )
-        .arg(Arg::with_name("INPUT").required(true).help("Path to ROM which should be run"));
+        .arg(
+            Arg::with_name("tone-freq")
+            .takes_value(true)
+            .long("tone-frequency")
+            .help("Sets the pitch of the beep tone to specified Hz value. Valid values: 20-20000 Hz. Default: 256 Hz.")
+            .validator(is_valid_tone_frequency)
+        )
+        .arg(
+            Arg::with_name("waveform")
+            .takes_value(true)
+            .long("waveform")
+            .help("Sets the waveform of the beep tone. Default: square.")
+            .possible_values(&["square", "sine", "triangle"])
+        )
+        .arg(
+            Arg::with_name("volume")
+            .takes_value(true)
+            .long("volume")
+            .help("Sets the volume of the beep tone in percent. Valid values: 0-100. Default: 10.")
+            .validator(is_valid_volume)
+        )
+        .arg(
+            Arg::with_name("wav-output")
+            .takes_value(true)
+            .long("wav-output")
+            .help("Records the produced audio to specified WAV file.")
+        )
+        .arg(
+            Arg::with_name("trace")
+            .takes_value(true)
+            .long("trace")
+            .value_name("FILE")
+            .help("Writes every executed instruction with register changes and memory writes to specified file.")
+        )
+        .arg(
+            Arg::with_name("trace-format")
+            .takes_value(true)
+            .long("trace-format")
+            .requires("trace")
+            .help("Sets the format of the trace file. Default: text.")
+            .possible_values(&["text", "binary"])
+        )
+        .arg(
+            Arg::with_name("trace-range")
+            .takes_value(true)
+            .long("trace-range")
+            .value_name("START-END")
+            .requires("trace")
+            .help("Traces only instructions in specified address range (hex, inclusive), e.g. 200-2FF.")
+            .validator(is_valid_trace_range)
+        )
+        .arg(
+            Arg::with_name("trace-opcodes")
+            .takes_value(true)
+            .long("trace-opcodes")
+            .value_name("CLASSES")
+            .requires("trace")
+            .help("Traces only specified opcode classes, given by the first hex digit of the opcode, e.g. 1,2,D.")
+            .validator(is_valid_opcode_classes)
+        )
+        .arg(
+            Arg::with_name("profile")
+            .takes_value(true)
+            .long("profile")
+            .value_name("FILE")
+            .help("Counts executed instructions and on exit writes a report of hot spots, never executed code, instructions per frame and the annotated disassembly to specified file.")
+        )
+        .arg(
+            Arg::with_name("check-memory")
+            .long("check-memory")
+            .help("Warns when the program executes or draws sprites from memory it never wrote, modifies its own code, or points I into the interpreter area below the load address.")
+        )
+        .arg(
+            Arg::with_name("font")
+            .takes_value(true)
+            .long("font")
+            .value_name("FONT")
+            .help("Sets the font to a built-in one (vip, chip48, schip, octo, dream6800) or loads it from specified file: 80 bytes of small characters, optionally followed by 100 or 160 bytes of large digits. Default: schip.")
+        )
+        .arg(
+            Arg::with_name("font-address")
+            .takes_value(true)
+            .long("font-address")
+            .value_name("ADDR")
+            .help("Loads the font at specified address (hex) below 0x200. Default: 050.")
+            .validator(is_valid_font_address)
+        )
+        .arg(
+            Arg::with_name("zip-entry")
+            .takes_value(true)
+            .long("zip-entry")
+            .value_name("NAME")
+            .help("Runs specified entry of a zip archive, needed if there's more than one ROM in it.")
+        )
+        .args(&load_address_args())
+        .arg(
+            Arg::with_name("entry")
+            .takes_value(true)
+            .long("entry")
+            .value_name("ADDR")
+            .help("Starts executing the program from specified address (hex) instead of where it's loaded.")
+            .validator(is_valid_address)
+        )
+        .arg(
+            Arg::with_name("blob")
+            .takes_value(true)
+            .long("blob")
+            .value_name("ADDR:FILE")
+            .multiple(true)
+            .number_of_values(1)
+            .help("Loads specified file at the address (hex) after loading the program, e.g. test fixtures; can be given multiple times.")
+            .validator(is_valid_blob)
+        )
+        .arg(Arg::with_name("INPUT").required(true).help("Path to ROM which should be run: a binary, a hex-text listing (.hex, .txt), an Octo cartridge (.gif) or a zip archive; - reads it from stdin"))
+        .subcommand(
+            SubCommand::with_name("lint")
+                .about("Checks a ROM without running it for unknown instructions, jumps out of the ROM, unbounded recursion and the quirks it relies on")
+                .arg(
+                    Arg::with_name("c48")
+                        .long("chip-48-mode")
+                        .short("c")
+                        .help("Checks the ROM for CHIP-48 mode")
+                )
+                .args(&load_address_args())
+                .arg(Arg::with_name("INPUT").required(true).help("Path to ROM which should be checked"))
+        )
+        .subcommand(
+            SubCommand::with_name("cfg")
+                .about("Prints the control-flow graph of a ROM's basic blocks in the Graphviz DOT language, e.g. for `dot -Tsvg`")
+                .args(&load_address_args())
+                .arg(Arg::with_name("INPUT").required(true).help("Path to ROM whose graph should be printed"))
+        );
 
     let matches = app.get_matches();
+    match matches.subcommand() {
+        ("lint", Some(lint_matches)) => return lint_rom(lint_matches),
+        ("cfg", Some(cfg_matches)) => {
+            let rom = read_rom_arg(cfg_matches)?;
+            print!("{}", Cfg::new(&rom, load_address(cfg_matches)).to_dot());
+            return Ok(());
+        }
+        _ => {}
+    }
 
     let sdl_ctx = sdl2::init()?;
     let mut interpreter = Interpreter::new(
@@ -104,13 +320,45 @@ For all available commands, print information with --help.")
     Ok(())
 }
 
+// Prints the lint report of the ROM; exits with status 1 if it has errors.
+fn lint_rom(matches: &ArgMatches<'_>) -> InterpResult<()> {
+    let rom = read_rom_arg(matches)?;
+    let report = lint(&rom, load_address(matches), matches.is_present("c48"));
+    print!("{}", report);
+    if report.has_errors() {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+// Options of the address the program is loaded at, shared with the subcommands.
+fn load_address_args() -> [Arg<'static, 'static>; 2] {
+    [
+        Arg::with_name("load-addr")
+            .takes_value(true)
+            .long("load-addr")
+            .value_name("ADDR")
+            .help("Loads the program at specified address (hex) and starts executing it from there. Default: 200.")
+            .validator(is_valid_address),
+        Arg::with_name("eti-660")
+            .long("eti-660")
+            .conflicts_with("load-addr")
+            .help("Loads and starts ETI-660 programs at 600, same as --load-addr 600."),
+    ]
+}
+
+fn read_rom_arg(matches: &ArgMatches<'_>) -> InterpResult<Vec<u8>> {
+    let path = matches.value_of("INPUT").unwrap_or_default();
+    read_rom(path, None)
+}
+
 fn is_valid_emu_frequency(freq: String) -> Result<(), String> {
-    match freq.parse::<u16>() {
+    match freq.parse::<u32>() {
         Ok(f) => {
-            if f < 200 || f > 1000 {
+            if f < 200 {
                 return Err(
-                    "invalid interpreter frequency specified: must be in range 200 - 1000 Hz"
-                        .to_string(),
+                    "invalid interpreter frequency specified: must be at least 200 Hz".to_string(),
                 );
             }
 
@@ -120,18 +368,88 @@ fn is_valid_emu_frequency(freq: String) -> Result<(), String> {
     }
 }
 
+fn is_valid_ipf(ipf: String) -> Result<(), String> {
+    match ipf.parse::<u32>() {
+        Ok(n) => {
+            if n == 0 || n > MAX_INSTRUCTIONS_PER_FRAME {
+                return Err(format!(
+                    "invalid instructions per frame specified: must be in range 1 - {}",
+                    MAX_INSTRUCTIONS_PER_FRAME
+                ));
+            }
+
+            Ok(())
+        }
+        Err(e) => Err(format!("parsing instructions per frame failed: {}", e)),
+    }
+}
+
 fn is_valid_rgb_color(rgb: String) -> Result<(), String> {
-    let vals: Vec<&str> = rgb.split(",").collect();
+    let vals: Vec<&str> = rgb.split(',').collect();
     if vals.len() != 3 {
         return Err("invalid RGB value provided. Valid format: R,G,B".to_string());
     }
 
     for v in vals {
-        match v.parse::<u8>() {
-            Err(e) => return Err(format!("parsing RGB values failed: {}", e)),
-            _ => return Ok(()),
+        if let Err(e) = v.parse::<u8>() {
+            return Err(format!("parsing RGB values failed: {}", e));
         }
     }
 
     Ok(())
 }
+
+fn is_valid_port(port: String) -> Result<(), String> {
+    match port.parse::<u16>() {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("parsing port failed: {}", e)),
+    }
+}
+
+fn is_valid_tone_frequency(freq: String) -> Result<(), String> {
+    match freq.parse::<f32>() {
+        Ok(f) => {
+            if !(20.0..=20_000.0).contains(&f) {
+                return Err(
+                    "invalid tone frequency specified: must be in range 20 - 20000 Hz".to_string(),
+                );
+            }
+
+            Ok(())
+        }
+        Err(e) => Err(format!("parsing tone frequency failed: {}", e)),
+    }
+}
+
+fn is_valid_volume(vol: String) -> Result<(), String> {
+    match vol.parse::<u8>() {
+        Ok(v) => {
+            if v > 100 {
+                return Err("invalid volume specified: must be in range 0 - 100".to_string());
+            }
+
+            Ok(())
+        }
+        Err(e) => Err(format!("parsing volume failed: {}", e)),
+    }
+}
+
+fn is_valid_trace_range(range: String) -> Result<(), String> {
+    parse_address_range(&range).map(|_| ())
+}
+
+fn is_valid_opcode_classes(classes: String) -> Result<(), String> {
+    parse_opcode_classes(&classes).map(|_| ())
+}
+
+fn is_valid_font_address(addr: String) -> Result<(), String> {
+    parse_font_address(&addr).map(|_| ())
+}
+
+fn is_valid_address(addr: String) -> Result<(), String> {
+    parse_address(&addr).map(|_| ())
+}
+
+fn is_valid_blob(blob: String) -> Result<(), String> {
+    parse_blob(&blob).map(|_| ())
+}
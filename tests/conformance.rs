@@ -0,0 +1,168 @@
+// Runs the test ROMs listed in tests/conformance/cases.txt headlessly
+// and compares their final display to the golden images. The cases of
+// Timendus' test suite, whose ROMs aren't distributed with yarchi, are
+// listed in timendus.txt and run with `cargo test -- --ignored`.
+//
+// Set YARCHI_BLESS=1 to (re)write the golden images of cases.txt from the
+// actual results instead, e.g. after adding a case; check them before
+// committing. Timendus' cases are never blessed: their golden images are
+// checked in after comparing them to the results documented by the suite.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use yarchi::interpreter::chip8::Chip8;
+use yarchi::interpreter::headless::{display_to_text, Headless, KeyPress, Timing};
+
+// Speed of the chip8 and chip48 presets.
+const INSTRUCTIONS_PER_FRAME: u32 = 15;
+
+struct Case {
+    rom: String,
+    preset: String,
+    frames: u32,
+    pokes: Vec<(u16, u8)>,
+    keys: Vec<KeyPress>,
+    golden: String,
+}
+
+fn base_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/conformance")
+}
+
+fn parse_cases(content: &str) -> Vec<Case> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(|line| {
+            let cols: Vec<&str> = line.split_whitespace().collect();
+            assert_eq!(cols.len(), 6, "invalid case '{}'", line);
+
+            Case {
+                rom: cols[0].to_string(),
+                preset: cols[1].to_string(),
+                frames: cols[2].parse().expect("invalid frame count"),
+                pokes: parse_pokes(cols[3]),
+                keys: parse_keys(cols[4]),
+                golden: cols[5].to_string(),
+            }
+        })
+        .collect()
+}
+
+fn parse_pokes(s: &str) -> Vec<(u16, u8)> {
+    if s == "-" {
+        return Vec::new();
+    }
+
+    s.split(',')
+        .map(|p| {
+            let (addr, val) = p.split_once('=').expect("expected ADDR=VALUE");
+            (
+                u16::from_str_radix(addr, 16).expect("invalid address"),
+                u8::from_str_radix(val, 16).expect("invalid value"),
+            )
+        })
+        .collect()
+}
+
+fn parse_keys(s: &str) -> Vec<KeyPress> {
+    if s == "-" {
+        return Vec::new();
+    }
+
+    s.split(',')
+        .map(|k| {
+            let (key, frames) = k.split_once('@').expect("expected KEY@FROM-TO");
+            let (from, to) = frames.split_once('-').expect("expected KEY@FROM-TO");
+            KeyPress {
+                key: u8::from_str_radix(key, 16).expect("invalid key"),
+                frames: from.parse().expect("invalid frame")..to.parse().expect("invalid frame"),
+            }
+        })
+        .collect()
+}
+
+fn run_case(case: &Case, rom: &[u8], jit: bool) -> String {
+    let (c48_mode, timing) = match case.preset.as_str() {
+        "chip8" => (false, Timing::InstructionsPerFrame(INSTRUCTIONS_PER_FRAME)),
+        "chip48" => (true, Timing::InstructionsPerFrame(INSTRUCTIONS_PER_FRAME)),
+        "vip" => (false, Timing::Vip),
+        other => panic!("unknown preset '{}'", other),
+    };
+
+    let mut machine = Chip8::new(c48_mode).load_program(rom);
+    for &(addr, val) in &case.pokes {
+        machine.poke(addr, val);
+    }
+
+    let mut headless = Headless::new(machine, timing).with_keys(case.keys.clone());
+    if jit {
+        headless = headless.with_jit();
+    }
+    headless.run_frames(case.frames);
+    display_to_text(&headless.machine.screen)
+}
+
+// Runs the cases listed in given file; returns the failures. A missing
+// ROM or golden image is a failure too, so a case can't pass without being
+// checked.
+fn run_cases(list: &str, blessable: bool) -> Vec<String> {
+    let dir = base_dir();
+    let cases = parse_cases(&fs::read_to_string(dir.join(list)).unwrap());
+    let bless = blessable && std::env::var_os("YARCHI_BLESS").is_some();
+    let mut failures = Vec::new();
+
+    for case in &cases {
+        let name = format!("{} ({})", case.rom, case.preset);
+        let rom = match fs::read(dir.join("roms").join(&case.rom)) {
+            Ok(rom) => rom,
+            Err(e) => {
+                failures.push(format!("{}: can't read the ROM: {}", name, e));
+                continue;
+            }
+        };
+
+        let actual = run_case(case, &rom, false);
+        let golden_path = dir.join("golden").join(&case.golden);
+        if bless {
+            fs::create_dir_all(golden_path.parent().unwrap()).unwrap();
+            fs::write(&golden_path, &actual).unwrap();
+            continue;
+        }
+
+        // the recompiler must give exactly the same results
+        if run_case(case, &rom, true) != actual {
+            failures.push(format!("{}: display differs when run with the JIT", name));
+        }
+
+        match fs::read_to_string(&golden_path) {
+            Ok(expected) if expected == actual => println!("{}: ok", name),
+            Ok(expected) => failures.push(format!(
+                "{}: display differs from {}\nexpected:\n{}actual:\n{}",
+                name, case.golden, expected, actual
+            )),
+            Err(_) => failures.push(format!(
+                "{}: golden image {} missing; actual:\n{}",
+                name, case.golden, actual
+            )),
+        }
+    }
+
+    failures
+}
+
+#[test]
+fn test_roms_match_golden_images() {
+    let failures = run_cases("cases.txt", true);
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+// Until golden images of the suite are checked in, each case fails with its display.
+#[test]
+#[ignore = "needs Timendus' test suite, fetched by tests/conformance/fetch-timendus.sh"]
+fn timendus_test_suite_matches_golden_images() {
+    let failures = run_cases("timendus.txt", false);
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
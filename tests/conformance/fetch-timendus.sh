@@ -0,0 +1,16 @@
+#!/bin/sh
+# Downloads the ROMs of Timendus' CHIP-8 test suite and runs the conformance
+# tests on them, failing if any display differs from its golden image; none are
+# checked in yet, so each case fails and prints its display for review.
+set -e
+
+dir="$(dirname "$0")/roms/timendus"
+url="https://github.com/Timendus/chip8-test-suite/raw/main/bin"
+
+mkdir -p "$dir"
+for rom in 1-chip8-logo 2-ibm-logo 3-corax+ 4-flags 5-quirks 6-keypad; do
+    curl -sSfL -o "$dir/$rom.ch8" "$url/$rom.ch8"
+done
+
+cd "$(dirname "$0")/../.."
+cargo test --test conformance -- --ignored
@@ -0,0 +1,90 @@
+// Runs random programs from random initial states on the interpreter and on
+// the reference implementation in tests/reference, comparing their states
+// after every instruction, and likewise on the JIT and the interpreter.
+// The fuzz targets in fuzz/ do the same with coverage-guided inputs;
+// this keeps a fixed set of cases in `cargo test`.
+
+mod reference;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use yarchi::interpreter::chip8::{Chip8, Jit};
+
+const PROGRAMS: u64 = 500;
+const STEPS: usize = 300;
+
+fn random_machine(rng: &mut StdRng, c48_mode: bool, fx1e_overflow: bool) -> Chip8 {
+    let len = rng.gen_range(2..512);
+    let program: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+    let mut machine = Chip8::new(c48_mode)
+        .with_fx1e_overflow(fx1e_overflow)
+        .load_program(&program);
+
+    let mut regs = machine.registers();
+    rng.fill(&mut regs.v);
+    regs.i = rng.gen();
+    regs.delay_timer = rng.gen();
+    regs.sound_timer = rng.gen();
+    machine.set_registers(&regs);
+
+    machine.input = (0..16u8).filter(|_| rng.gen_bool(0.2)).collect();
+    machine
+}
+
+#[test]
+fn interpreter_matches_reference_implementation() {
+    for seed in 0..PROGRAMS {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let (c48_mode, fx1e_overflow) = rng.gen();
+        let mut machine = random_machine(&mut rng, c48_mode, fx1e_overflow);
+
+        if let Err(e) = reference::run_differential(&mut machine, c48_mode, fx1e_overflow, STEPS) {
+            panic!(
+                "seed {} (c48_mode: {}, fx1e_overflow: {}): {}",
+                seed, c48_mode, fx1e_overflow, e
+            );
+        }
+    }
+}
+
+fn same_state(a: &Chip8, b: &Chip8) -> bool {
+    a.registers() == b.registers()
+        && a.memory() == b.memory()
+        && a.stack() == b.stack()
+        && a.screen.display[..] == b.screen.display[..]
+}
+
+#[test]
+fn jit_matches_interpreter() {
+    for seed in 0..PROGRAMS {
+        let (c48_mode, fx1e_overflow) = (seed % 2 == 0, seed % 4 < 2);
+        let mut jitted = random_machine(&mut StdRng::seed_from_u64(seed), c48_mode, fx1e_overflow)
+            .with_rng_seed(seed);
+        let mut interpreted =
+            random_machine(&mut StdRng::seed_from_u64(seed), c48_mode, fx1e_overflow)
+                .with_rng_seed(seed);
+        let mut jit = Jit::default();
+        let mut limits = StdRng::seed_from_u64(seed);
+
+        let mut left = STEPS;
+        while left > 0 {
+            // blocks are also cut short by the instruction count
+            let executed = limits.gen_range(1..=left);
+            jit.run(&mut jitted, executed);
+            for _ in 0..executed {
+                interpreted.run_instruction(false);
+            }
+            left -= executed;
+
+            assert!(
+                same_state(&jitted, &interpreted),
+                "seed {}: states differ after {} instructions; JIT: {:?}, interpreter: {:?}",
+                seed,
+                STEPS - left,
+                jitted,
+                interpreted
+            );
+        }
+    }
+}
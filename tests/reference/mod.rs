@@ -0,0 +1,258 @@
+// A deliberately simple implementation of the CHIP-8 instruction set,
+// written straight from the spec independently of the interpreter, with
+// the same quirk choices (in CHIP-8 mode 8XY1 / 8XY2 / 8XY3 reset VF and
+// FX55 / FX65 increment I, CHIP-48 mode leaves both; shifts and BNNN depend
+// on the CHIP-48 mode; FX1E sets VF only with the overflow quirk; sprites
+// are clipped at the screen edges; memory addresses wrap around). Used as the oracle for differential
+// testing by tests/differential.rs and the fuzz targets in fuzz/.
+
+use yarchi::interpreter::chip8::Chip8;
+
+const MEMORY_SIZE: usize = 4096;
+const WIDTH: usize = 64;
+const HEIGHT: usize = 32;
+const FONT_ADDR: u16 = 0x050;
+const BIG_FONT_ADDR: u16 = FONT_ADDR + 16 * 5;
+
+pub struct Reference {
+    pub memory: [u8; MEMORY_SIZE],
+    pub v: [u8; 16],
+    pub i: u16,
+    pub pc: u16,
+    pub stack: Vec<u16>,
+    pub display: [bool; WIDTH * HEIGHT],
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub keys: Vec<u8>,
+    pub c48_mode: bool,
+    pub fx1e_overflow: bool,
+}
+
+impl Reference {
+    // Copies the state of the machine, which must not have executed anything yet.
+    pub fn from_machine(machine: &Chip8, c48_mode: bool, fx1e_overflow: bool) -> Reference {
+        let regs = machine.registers();
+        let mut memory = [0; MEMORY_SIZE];
+        memory.copy_from_slice(machine.memory());
+
+        Reference {
+            memory,
+            v: regs.v,
+            i: regs.i,
+            pc: regs.pc,
+            stack: machine.stack().to_vec(),
+            display: machine.screen.display,
+            delay_timer: regs.delay_timer,
+            sound_timer: regs.sound_timer,
+            keys: machine.input.clone(),
+            c48_mode,
+            fx1e_overflow,
+        }
+    }
+
+    fn read(&self, addr: u16) -> u8 {
+        self.memory[addr as usize % MEMORY_SIZE]
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        self.memory[addr as usize % MEMORY_SIZE] = val;
+    }
+
+    // Opcode of the next instruction.
+    pub fn opcode(&self) -> u16 {
+        (self.read(self.pc) as u16) << 8 | self.read(self.pc.wrapping_add(1)) as u16
+    }
+
+    // Executes the next instruction; CXKK uses `random` as its random byte.
+    pub fn step(&mut self, random: u8) {
+        let op = self.opcode();
+        let x = (op >> 8 & 0xF) as usize;
+        let y = (op >> 4 & 0xF) as usize;
+        let n = op & 0xF;
+        let kk = op as u8;
+        let nnn = op & 0xFFF;
+        let (vx, vy) = (self.v[x], self.v[y]);
+
+        self.pc = self.pc.wrapping_add(2);
+        let skip = |pc: u16, condition: bool| if condition { pc.wrapping_add(2) } else { pc };
+
+        match op >> 12 {
+            0x0 => match op {
+                0x00E0 => self.display = [false; WIDTH * HEIGHT],
+                0x00EE => self.pc = self.stack.pop().unwrap_or(0),
+                _ => {}
+            },
+            0x1 => self.pc = nnn,
+            0x2 => {
+                self.stack.push(self.pc);
+                self.pc = nnn;
+            }
+            0x3 => self.pc = skip(self.pc, vx == kk),
+            0x4 => self.pc = skip(self.pc, vx != kk),
+            0x5 => self.pc = skip(self.pc, vx == vy),
+            0x6 => self.v[x] = kk,
+            0x7 => self.v[x] = vx.wrapping_add(kk),
+            0x8 => match n {
+                0x0 => self.v[x] = vy,
+                0x1..=0x3 => {
+                    self.v[x] = match n {
+                        0x1 => vx | vy,
+                        0x2 => vx & vy,
+                        _ => vx ^ vy,
+                    };
+                    if !self.c48_mode {
+                        self.v[0xF] = 0;
+                    }
+                }
+                0x4 => {
+                    let sum = vx as u16 + vy as u16;
+                    self.v[x] = sum as u8;
+                    self.v[0xF] = (sum > 0xFF) as u8;
+                }
+                0x5 => {
+                    self.v[x] = vx.wrapping_sub(vy);
+                    self.v[0xF] = (vx >= vy) as u8;
+                }
+                0x6 => {
+                    let src = if self.c48_mode { vx } else { vy };
+                    self.v[x] = src >> 1;
+                    self.v[0xF] = src & 1;
+                }
+                0x7 => {
+                    self.v[x] = vy.wrapping_sub(vx);
+                    self.v[0xF] = (vy >= vx) as u8;
+                }
+                0xE => {
+                    let src = if self.c48_mode { vx } else { vy };
+                    self.v[x] = src << 1;
+                    self.v[0xF] = src >> 7;
+                }
+                _ => {}
+            },
+            0x9 => self.pc = skip(self.pc, vx != vy),
+            0xA => self.i = nnn,
+            0xB => {
+                let offset = if self.c48_mode { vx } else { self.v[0] };
+                self.pc = nnn + offset as u16;
+            }
+            0xC => self.v[x] = random & kk,
+            0xD => self.draw(vx as usize % WIDTH, vy as usize % HEIGHT, n),
+            0xE => match kk {
+                0x9E => self.pc = skip(self.pc, self.keys.contains(&vx)),
+                0xA1 => self.pc = skip(self.pc, !self.keys.contains(&vx)),
+                _ => {}
+            },
+            _ => match kk {
+                0x07 => self.v[x] = self.delay_timer,
+                0x0A => match self.keys.first() {
+                    Some(&key) => self.v[x] = key,
+                    None => self.pc = self.pc.wrapping_sub(2),
+                },
+                0x15 => self.delay_timer = vx,
+                0x18 => self.sound_timer = vx,
+                0x1E => {
+                    self.i = self.i.wrapping_add(vx as u16);
+                    if self.fx1e_overflow {
+                        self.v[0xF] = (self.i as usize >= MEMORY_SIZE) as u8;
+                    }
+                }
+                0x29 => self.i = FONT_ADDR + 5 * (vx & 0xF) as u16,
+                0x30 => self.i = BIG_FONT_ADDR + 10 * (vx & 0xF) as u16,
+                0x33 => {
+                    self.write(self.i, vx / 100);
+                    self.write(self.i.wrapping_add(1), vx / 10 % 10);
+                    self.write(self.i.wrapping_add(2), vx % 10);
+                }
+                0x55 => {
+                    for r in 0..=x {
+                        self.write(self.i.wrapping_add(r as u16), self.v[r]);
+                    }
+                    if !self.c48_mode {
+                        self.i = self.i.wrapping_add(x as u16 + 1);
+                    }
+                }
+                0x65 => {
+                    for r in 0..=x {
+                        self.v[r] = self.read(self.i.wrapping_add(r as u16));
+                    }
+                    if !self.c48_mode {
+                        self.i = self.i.wrapping_add(x as u16 + 1);
+                    }
+                }
+                _ => {}
+            },
+        }
+    }
+
+    fn draw(&mut self, x: usize, y: usize, rows: u16) {
+        self.v[0xF] = 0;
+        for row in 0..rows {
+            let sprite = self.read(self.i.wrapping_add(row));
+            for bit in 0..8 {
+                let (px, py) = (x + bit, y + row as usize);
+                if px >= WIDTH || py >= HEIGHT || sprite & (0x80 >> bit) == 0 {
+                    continue;
+                }
+
+                let pixel = &mut self.display[py * WIDTH + px];
+                if *pixel {
+                    self.v[0xF] = 1;
+                }
+                *pixel = !*pixel;
+            }
+        }
+    }
+
+    // Describes the first difference from the state of the machine, if any.
+    pub fn compare(&self, machine: &Chip8) -> Result<(), String> {
+        let regs = machine.registers();
+        let differences = [
+            ("PC", regs.pc != self.pc),
+            ("V registers", regs.v != self.v),
+            ("I", regs.i != self.i),
+            ("stack", machine.stack() != &self.stack[..]),
+            ("memory", machine.memory() != &self.memory[..]),
+            ("display", machine.screen.display[..] != self.display[..]),
+            ("delay timer", regs.delay_timer != self.delay_timer),
+            ("sound timer", regs.sound_timer != self.sound_timer),
+        ];
+
+        match differences.iter().find(|(_, differs)| *differs) {
+            Some((what, _)) => Err(format!(
+                "{} differs; machine: {:?}, reference: pc={:03X} v={:02X?} i={:03X}",
+                what, machine, self.pc, self.v, self.i
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+// Runs the machine and the reference in lockstep for up to `steps` instructions,
+// comparing their states after each one.
+pub fn run_differential(
+    machine: &mut Chip8,
+    c48_mode: bool,
+    fx1e_overflow: bool,
+    steps: usize,
+) -> Result<(), String> {
+    let mut reference = Reference::from_machine(machine, c48_mode, fx1e_overflow);
+
+    for step in 0..steps {
+        let opcode = reference.opcode();
+        machine.run_instruction(false);
+
+        // the machine's random byte isn't known, only that it's masked by KK
+        let random = if opcode >> 12 == 0xC {
+            machine.registers().v[(opcode >> 8 & 0xF) as usize]
+        } else {
+            0
+        };
+        reference.step(random);
+
+        reference
+            .compare(machine)
+            .map_err(|e| format!("step {}, opcode {:04X}: {}", step, opcode, e))?;
+    }
+
+    Ok(())
+}